@@ -6,8 +6,9 @@ use std::io;
 use std::io::BufRead;
 
 
-use histogram::print_histogram;
+use histogram::{print_histogram, HistogramOptions};
 
+// Superseded by `seqtools alignment-histogram`, which also reads BAM and supports filters.
 fn main(){
     let args: Vec<String> = env::args().collect();
     let filename = args[1].clone();
@@ -23,10 +24,12 @@ fn main(){
     let mut starts = Vec::<i64>::new();
     for line in lines{
         let line = line.unwrap();
+        if line.starts_with('@') { continue } // Header line
         let tokens: Vec<&str> = line.split('\t').collect();
-        let length: u64 = tokens[2].parse().unwrap();
-        starts.push(length as i64);
+        let pos: u64 = tokens[3].parse().unwrap(); // The POS column
+        starts.push(pos as i64);
     }
 
-    print_histogram(starts.into_iter(), min, max, nbins);
+    let options = HistogramOptions{min: Some(min), max: Some(max), n_bins: nbins as usize, ..Default::default()};
+    print_histogram(starts.into_iter(), &options);
 }
\ No newline at end of file
@@ -0,0 +1,256 @@
+// Random access into bgzip-compressed (BGZF) files.
+//
+// A BGZF file is a series of gzip members ("blocks") of at most 64 KiB of uncompressed data each.
+// The compressed size of each block is stored in an extra field of the gzip header, which lets us
+// hop from block to block without decompressing. A .gzi index (as written by `bgzip -i`) lists the
+// compressed and uncompressed start offsets of the blocks, so that we can seek to any uncompressed
+// position by decompressing just one block.
+
+use std::io::{Read, Seek, SeekFrom};
+
+// Returns true if the file starts with a BGZF block header.
+pub fn is_bgzf<P: AsRef<std::path::Path>>(path: P) -> Result<bool, Box<dyn std::error::Error>> {
+    let mut header = [0u8; 16];
+    let mut file = std::fs::File::open(path)?;
+    let mut n_read = 0;
+    while n_read < header.len() {
+        let n = file.read(&mut header[n_read..])?;
+        if n == 0 { return Ok(false) }
+        n_read += n;
+    }
+
+    // Gzip magic, deflate, FEXTRA flag set, and the first extra subfield is 'BC'
+    Ok(header[0] == 0x1f && header[1] == 0x8b && header[2] == 8 && header[3] & 4 != 0 && header[12] == b'B' && header[13] == b'C')
+}
+
+struct BlockHeader {
+    bytes: Vec<u8>,
+    block_size: usize, // Total compressed size of the block, including the header
+}
+
+// Reads the header of the block starting at the current position of the input.
+// Returns None at the end of the file.
+fn read_block_header(input: &mut impl Read) -> Result<Option<BlockHeader>, Box<dyn std::error::Error>> {
+    let mut header = vec![0u8; 12];
+    let mut n_read = 0;
+    while n_read < header.len() {
+        let n = input.read(&mut header[n_read..])?;
+        if n == 0 {
+            if n_read == 0 { return Ok(None) } // Clean end of file
+            return Err("Truncated BGZF block header".into());
+        }
+        n_read += n;
+    }
+    if header[0] != 0x1f || header[1] != 0x8b || header[3] & 4 == 0 {
+        return Err("Not a BGZF block. Random access to gzipped files requires compression with bgzip".into());
+    }
+
+    let xlen = u16::from_le_bytes([header[10], header[11]]) as usize;
+    let mut extra = vec![0u8; xlen];
+    input.read_exact(&mut extra)?;
+    header.extend_from_slice(&extra);
+
+    // Find the BC subfield that stores the block size minus one
+    let mut i = 0;
+    while i + 4 <= xlen {
+        let subfield_len = u16::from_le_bytes([extra[i+2], extra[i+3]]) as usize;
+        if extra[i] == b'B' && extra[i+1] == b'C' && subfield_len == 2 {
+            let bsize = u16::from_le_bytes([extra[i+4], extra[i+5]]) as usize;
+            return Ok(Some(BlockHeader{bytes: header, block_size: bsize + 1}));
+        }
+        i += 4 + subfield_len;
+    }
+    Err("BGZF block header is missing the BC field".into())
+}
+
+// The .gzi index: pairs (compressed offset, uncompressed offset) for the starts of all blocks.
+// Unlike in the file format, the first block (0,0) is included.
+pub struct GziIndex {
+    pub blocks: Vec<(u64, u64)>,
+}
+
+impl GziIndex {
+
+    // Scans through the block headers of a BGZF stream. Only the 4-byte uncompressed size
+    // at the end of each block is read, so nothing is decompressed.
+    pub fn build(mut input: impl Read) -> Result<GziIndex, Box<dyn std::error::Error>> {
+        let mut blocks = vec![];
+        let mut compressed_offset = 0_u64;
+        let mut uncompressed_offset = 0_u64;
+        let mut body = Vec::<u8>::new();
+        while let Some(BlockHeader{bytes: header, block_size}) = read_block_header(&mut input)? {
+            body.resize(block_size - header.len(), 0);
+            input.read_exact(&mut body)?;
+            let isize_bytes = &body[body.len()-4..];
+            let uncompressed_size = u32::from_le_bytes(isize_bytes.try_into().unwrap()) as u64;
+
+            blocks.push((compressed_offset, uncompressed_offset));
+            compressed_offset += block_size as u64;
+            uncompressed_offset += uncompressed_size;
+        }
+        Ok(GziIndex{blocks})
+    }
+
+    pub fn from_file<P: AsRef<std::path::Path>>(path: P) -> Result<GziIndex, Box<dyn std::error::Error>> {
+        let bytes = std::fs::read(path)?;
+        if bytes.len() < 8 {
+            return Err("Truncated .gzi file".into());
+        }
+        let n_entries = u64::from_le_bytes(bytes[0..8].try_into().unwrap()) as usize;
+        if bytes.len() != 8 + 16 * n_entries {
+            return Err("Corrupt .gzi file: size does not match the number of entries".into());
+        }
+
+        let mut blocks = vec![(0, 0)];
+        for chunk in bytes[8..].chunks_exact(16) {
+            let compressed = u64::from_le_bytes(chunk[0..8].try_into().unwrap());
+            let uncompressed = u64::from_le_bytes(chunk[8..16].try_into().unwrap());
+            blocks.push((compressed, uncompressed));
+        }
+        Ok(GziIndex{blocks})
+    }
+
+    pub fn write_to_file<P: AsRef<std::path::Path>>(&self, path: P) -> Result<(), Box<dyn std::error::Error>> {
+        let mut bytes = Vec::<u8>::new();
+        let entries = &self.blocks[1.min(self.blocks.len())..]; // The first block is implicit in the file format
+        bytes.extend_from_slice(&(entries.len() as u64).to_le_bytes());
+        for (compressed, uncompressed) in entries {
+            bytes.extend_from_slice(&compressed.to_le_bytes());
+            bytes.extend_from_slice(&uncompressed.to_le_bytes());
+        }
+        std::fs::write(path, bytes)?;
+        Ok(())
+    }
+}
+
+// A reader that presents the uncompressed contents of a BGZF file, with seeking
+// to uncompressed offsets using a .gzi index.
+pub struct BgzfReader<R: Read + Seek> {
+    inner: R,
+    index: GziIndex,
+    block: Vec<u8>, // Uncompressed data of the current block
+    block_start: u64, // Uncompressed offset of the start of the current block
+    pos_in_block: usize,
+    next_block_offset: u64, // Compressed offset of the next block
+}
+
+impl<R: Read + Seek> BgzfReader<R> {
+    pub fn new(inner: R, index: GziIndex) -> Self {
+        BgzfReader{inner, index, block: vec![], block_start: 0, pos_in_block: 0, next_block_offset: 0}
+    }
+
+    // Loads the next block. Returns false at the end of the file.
+    fn load_next_block(&mut self) -> Result<bool, Box<dyn std::error::Error>> {
+        self.inner.seek(SeekFrom::Start(self.next_block_offset))?;
+        let BlockHeader{bytes: header, block_size} = match read_block_header(&mut self.inner)? {
+            Some(x) => x,
+            None => return Ok(false),
+        };
+        let mut compressed = header;
+        let header_len = compressed.len();
+        compressed.resize(block_size, 0);
+        self.inner.read_exact(&mut compressed[header_len..])?;
+
+        self.block_start += self.block.len() as u64;
+        self.block.clear();
+        flate2::read::GzDecoder::new(compressed.as_slice()).read_to_end(&mut self.block)?;
+        self.pos_in_block = 0;
+        self.next_block_offset += block_size as u64;
+        Ok(true)
+    }
+
+    fn seek_to(&mut self, target: u64) -> Result<(), Box<dyn std::error::Error>> {
+        let in_current_block = target >= self.block_start && target < self.block_start + self.block.len() as u64;
+        if !in_current_block {
+            // Start from the last block that begins at or before the target
+            let block_idx = self.index.blocks.partition_point(|&(_, u)| u <= target).saturating_sub(1);
+            let (compressed, uncompressed) = self.index.blocks.get(block_idx).copied().unwrap_or((0, 0));
+            self.next_block_offset = compressed;
+            self.block_start = uncompressed;
+            self.block.clear();
+            self.pos_in_block = 0;
+            while self.block_start + self.block.len() as u64 <= target {
+                if !self.load_next_block()? {
+                    break; // Past the end: reads will return 0 bytes
+                }
+            }
+        }
+        self.pos_in_block = (target - self.block_start) as usize;
+        Ok(())
+    }
+}
+
+impl<R: Read + Seek> Read for BgzfReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        while self.pos_in_block >= self.block.len() {
+            // Loop because of empty blocks, such as the EOF marker block
+            let loaded = self.load_next_block().map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e.to_string()))?;
+            if !loaded { return Ok(0) }
+        }
+        let available = &self.block[self.pos_in_block..];
+        let n = std::cmp::min(available.len(), buf.len());
+        buf[..n].copy_from_slice(&available[..n]);
+        self.pos_in_block += n;
+        Ok(n)
+    }
+}
+
+impl<R: Read + Seek> Seek for BgzfReader<R> {
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        let current = self.block_start + self.pos_in_block as u64;
+        let target = match pos {
+            SeekFrom::Start(x) => x,
+            SeekFrom::Current(delta) => current.checked_add_signed(delta).ok_or(std::io::ErrorKind::InvalidInput)?,
+            SeekFrom::End(_) => return Err(std::io::Error::new(std::io::ErrorKind::Unsupported, "Seeking from the end of a BGZF file is not supported")),
+        };
+        self.seek_to(target).map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e.to_string()))?;
+        Ok(target)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    // Compresses the data into a single BGZF block
+    fn bgzf_block(data: &[u8]) -> Vec<u8> {
+        let mut encoder = flate2::write::DeflateEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(data).unwrap();
+        let cdata = encoder.finish().unwrap();
+        let mut crc = flate2::Crc::new();
+        crc.update(data);
+
+        let bsize = (18 + cdata.len() + 8 - 1) as u16;
+        let mut block = vec![0x1f, 0x8b, 8, 4, 0, 0, 0, 0, 0, 0xff, 6, 0, b'B', b'C', 2, 0];
+        block.extend_from_slice(&bsize.to_le_bytes());
+        block.extend_from_slice(&cdata);
+        block.extend_from_slice(&crc.sum().to_le_bytes());
+        block.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        block
+    }
+
+    #[test]
+    fn test_bgzf_seek(){
+        let mut file = Vec::<u8>::new();
+        file.extend(bgzf_block(b"ACGTACGT"));
+        file.extend(bgzf_block(b"TTTT"));
+        file.extend(bgzf_block(b"GGGGCCCC"));
+        file.extend(bgzf_block(b"")); // EOF marker
+
+        let index = GziIndex::build(file.as_slice()).unwrap();
+        assert_eq!(index.blocks.len(), 4);
+        assert_eq!(index.blocks[2].1, 12);
+
+        let mut reader = BgzfReader::new(std::io::Cursor::new(file), index);
+        let mut buf = [0u8; 6];
+        reader.seek(SeekFrom::Start(10)).unwrap();
+        reader.read_exact(&mut buf).unwrap(); // Spans a block boundary
+        assert_eq!(&buf, b"TTGGGG");
+
+        reader.seek(SeekFrom::Start(2)).unwrap();
+        let mut rest = Vec::new();
+        reader.read_to_end(&mut rest).unwrap();
+        assert_eq!(rest, b"GTACGTTTTTGGGGCCCC");
+    }
+}
@@ -28,6 +28,39 @@ pub fn build_cli() -> Command {
         .help("Output filename")
         .global(false);
 
+    let histogram_min =
+        Arg::new("min")
+        .long("min")
+        .value_parser(value_parser!(i64))
+        .help("Start of the first bin. Smaller values go to the first bin. By default, the smallest value.");
+    let histogram_max =
+        Arg::new("max")
+        .long("max")
+        .value_parser(value_parser!(i64))
+        .help("End of the last bin. Larger values go to the last bin. By default, the largest value.");
+    let histogram_nbins =
+        Arg::new("nbins")
+        .long("nbins")
+        .value_parser(value_parser!(usize))
+        .default_value("20")
+        .help("Number of bins. There are fewer bins if the range does not have enough distinct values.");
+    let histogram_log =
+        Arg::new("log-bins")
+        .long("log-bins")
+        .action(ArgAction::SetTrue)
+        .help("Use bins of exponentially increasing widths, for long-tailed distributions like long read lengths. The bins start from 1 or from --min if it is larger.");
+    let histogram_width =
+        Arg::new("width")
+        .long("width")
+        .value_parser(value_parser!(usize))
+        .default_value("40")
+        .help("Number of characters in the longest bar");
+    let histogram_tsv =
+        Arg::new("tsv")
+        .long("tsv")
+        .action(ArgAction::SetTrue)
+        .help("Print the start, end, count and percentage of each bin as TSV instead of bars");
+
     Command::new("seqtools")
         .version("0.1.0")
         .author("Jarno N. Alanko <alanko.jarno@gmail.com>")
@@ -42,10 +75,9 @@ pub fn build_cli() -> Command {
         .subcommand(
             Command::new("length-histogram")
                 .about("Print the length histogram of the sequences.")
+                .long_about("Print the length histogram of the sequences. Each line has the range of lengths in the bin, the number and percentage of sequences in it, and a bar.")
                 .arg_required_else_help(true)
-                .arg(Arg::new("min").long("min").default_value("0").help("Minimum value"))
-                .arg(Arg::new("max").long("max").default_value("1000").help("Maximum value"))
-                .arg(Arg::new("nbins").long("nbins").default_value("20").help("Number of bins")),
+                .args([&histogram_min, &histogram_max, &histogram_nbins, &histogram_log, &histogram_width, &histogram_tsv]),
         )
         .subcommand(
             Command::new("print-lengths")
@@ -179,20 +211,124 @@ pub fn build_cli() -> Command {
                     .long("names-listfile")
                     .help("One sequence name per line. Name is the part of the header that comes before the first space character, without the leading '>' or '@'.")
                 )
+                .arg(Arg::new("stream")
+                    .long("stream")
+                    .action(ArgAction::SetTrue)
+                    .help("Extract reads by name in one streaming pass, holding only the names in memory. The reads are written in file order instead of list order.")
+                    .conflicts_with("rank").conflicts_with("ranks-listfile").conflicts_with("index")
+                )
+                .arg(Arg::new("invert")
+                    .long("invert")
+                    .action(ArgAction::SetTrue)
+                    .help("Write all reads whose name is NOT in the list, e.g. to remove host or contaminant reads. Implies --stream.")
+                    .conflicts_with("rank").conflicts_with("ranks-listfile").conflicts_with("index")
+                )
+                .arg(Arg::new("strip-mate-suffix")
+                    .long("strip-mate-suffix")
+                    .action(ArgAction::SetTrue)
+                    .help("Compare names with trailing /1 and /2 suffixes removed, both in the list and in the reads.")
+                    .conflicts_with("rank").conflicts_with("ranks-listfile").conflicts_with("index")
+                )
+                .arg(Arg::new("index")
+                    .long("index")
+                    .action(ArgAction::SetTrue)
+                    .help("Seek directly to the reads using an on-disk index of read names and ranks in <input>.ridx. The index is built on first use and reused afterwards. Requires the input to be an uncompressed or bgzip-compressed file.")
+                )
+        ).subcommand(
+            Command::new("grep")
+                .about("Select reads by a regular expression on the header or by sequence motifs.")
+                .long_about("Select reads by a regular expression on the header or by sequence motifs. Motifs may contain IUPAC degenerate characters and are searched on both strands. If both a header regex and motifs are given, a read must match both. A read matches the motifs if it contains any of them.")
+                .arg_required_else_help(true)
+                .arg(Arg::new("header-regex")
+                    .short('r')
+                    .long("header-regex")
+                    .help("Regular expression matched against the whole header line, without the leading '>' or '@'.")
+                )
+                .arg(Arg::new("motif")
+                    .short('m')
+                    .long("motif")
+                    .help("Sequence motif with IUPAC characters, e.g. GAATTC or GGNCC. Can be given multiple times.")
+                    .action(ArgAction::Append) // Can have multiple
+                )
+                .arg(Arg::new("max-mismatches")
+                    .long("max-mismatches")
+                    .help("Maximum number of mismatches in a motif match. An IUPAC character matches any of the bases it stands for.")
+                    .default_value("0")
+                    .value_parser(value_parser!(usize))
+                )
+                .arg(Arg::new("forward-only")
+                    .long("forward-only")
+                    .action(ArgAction::SetTrue)
+                    .help("Do not search for the reverse complements of the motifs.")
+                )
+                .arg(Arg::new("invert")
+                    .short('v')
+                    .long("invert")
+                    .action(ArgAction::SetTrue)
+                    .help("Select reads that do NOT match.")
+                )
+                .arg(Arg::new("count")
+                    .short('c')
+                    .long("count")
+                    .action(ArgAction::SetTrue)
+                    .help("Only print the number of selected reads.")
+                )
+                .arg(&output_file)
+                .arg(&stdout_fasta)
+                .arg(&stdout_fastq)
+                .arg(&stdout_gzip)
+        ).subcommand(
+            Command::new("locate")
+                .about("Report the coordinates of all occurrences of sequence motifs as BED.")
+                .long_about("Report the coordinates of all occurrences of sequence motifs, such as primers or restriction sites. Motifs may contain IUPAC degenerate characters and are searched on both strands. The output has one line per occurrence with the tab-separated BED6 columns: sequence name, start (0-based), end (exclusive), motif, number of mismatches and strand.")
+                .arg_required_else_help(true)
+                .arg(Arg::new("motif")
+                    .short('m')
+                    .long("motif")
+                    .help("Sequence motif with IUPAC characters, e.g. GAATTC or GGNCC. Can be given multiple times.")
+                    .action(ArgAction::Append) // Can have multiple
+                )
+                .arg(Arg::new("motifs-file")
+                    .long("motifs-file")
+                    .help("File with one motif per line.")
+                )
+                .arg(Arg::new("max-mismatches")
+                    .long("max-mismatches")
+                    .help("Maximum number of mismatches in a motif match. An IUPAC character matches any of the bases it stands for.")
+                    .default_value("0")
+                    .value_parser(value_parser!(usize))
+                )
+                .arg(Arg::new("forward-only")
+                    .long("forward-only")
+                    .action(ArgAction::SetTrue)
+                    .help("Do not search for the reverse complements of the motifs.")
+                )
         ).subcommand(
             Command::new("extract-region")
-                .about("Extract a coordinate region of the first sequence in the file.")
+                .about("Extract a coordinate region of a sequence in the file.")
+                .long_about("Extract coordinate regions of sequences in the file. If regions like chr7:100000-100500 or a BED file are given, the sequences are looked up using a samtools-compatible .fai index next to the input file, which is built if it does not exist. This also works for files compressed with bgzip, in which case a .gzi index is used as well. Each region is written as a separate record with the region as the header. Otherwise, the region given with --start and --end is extracted from the first sequence in the file.")
                 .arg_required_else_help(true)                
+                .arg(Arg::new("region")
+                    .help("Region in samtools format: name, name:start or name:start-end, where the coordinates are 1-based and inclusive. Can be given multiple times. Requires the input to be a file.")
+                    .index(2)
+                    .action(ArgAction::Append) // Can have multiple
+                    .conflicts_with("start").conflicts_with("end")
+                )
+                .arg(Arg::new("bed")
+                    .long("bed")
+                    .help("BED file of regions to extract (0-based, end exclusive). If the sixth column (strand) is '-', the reverse complement is written. Requires the input to be a file.")
+                    .conflicts_with("start").conflicts_with("end")
+                )
                 .arg(Arg::new("start")
                     .short('s')
                     .long("start")
-                    .help("Start of the region")
+                    .help("Start of the region in the first sequence (0-based)")
                     .value_parser(clap::value_parser!(usize))
                 )
                 .arg(Arg::new("end")
                     .short('e')
                     .long("end")
-                    .help("End of the region (inclusive)")
+                    .help("End of the region in the first sequence (0-based, inclusive)")
                     .value_parser(clap::value_parser!(usize))
                 )
             .arg(&output_file)
@@ -200,6 +336,90 @@ pub fn build_cli() -> Command {
             .arg(&stdout_fastq)
             .arg(&stdout_gzip)
         )
+        .subcommand(Command::new("alignment-histogram")
+            .about("Print a histogram of a field of the alignments in a SAM or BAM file.")
+            .long_about("Print a histogram of a field of the alignments in a SAM or BAM file. The input is read from the input file or stdin, and the format is detected automatically. The fields are the 1-based leftmost position (pos), the mapping quality (mapq, skipping alignments with 255 for not available), the template length (tlen, from the leftmost mate only, so that each pair is counted once) and the aligned length (aligned-length, the number of reference bases covered according to the CIGAR string). By default, unmapped reads and secondary and supplementary alignments are excluded.")
+            .arg_required_else_help(true)
+            .arg(Arg::new("field")
+                .long("field")
+                .help("The field to make the histogram of")
+                .value_parser(["pos", "mapq", "tlen", "aligned-length"])
+                .default_value("pos")
+            )
+            .arg(Arg::new("require-flags")
+                .short('f')
+                .long("require-flags")
+                .help("Only include alignments with all of these flags set, in decimal or in hexadecimal with 0x")
+                .default_value("0")
+            )
+            .arg(Arg::new("exclude-flags")
+                .short('F')
+                .long("exclude-flags")
+                .help("Exclude alignments with any of these flags set, in decimal or in hexadecimal with 0x")
+                .default_value("0x904")
+            )
+            .arg(Arg::new("reference")
+                .short('r')
+                .long("reference")
+                .help("Only include alignments on this reference sequence. Can be given multiple times.")
+                .action(ArgAction::Append)
+            )
+            .args([&histogram_min, &histogram_max, &histogram_nbins, &histogram_log, &histogram_width, &histogram_tsv])
+        )
+        .subcommand(Command::new("coverage")
+            .about("Compute the depth of coverage along the references from a SAM or BAM file.")
+            .long_about("Compute the depth of coverage along the references from a SAM or BAM file, and write it as bedGraph. The input is read from the input file or stdin, and the format is detected automatically. Like in samtools depth, bases are covered by the M, = and X operations of the CIGAR strings, but not by deletions or skipped regions. The depths are computed for all references in the header, so the input does not need to be sorted, but memory use is four bytes per reference base. The mean depth and the breadth of coverage at 1x and 10x are printed to stderr. By default, unmapped reads and secondary, QC-failed and duplicate alignments are excluded.")
+            .arg_required_else_help(true)
+            .arg(Arg::new("output")
+                .short('o')
+                .long("output")
+                .help("Write the bedGraph to this file instead of stdout")
+            )
+            .arg(Arg::new("window")
+                .short('w')
+                .long("window")
+                .help("Write the mean depth in windows of this many bases instead of the depth at every base")
+                .value_parser(value_parser!(usize))
+            )
+            .arg(Arg::new("min-mapq")
+                .short('Q')
+                .long("min-mapq")
+                .help("Only include alignments with at least this mapping quality")
+                .value_parser(value_parser!(u8))
+                .default_value("0")
+            )
+            .arg(Arg::new("require-flags")
+                .short('f')
+                .long("require-flags")
+                .help("Only include alignments with all of these flags set, in decimal or in hexadecimal with 0x")
+                .default_value("0")
+            )
+            .arg(Arg::new("exclude-flags")
+                .short('F')
+                .long("exclude-flags")
+                .help("Exclude alignments with any of these flags set, in decimal or in hexadecimal with 0x")
+                .default_value("0x704")
+            )
+            .arg(Arg::new("reference")
+                .short('r')
+                .long("reference")
+                .help("Only report this reference sequence. Can be given multiple times.")
+                .action(ArgAction::Append)
+            )
+            .arg(Arg::new("summary")
+                .long("summary")
+                .help("Write the length, mean depth and breadth of coverage at 1x and 10x of each reference and in total as TSV to this file")
+            )
+            .arg(Arg::new("depth-histogram")
+                .long("depth-histogram")
+                .help("Write the number of reference bases with each depth as TSV to this file")
+            )
+        )
+        .subcommand(Command::new("faidx")
+            .about("Build a samtools-compatible .fai index for random access with extract-region.")
+            .long_about("Build a samtools-compatible .fai index for random access with extract-region. The index is written to <input>.fai. If the input is compressed with bgzip, a .gzi index is also written to <input>.gzi. Regular gzip compression does not allow random access.")
+            .arg_required_else_help(true)
+        )
         .subcommand(Command::new("concat")
             .about("Concatenate all sequences in the input.")
             .arg(Arg::new("header")
@@ -212,6 +432,632 @@ pub fn build_cli() -> Command {
             .arg(&stdout_fastq)
             .arg(&stdout_gzip)
         )
-        .subcommand(Command::new("gc-content").about("Compute the GC content of the sample. Non-ACGT characters are ignored."))
+        .subcommand(Command::new("kmer-compare")
+            .about("Compare the sets of distinct canonical k-mers of two or more files.")
+            .long_about("Compare the sets of distinct canonical k-mers of two or more files. The first file is the input file and the rest are given after it. Prints one tab-separated line for each pair of files with the number of distinct k-mers in both files, the sizes of the intersection and the union, the Jaccard index, and the fraction of the k-mers of each file contained in the other.")
+            .arg_required_else_help(true)
+            .arg(Arg::new("files")
+                .help("The other files to compare with the input file")
+                .index(2)
+                .action(ArgAction::Append) // Can have multiple
+            )
+            .arg(Arg::new("k")
+                .short('k')
+                .help("The k-mer length, at most 64. k-mers containing characters other than ACGT are skipped")
+                .required(true)
+                .value_parser(value_parser!(usize))
+            )
+            .arg(Arg::new("threads")
+                .short('t')
+                .long("threads")
+                .help("Number of files to process in parallel. By default, the number of available cores.")
+                .value_parser(value_parser!(usize))
+            )
+        )
+        .subcommand(Command::new("kmer-count")
+            .about("Count the canonical k-mers of the input and print the k-mer spectrum.")
+            .long_about("Count the canonical k-mers of the input and print the k-mer spectrum, that is, how many distinct k-mers occur each number of times. By default the spectrum is printed as a histogram. With --spectrum-tsv, it is printed as tab-separated lines of count and number of k-mers, which is the input format of genome size estimation tools like GenomeScope. k-mers with characters other than ACGT are skipped.")
+            .arg_required_else_help(true)
+            .arg(Arg::new("k")
+                .short('k')
+                .help("The k-mer length, at most 64")
+                .required(true)
+                .value_parser(value_parser!(usize))
+            )
+            .arg(Arg::new("kmers-out")
+                .long("kmers-out")
+                .help("Write the k-mers and their counts to this file")
+            )
+            .arg(Arg::new("format")
+                .long("format")
+                .help("Format of --kmers-out: tsv for k-mer and count on each line, fasta for the count as the header and the k-mer as the sequence")
+                .value_parser(["tsv", "fasta"])
+                .default_value("tsv")
+            )
+            .arg(Arg::new("min-count")
+                .long("min-count")
+                .help("Only write k-mers that occur at least this many times to --kmers-out")
+                .value_parser(value_parser!(u32))
+                .default_value("1")
+            )
+            .arg(Arg::new("spectrum-tsv")
+                .long("spectrum-tsv")
+                .help("Print the spectrum as TSV instead of a histogram")
+                .action(ArgAction::SetTrue)
+            )
+            .arg(Arg::new("max").long("max").default_value("100").value_parser(value_parser!(i64)).help("Largest count in the histogram. Higher counts go to the last bin."))
+            .arg(Arg::new("nbins").long("nbins").default_value("100").value_parser(value_parser!(usize)).help("Number of bins in the histogram"))
+            .args([&histogram_log, &histogram_width])
+        )
+        .subcommand(Command::new("interleave")
+            .about("Interleave the mates of paired-end reads from two files.")
+            .long_about("Interleave the mates of paired-end reads from two files: the input file has the first mates and the file given after it has the second mates. The names of the mates must match after removing /1 and /2 suffixes and everything after the first space, such as Illumina comment fields. Without --orphans, the files must have the same reads in the same order. With --orphans, the mates are paired by name, and reads whose mate is missing from the other file are written to the orphans file.")
+            .arg_required_else_help(true)
+            .arg(Arg::new("mates2")
+                .help("The file with the second mates")
+                .index(2)
+            )
+            .arg(Arg::new("orphans")
+                .long("orphans")
+                .help("Write reads whose mate is missing to this file instead of stopping with an error")
+            )
+            .arg(&output_file)
+            .arg(&stdout_fasta)
+            .arg(&stdout_fastq)
+            .arg(&stdout_gzip)
+        )
+        .subcommand(Command::new("deinterleave")
+            .about("Split interleaved paired-end reads into two files.")
+            .long_about("Split interleaved paired-end reads into two files. The names of the mates must match after removing /1 and /2 suffixes and everything after the first space, such as Illumina comment fields. With --orphans, a read that is not followed by its mate is written to the orphans file instead of stopping with an error.")
+            .arg(Arg::new("out1")
+                .long("out1")
+                .help("Output file for the first mates")
+                .required(true)
+            )
+            .arg(Arg::new("out2")
+                .long("out2")
+                .help("Output file for the second mates")
+                .required(true)
+            )
+            .arg(Arg::new("orphans")
+                .long("orphans")
+                .help("Write reads whose mate is missing to this file instead of stopping with an error")
+            )
+        )
+        .subcommand(Command::new("repair")
+            .about("Restore the pairing of paired-end reads that have gone out of sync.")
+            .long_about("Restore the pairing of paired-end reads that have gone out of sync, for example after filtering the first and second mates separately. The input is either two files, the input file with the first mates and the file given after it with the second mates, or one interleaved file. Mates are paired by name after removing /1 and /2 suffixes and everything after the first space. In an interleaved file, the mate number is taken from a /1 or /2 suffix or an Illumina comment like 2:N:0, or else from the order of the reads. Pairs are written in the order in which they are completed, either interleaved or to --out1 and --out2. Reads whose mate is missing are written to --singletons, if given. Reads are held in memory until their mate is found, or at most --window reads, after which they are taken to be singletons. Of two files, the one that is behind is read next, so memory use is small when the inputs are mostly in order.")
+            .arg_required_else_help(true)
+            .arg(Arg::new("mates2")
+                .help("The file with the second mates. If not given, the input is interleaved.")
+                .index(2)
+            )
+            .arg(Arg::new("out1")
+                .long("out1")
+                .help("Output file for the first mates. By default, the pairs are written interleaved.")
+                .requires("out2")
+                .conflicts_with_all(["output", "fasta-out", "fastq-out", "gzip-out"])
+            )
+            .arg(Arg::new("out2")
+                .long("out2")
+                .help("Output file for the second mates")
+                .requires("out1")
+            )
+            .arg(Arg::new("singletons")
+                .long("singletons")
+                .help("Write reads whose mate is missing to this file")
+            )
+            .arg(Arg::new("window")
+                .short('w')
+                .long("window")
+                .help("Number of reads after which a read whose mate has not been found is a singleton")
+                .value_parser(value_parser!(u64))
+                .default_value("100000")
+            )
+            .arg(&output_file)
+            .arg(&stdout_fasta)
+            .arg(&stdout_fastq)
+            .arg(&stdout_gzip)
+        )
+        .subcommand(Command::new("merge-pairs")
+            .about("Merge overlapping paired-end mates into single reads.")
+            .long_about("Merge overlapping paired-end mates into single reads, for example for amplicon sequencing. The input is either two files, the input file with the first mates and the file given after it with the second mates, or one interleaved file. The reverse complement of the second mate is aligned without gaps against the first mate, and the best overlap that is at least --min-overlap bases long and has at most --max-mismatch-rate mismatches is used. In the overlap, the consensus base is the one with the highest posterior probability given the qualities of both mates, and its quality is the posterior probability of error, capped at 41. If the insert is shorter than the reads, the reverse complement of the second mate starts before the first mate and both mates end in adapter sequence, which is trimmed from the merged read. Pairs that could not be merged are written to --unmerged, if given.")
+            .arg_required_else_help(true)
+            .arg(Arg::new("mates2")
+                .help("The file with the second mates. If not given, the input is interleaved.")
+                .index(2)
+            )
+            .arg(Arg::new("min-overlap")
+                .long("min-overlap")
+                .help("Minimum length of the overlap")
+                .default_value("10")
+                .value_parser(value_parser!(usize))
+            )
+            .arg(Arg::new("max-mismatch-rate")
+                .long("max-mismatch-rate")
+                .help("Maximum fraction of mismatches in the overlap")
+                .default_value("0.1")
+                .value_parser(value_parser!(f64))
+            )
+            .arg(Arg::new("unmerged")
+                .long("unmerged")
+                .help("Write the pairs that could not be merged to this file, interleaved")
+            )
+            .arg(&output_file)
+            .arg(&stdout_fasta)
+            .arg(&stdout_fastq)
+            .arg(&stdout_gzip)
+        )
+        .subcommand(Command::new("split")
+            .about("Split the input into many files.")
+            .long_about("Split the input into many files in the output directory. Exactly one of the splitting modes must be given. The output files are named <prefix><label>.fna or <prefix><label>.fastq, with .gz added with --gzip, where the label is the key from the header, the number of the part or chunk starting from 1, or the length range of the bin. With --paired-interleaved, pairs are kept in the same file: the key of a pair is taken from the first mate, and the length of a pair is the total length of the mates. Records with the same label do not need to be next to each other in the input.")
+            .arg_required_else_help(true)
+            .arg(Arg::new("outdir")
+                .short('d')
+                .long("outdir")
+                .help("Output directory. Created if it does not exist.")
+                .required(true)
+            )
+            .arg(Arg::new("prefix")
+                .long("prefix")
+                .help("Prefix of the output file names")
+                .default_value("")
+            )
+            .arg(Arg::new("by-header-regex")
+                .long("by-header-regex")
+                .help("Split by the part of the header matched by the first capture group of this regular expression, or by the whole match if there are no groups. Records whose header does not match go to the file labeled unmatched. For example, '^([^.]*)' splits by the accession before the first '.'.")
+            )
+            .arg(Arg::new("parts")
+                .long("parts")
+                .help("Split into this many parts, dealing the reads round-robin")
+                .value_parser(value_parser!(usize))
+            )
+            .arg(Arg::new("contiguous")
+                .long("contiguous")
+                .help("With --parts, make each part a contiguous range of reads of equal size instead. The input is read twice, so it must be a file.")
+                .action(ArgAction::SetTrue)
+                .requires("parts")
+            )
+            .arg(Arg::new("chunk-size")
+                .long("chunk-size")
+                .help("Split into chunks of this many reads, or bases with --chunk-unit bases")
+                .value_parser(value_parser!(u64))
+            )
+            .arg(Arg::new("chunk-unit")
+                .long("chunk-unit")
+                .help("Unit of --chunk-size. A chunk in bases ends before the read that would make it larger than the chunk size.")
+                .value_parser(["reads", "bases"])
+                .default_value("reads")
+            )
+            .arg(Arg::new("by-length-bins")
+                .long("by-length-bins")
+                .help("Split by sequence length into bins with these comma-separated boundaries. For example, 100,1000 gives the bins 0-99, 100-999 and 1000-inf.")
+            )
+            .group(clap::ArgGroup::new("split-mode")
+                .args(["by-header-regex", "parts", "chunk-size", "by-length-bins"])
+                .required(true)
+            )
+            .arg(Arg::new("paired-interleaved")
+                .long("paired-interleaved")
+                .help("The input is paired-end reads with the mates interleaved. Pairs are kept in the same file and counted as one unit in --parts and --chunk-size.")
+                .action(ArgAction::SetTrue)
+            )
+            .arg(Arg::new("max-open-files")
+                .long("max-open-files")
+                .help("Maximum number of output files open at a time. Files are closed and reopened for appending as needed.")
+                .default_value("128")
+                .value_parser(value_parser!(usize))
+            )
+            .arg(Arg::new("gzip")
+                .long("gzip")
+                .help("Compress the output files with gzip")
+                .action(ArgAction::SetTrue)
+            )
+        )
+        .subcommand(Command::new("prefixes")
+            .about("Write nested subsets of the input of increasing sizes, for titration experiments.")
+            .long_about("Write nested subsets of the input of increasing sizes, for titration experiments. Each subset is written to <out-prefix>-<size>.fna or <out-prefix>-<size>.fastq, with .gz added with --gzip. Exactly one of --ratio, --linear and --sizes must be given. By default the subsets are prefixes of the input. With --random, they are prefixes of a random permutation of the reads, so they are random but every subset is contained in the larger ones. A subset of size S in bases consists of the reads that start before S bases, so it has at least S bases unless the input is smaller. All outputs are written in a single pass over the input. The input is read once before that to count its size if --ratio, --random, or --linear without --steps is given.")
+            .arg_required_else_help(true)
+            .arg(Arg::new("out-prefix")
+                .short('p')
+                .long("out-prefix")
+                .help("Prefix of the output file names")
+                .required(true)
+            )
+            .arg(Arg::new("ratio")
+                .long("ratio")
+                .help("Sizes from the size of the input, dividing by this ratio at each step. For example, 2 gives the whole input, half, a quarter and so on.")
+                .value_parser(value_parser!(f64))
+            )
+            .arg(Arg::new("linear")
+                .long("linear")
+                .help("Sizes at multiples of this step")
+                .value_parser(value_parser!(u64))
+            )
+            .arg(Arg::new("sizes")
+                .long("sizes")
+                .help("Comma-separated list of sizes")
+            )
+            .group(clap::ArgGroup::new("prefix-sizes")
+                .args(["ratio", "linear", "sizes"])
+                .required(true)
+            )
+            .arg(Arg::new("steps")
+                .long("steps")
+                .help("Number of sizes with --ratio or --linear. By default, --ratio goes down to size 1 and --linear goes up to the size of the input.")
+                .value_parser(value_parser!(usize))
+            )
+            .arg(Arg::new("unit")
+                .long("unit")
+                .help("Unit of the sizes")
+                .value_parser(["reads", "bases"])
+                .default_value("reads")
+            )
+            .arg(Arg::new("random")
+                .long("random")
+                .help("Take random nested subsets instead of prefixes")
+                .action(ArgAction::SetTrue)
+            )
+            .arg(Arg::new("seed")
+                .help("The seed for the random number generator with --random. If not given, the seed is generated from the current time.")
+                .short('s')
+                .long("seed")
+                .value_parser(value_parser!(u64))
+                .requires("random")
+            )
+            .arg(Arg::new("gzip")
+                .long("gzip")
+                .help("Compress the output files with gzip")
+                .action(ArgAction::SetTrue)
+            )
+        )
+        .subcommand(Command::new("kmer-filter")
+            .about("Remove reads that share k-mers with a reference, such as a contaminant or host genome.")
+            .long_about("Remove reads that share k-mers with a reference, such as a contaminant or host genome. A read matches the reference if at least --min-shared of its canonical k-mers are in the reference, and they make up at least --min-fraction of all k-mers of the read. Matching reads are removed, or with --keep-matching, only matching reads are kept. With --paired-interleaved, a pair matches if either mate matches, and pairs are kept or removed together. The number of removed reads is printed to stderr.")
+            .arg_required_else_help(true)
+            .arg(Arg::new("reference")
+                .short('r')
+                .long("reference")
+                .help("Fasta or fastq file with the reference sequences")
+                .required(true)
+            )
+            .arg(Arg::new("k")
+                .short('k')
+                .help("The k-mer length, at most 64. k-mers containing characters other than ACGT are skipped")
+                .default_value("31")
+                .value_parser(value_parser!(usize))
+            )
+            .arg(Arg::new("min-shared")
+                .long("min-shared")
+                .help("Minimum number of k-mers shared with the reference for a read to match")
+                .default_value("1")
+                .value_parser(value_parser!(usize))
+            )
+            .arg(Arg::new("min-fraction")
+                .long("min-fraction")
+                .help("Minimum fraction of the k-mers of a read shared with the reference for the read to match")
+                .default_value("0")
+                .value_parser(value_parser!(f64))
+            )
+            .arg(Arg::new("keep-matching")
+                .long("keep-matching")
+                .help("Keep only the reads that match the reference")
+                .action(ArgAction::SetTrue)
+            )
+            .arg(Arg::new("paired-interleaved")
+                .long("paired-interleaved")
+                .help("The input is paired-end reads with the mates interleaved")
+                .action(ArgAction::SetTrue)
+            )
+            .arg(&output_file)
+            .arg(&stdout_fasta)
+            .arg(&stdout_fastq)
+            .arg(&stdout_gzip)
+        )
+        .subcommand(Command::new("filter")
+            .about("Remove reads by length, GC content, N content, quality and complexity.")
+            .long_about("Remove reads by length, GC content, N content, quality and complexity. Only the criteria that are given are checked. The GC content is the percentage of G and C among the ACGT characters, and N counts all characters other than ACGT. The quality criteria require fastq input with phred+33 qualities. The expected number of errors of a read is the sum of the error probabilities of its bases, as in usearch -fastq_maxee. The complexity is the Shannon entropy of the trinucleotide composition normalized between 0 and 1, like in prinseq. Homopolymers have complexity 0 and dinucleotide repeats about 0.2. A report of how many reads each criterion removed is printed to stderr, counting each read under the first criterion it fails in the order of this help.")
+            .arg_required_else_help(true)
+            .arg(Arg::new("min-length")
+                .long("min-length")
+                .help("Remove reads shorter than this")
+                .value_parser(value_parser!(usize))
+            )
+            .arg(Arg::new("max-length")
+                .long("max-length")
+                .help("Remove reads longer than this")
+                .value_parser(value_parser!(usize))
+            )
+            .arg(Arg::new("min-gc")
+                .long("min-gc")
+                .help("Remove reads with a GC percentage lower than this")
+                .value_parser(value_parser!(f64))
+            )
+            .arg(Arg::new("max-gc")
+                .long("max-gc")
+                .help("Remove reads with a GC percentage higher than this")
+                .value_parser(value_parser!(f64))
+            )
+            .arg(Arg::new("max-n")
+                .long("max-n")
+                .help("Remove reads with more than this many non-ACGT characters")
+                .value_parser(value_parser!(u64))
+            )
+            .arg(Arg::new("max-n-fraction")
+                .long("max-n-fraction")
+                .help("Remove reads with a larger fraction of non-ACGT characters than this")
+                .value_parser(value_parser!(f64))
+            )
+            .arg(Arg::new("min-mean-quality")
+                .long("min-mean-quality")
+                .help("Remove reads whose mean phred quality is lower than this")
+                .value_parser(value_parser!(f64))
+            )
+            .arg(Arg::new("max-expected-errors")
+                .long("max-expected-errors")
+                .help("Remove reads with more expected errors than this")
+                .value_parser(value_parser!(f64))
+            )
+            .arg(Arg::new("min-complexity")
+                .long("min-complexity")
+                .help("Remove reads with a normalized trinucleotide entropy lower than this, between 0 and 1")
+                .value_parser(value_parser!(f64))
+            )
+            .arg(Arg::new("paired-interleaved")
+                .long("paired-interleaved")
+                .help("The input is paired-end reads with the mates interleaved. A pair is removed if either mate fails.")
+                .action(ArgAction::SetTrue)
+            )
+            .arg(&output_file)
+            .arg(&stdout_fasta)
+            .arg(&stdout_fastq)
+            .arg(&stdout_gzip)
+        )
+        .subcommand(Command::new("mask")
+            .about("Mask low-complexity regions of sequences with DUST or trinucleotide entropy.")
+            .long_about("Mask low-complexity regions of sequences, or with --discard, remove the sequences that are low-complexity as a whole. The dust method is the symmetric DUST algorithm of sdust in minimap2: an interval is low-complexity if the sum of c(c-1)/2 over the counts c of its trinucleotides, divided by the number of trinucleotides minus one, is above the threshold divided by 10, and no sub-interval scores higher. The entropy method masks the windows whose Shannon entropy of the trinucleotide composition, normalized between 0 and 1 as in the filter subcommand, is below the threshold. Characters other than ACGT break trinucleotides. By default masked bases are converted to lowercase. The k-mer subcommands treat lowercase bases like uppercase ones, so use --hard to keep masked regions out of k-mer analyses. A report of the masked bases or removed sequences is printed to stderr.")
+            .arg_required_else_help(true)
+            .arg(Arg::new("method")
+                .long("method")
+                .help("The low-complexity criterion")
+                .value_parser(["dust", "entropy"])
+                .default_value("dust")
+            )
+            .arg(Arg::new("window")
+                .short('w')
+                .long("window")
+                .help("The window size in bases, at most the length of the longest masked interval for dust. At most 1000 for dust, whose running time grows with the window")
+                .value_parser(value_parser!(usize))
+                .default_value("64")
+            )
+            .arg(Arg::new("dust-threshold")
+                .long("dust-threshold")
+                .help("Score threshold of the dust method, ten times the score of an interval")
+                .value_parser(value_parser!(u32))
+                .default_value("20")
+            )
+            .arg(Arg::new("entropy-threshold")
+                .long("entropy-threshold")
+                .help("Entropy threshold of the entropy method, between 0 and 1")
+                .value_parser(value_parser!(f64))
+                .default_value("0.5")
+            )
+            .arg(Arg::new("hard")
+                .long("hard")
+                .help("Replace masked bases with N instead of converting them to lowercase")
+                .action(ArgAction::SetTrue)
+            )
+            .arg(Arg::new("discard")
+                .long("discard")
+                .help("Remove sequences that are low-complexity as a whole instead of masking: with dust, those with at least half of the bases masked, and with entropy, those whose entropy is below the threshold")
+                .action(ArgAction::SetTrue)
+                .conflicts_with("hard")
+            )
+            .arg(&output_file)
+            .arg(&stdout_fasta)
+            .arg(&stdout_fastq)
+            .arg(&stdout_gzip)
+        )
+        .subcommand(Command::new("sort")
+            .about("Sort sequences by length, name, sequence or GC content.")
+            .long_about("Sort sequences by length, name, sequence or GC content. The name is the header up to the first whitespace. In natural order, runs of digits in names are compared as numbers, so that read2 comes before read10. The GC content is the fraction of G and C among the ACGT characters, and sequences without ACGT characters come first. Sequences with the same key stay in their input order. Inputs larger than the memory limit are sorted in parts that are written to temporary files and then merged, so the temporary directory needs about as much free space as the input takes uncompressed. With --paired-interleaved, the mates of a pair stay together and the pair is sorted by the key of the first mate.")
+            .arg_required_else_help(true)
+            .arg(Arg::new("key")
+                .short('k')
+                .long("key")
+                .help("The sort key")
+                .value_parser(["length", "name", "sequence", "gc"])
+                .required(true)
+            )
+            .arg(Arg::new("name-order")
+                .long("name-order")
+                .help("How names are compared")
+                .value_parser(["natural", "lexical"])
+                .default_value("natural")
+            )
+            .arg(Arg::new("reverse")
+                .short('r')
+                .long("reverse")
+                .help("Sort in descending order")
+                .action(ArgAction::SetTrue)
+            )
+            .arg(Arg::new("paired-interleaved")
+                .long("paired-interleaved")
+                .help("The input is paired-end reads with the mates interleaved")
+                .action(ArgAction::SetTrue)
+            )
+            .arg(Arg::new("max-memory")
+                .short('m')
+                .long("max-memory")
+                .help("Approximate memory limit for the sequences in megabytes")
+                .value_parser(value_parser!(usize))
+                .default_value("1024")
+            )
+            .arg(Arg::new("temp-dir")
+                .long("temp-dir")
+                .help("Directory for temporary files. The system temporary directory by default.")
+                .value_parser(value_parser!(std::path::PathBuf))
+            )
+            .arg(&output_file)
+            .arg(&stdout_fasta)
+            .arg(&stdout_fastq)
+            .arg(&stdout_gzip)
+        )
+        .subcommand(Command::new("shuffle")
+            .about("Shuffle sequences into a uniformly random order.")
+            .long_about("Shuffle sequences into a uniformly random order. The random numbers come from the same seeded ChaCha20 generator as in subsample and prefixes, so the same seed, input and memory limit give the same order. Inputs larger than the memory limit are shuffled on disk: each sequence is written to a random one of 256 temporary files, and the files are then shuffled in memory one at a time, so the temporary directory needs about as much free space as the input takes uncompressed. With --paired-interleaved, the mates of a pair stay together.")
+            .arg(Arg::new("seed")
+                .short('s')
+                .long("seed")
+                .help("The seed for the random number generator. If not given, the seed is generated from the current time.")
+                .value_parser(value_parser!(u64))
+            )
+            .arg(Arg::new("paired-interleaved")
+                .long("paired-interleaved")
+                .help("The input is paired-end reads with the mates interleaved")
+                .action(ArgAction::SetTrue)
+            )
+            .arg(Arg::new("max-memory")
+                .short('m')
+                .long("max-memory")
+                .help("Approximate memory limit for the sequences in megabytes")
+                .value_parser(value_parser!(usize))
+                .default_value("1024")
+            )
+            .arg(Arg::new("temp-dir")
+                .long("temp-dir")
+                .help("Directory for temporary files. The system temporary directory by default.")
+                .value_parser(value_parser!(std::path::PathBuf))
+            )
+            .arg(&output_file)
+            .arg(&stdout_fasta)
+            .arg(&stdout_fastq)
+            .arg(&stdout_gzip)
+        )
+        .subcommand(Command::new("minimizers")
+            .about("Compute the (w,k)-minimizers of each sequence.")
+            .long_about("Compute the (w,k)-minimizers of each sequence: the smallest k-mer in each window of w consecutive k-mers, the leftmost one in case of ties. By default, prints the distinct minimizers of each sequence as tab-separated lines of sequence name, 0-based position, minimizer and strand. With --super-kmers, the sequences are instead split into super-k-mers, which are maximal runs of consecutive windows with the same minimizer, and printed in fasta format. k-mers with characters other than ACGT are skipped.")
+            .arg_required_else_help(true)
+            .arg(Arg::new("k")
+                .short('k')
+                .help("The k-mer length, at most 64")
+                .required(true)
+                .value_parser(value_parser!(usize))
+            )
+            .arg(Arg::new("w")
+                .short('w')
+                .help("The number of consecutive k-mers in a window")
+                .required(true)
+                .value_parser(value_parser!(usize))
+            )
+            .arg(Arg::new("order")
+                .long("order")
+                .help("The order of k-mers: hash orders by a 64-bit hash of the packed k-mer, lex orders lexicographically")
+                .value_parser(["hash", "lex"])
+                .default_value("hash")
+            )
+            .arg(Arg::new("canonical")
+                .long("canonical")
+                .help("Compare the smaller of the two orientations of each k-mer, so that a sequence and its reverse complement have the same minimizers")
+                .action(ArgAction::SetTrue)
+            )
+            .arg(Arg::new("super-kmers")
+                .long("super-kmers")
+                .help("Write the super-k-mers in fasta format instead of the minimizer positions")
+                .action(ArgAction::SetTrue)
+            )
+            .arg(Arg::new("output")
+                .short('o')
+                .long("output")
+                .help("Output file. By default, the output is written to stdout.")
+            )
+        )
+        .subcommand(Command::new("sketch")
+            .about("Build MinHash sketches of the canonical k-mer sets of one or more files.")
+            .long_about("Build MinHash sketches of the canonical k-mer sets of one or more files, for fast similarity estimates with sketch-compare. The first file is the input file and the rest are given after it. All sketches are written into a single sketch file. By default, the sketch of each file is the given number of smallest k-mer hashes (bottom-k MinHash, as in Mash). With --scaled, the sketch is all k-mer hashes below 2^64 divided by the scale (FracMinHash, as in sourmash), which gives better containment estimates between sets of different sizes. k-mers with characters other than ACGT are skipped.")
+            .arg_required_else_help(true)
+            .arg(Arg::new("files")
+                .help("More files to sketch")
+                .index(2)
+                .action(ArgAction::Append) // Can have multiple
+            )
+            .arg(Arg::new("k")
+                .short('k')
+                .help("The k-mer length, at most 64")
+                .default_value("21")
+                .value_parser(value_parser!(usize))
+            )
+            .arg(Arg::new("size")
+                .short('s')
+                .long("size")
+                .help("Number of hashes in each bottom-k sketch")
+                .default_value("1000")
+                .value_parser(value_parser!(usize))
+                .conflicts_with("scaled")
+            )
+            .arg(Arg::new("scaled")
+                .long("scaled")
+                .help("Build FracMinHash sketches that keep about one in this many k-mers")
+                .value_parser(value_parser!(u64))
+            )
+            .arg(Arg::new("output")
+                .short('o')
+                .long("output")
+                .help("Output sketch file")
+                .required(true)
+            )
+            .arg(Arg::new("threads")
+                .short('t')
+                .long("threads")
+                .help("Number of files to process in parallel. By default, the number of available cores.")
+                .value_parser(value_parser!(usize))
+            )
+        )
+        .subcommand(Command::new("sketch-compare")
+            .about("Estimate the similarity of all pairs of sketches in one or more sketch files.")
+            .long_about("Estimate the similarity of all pairs of sketches in one or more sketch files written by the sketch subcommand. The first file is the input file and the rest are given after it. Prints one tab-separated line for each pair of sketches with the number of shared hashes, the estimated Jaccard index, the estimated fraction of the k-mers of each sketched file contained in the other, and the Mash distance. The sketches must have the same k. Sketches of different kinds or sizes can be compared, but the estimates are only as accurate as the smaller sketch allows.")
+            .arg_required_else_help(true)
+            .arg(Arg::new("files")
+                .help("More sketch files")
+                .index(2)
+                .action(ArgAction::Append) // Can have multiple
+            )
+            .arg(Arg::new("threads")
+                .short('t')
+                .long("threads")
+                .help("Number of threads. By default, the number of available cores.")
+                .value_parser(value_parser!(usize))
+            )
+        )
+        .subcommand(Command::new("gc-content")
+            .about("Compute the GC content of the sample. Non-ACGT characters are ignored.")
+            .long_about("Compute the GC content of the sample. Non-ACGT characters are ignored. By default, the GC content of all sequences together is printed. With --per-sequence, the name, length, GC percentage and percentage of non-ACGT characters of each sequence are printed as TSV. With --window, the GC percentage in windows along each sequence is printed as bedGraph. With --histogram, the histogram of the GC percentages of the sequences is printed, which can reveal contamination from an organism with a different GC content.")
+            .arg(Arg::new("per-sequence")
+                .long("per-sequence")
+                .help("Print the GC content of each sequence as TSV")
+                .action(ArgAction::SetTrue)
+                .conflicts_with_all(["window", "histogram"])
+            )
+            .arg(Arg::new("window")
+                .short('w')
+                .long("window")
+                .help("Print the GC content in windows of this many bases as bedGraph")
+                .value_parser(value_parser!(usize))
+                .conflicts_with("histogram")
+            )
+            .arg(Arg::new("step")
+                .short('s')
+                .long("step")
+                .help("Distance between the starts of consecutive windows. By default, the window size.")
+                .value_parser(value_parser!(usize))
+                .requires("window")
+            )
+            .arg(Arg::new("histogram")
+                .long("histogram")
+                .help("Print the histogram of the GC percentages of the sequences. The range is from 0 to 100 unless given.")
+                .action(ArgAction::SetTrue)
+            )
+            .args([&histogram_min, &histogram_max, &histogram_nbins, &histogram_log, &histogram_width, &histogram_tsv])
+        )
         .subcommand(Command::new("stats").about("Print stats about the input."))
 }
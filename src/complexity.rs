@@ -0,0 +1,353 @@
+// Measures of sequence complexity, and masking of low-complexity regions.
+//
+// Two masking methods are supported. The symmetric DUST algorithm (Morgulis et al. 2006,
+// ported from sdust of minimap2) scores an interval by how often its trinucleotides repeat,
+// and masks the intervals whose score is above a threshold and not dominated by a
+// sub-interval. The entropy method masks the windows whose normalized trinucleotide entropy
+// is below a threshold. Both treat characters other than ACGT as breaks between trinucleotides.
+
+use std::collections::VecDeque;
+use jseqio::reader::DynamicFastXReader;
+use jseqio::record::OwnedRecord;
+use jseqio::writer::SeqRecordWriter;
+use crate::kmer::{encode_base, PackedKmer};
+
+// Entropy of the distribution given by the counts that sum to n, normalized by its largest
+// possible value: log2 of the smaller of 64 and n. Returns 0 if n < 2.
+fn normalized_entropy(counts: &[u32; 64], n: u32) -> f64 {
+    if n < 2 {
+        return 0.0;
+    }
+    let entropy: f64 = counts.iter().filter(|&&c| c > 0).map(|&c| {
+        let p = c as f64 / n as f64;
+        p * (1.0 / p).log2()
+    }).sum();
+    entropy / (std::cmp::min(64, n) as f64).log2()
+}
+
+// Shannon entropy of the trinucleotide composition of the sequence, normalized to be
+// between 0 and 1 like in prinseq: the entropy is divided by its largest possible value,
+// log2 of the smaller of 64 and the number of trinucleotides. Trinucleotides with
+// characters other than ACGT are skipped. Sequences with fewer than two trinucleotides
+// have entropy 0.
+pub fn trinucleotide_entropy(seq: &[u8]) -> f64 {
+    let mut counts = [0_u32; 64];
+    let mut n_trinucleotides = 0_u32;
+    for w in seq.windows(3) {
+        let Some(x) = u64::encode(w) else { continue };
+        counts[x as usize] += 1;
+        n_trinucleotides += 1;
+    }
+    normalized_entropy(&counts, n_trinucleotides)
+}
+
+// Largest supported DUST window. Finding the perfect intervals takes time proportional to
+// the window at each base that is in a high-scoring window.
+pub const MAX_DUST_WINDOW: usize = 1000;
+
+#[derive(Debug, Clone, Copy)]
+struct PerfectInterval {
+    start: usize,
+    finish: usize,
+    r: u64, // Sum of c(c-1)/2 over the trinucleotide counts
+    l: u64, // Number of trinucleotides minus one
+}
+
+// Moves the first perfect interval to the result if it starts before the window, merging it
+// with the previous region if they overlap, and drops the others that start before the
+// window. The perfect intervals are in decreasing order of start.
+fn save_masked_regions(result: &mut Vec<(usize, usize)>, perfect: &mut Vec<PerfectInterval>, window_start: usize) {
+    let Some(&p) = perfect.last() else { return };
+    if p.start >= window_start { return }
+    match result.last_mut() {
+        Some(last) if p.start <= last.1 => last.1 = std::cmp::max(last.1, p.finish),
+        _ => result.push((p.start, p.finish)),
+    }
+    while perfect.last().is_some_and(|p| p.start < window_start) {
+        perfect.pop();
+    }
+}
+
+// The sliding window of sdust: the trinucleotides in the window with their counts and score
+// (cw, rw), and the same for the longest suffix of the window in which no trinucleotide
+// occurs too often for it to be in a perfect interval (cv, rv, suffix_len).
+struct DustWindow {
+    triplets: VecDeque<usize>,
+    cw: [u32; 64],
+    cv: [u32; 64],
+    rw: u64,
+    rv: u64,
+    suffix_len: usize,
+}
+
+impl DustWindow {
+    fn new() -> Self {
+        DustWindow{triplets: VecDeque::new(), cw: [0; 64], cv: [0; 64], rw: 0, rv: 0, suffix_len: 0}
+    }
+
+    fn shift(&mut self, t: usize, window: usize, threshold: u32) {
+        if self.triplets.len() > window - 3 {
+            let s = self.triplets.pop_front().unwrap();
+            self.cw[s] -= 1;
+            self.rw -= self.cw[s] as u64;
+            if self.suffix_len > self.triplets.len() {
+                self.suffix_len -= 1;
+                self.cv[s] -= 1;
+                self.rv -= self.cv[s] as u64;
+            }
+        }
+        self.triplets.push_back(t);
+        self.suffix_len += 1;
+        self.rw += self.cw[t] as u64;
+        self.cw[t] += 1;
+        self.rv += self.cv[t] as u64;
+        self.cv[t] += 1;
+        if self.cv[t] as u64 * 10 > threshold as u64 * 2 {
+            // Shorten the suffix to start after the previous occurrence of t
+            loop {
+                let s = self.triplets[self.triplets.len() - self.suffix_len];
+                self.cv[s] -= 1;
+                self.rv -= self.cv[s] as u64;
+                self.suffix_len -= 1;
+                if s == t { break }
+            }
+        }
+    }
+
+    // Adds the perfect intervals that end at the end of the window and start before the suffix
+    fn find_perfect(&self, perfect: &mut Vec<PerfectInterval>, window_start: usize, threshold: u32) {
+        let mut c = self.cv;
+        let mut r = self.rv;
+        let (mut max_r, mut max_l) = (0_u64, 0_u64);
+        let n = self.triplets.len();
+        for i in (0..n - self.suffix_len).rev() {
+            let t = self.triplets[i];
+            r += c[t] as u64;
+            c[t] += 1;
+            let l = (n - i - 1) as u64;
+            if r * 10 > threshold as u64 * l {
+                let mut j = 0;
+                while j < perfect.len() && perfect[j].start >= i + window_start {
+                    let p = &perfect[j];
+                    if max_r == 0 || p.r * max_l > max_r * p.l {
+                        (max_r, max_l) = (p.r, p.l);
+                    }
+                    j += 1;
+                }
+                if max_r == 0 || r * max_l >= max_r * l {
+                    (max_r, max_l) = (r, l);
+                    perfect.insert(j, PerfectInterval{start: i + window_start, finish: n + 2 + window_start, r, l});
+                }
+            }
+        }
+    }
+}
+
+// The symmetric DUST algorithm. Returns the low-complexity regions as sorted, disjoint
+// 0-based half-open intervals. sdust uses window 64 and threshold 20 by default.
+pub fn sdust(seq: &[u8], window: usize, threshold: u32) -> Vec<(usize, usize)> {
+    assert!((4..=MAX_DUST_WINDOW).contains(&window), "the DUST window must be between 4 and {}", MAX_DUST_WINDOW);
+    let mut result = vec![];
+    let mut perfect = Vec::<PerfectInterval>::new();
+    let mut w = DustWindow::new();
+    let mut run_len = 0_usize; // Length of the current run of ACGT characters
+    let mut t = 0_usize; // The last trinucleotide
+    for i in 0..=seq.len() {
+        match seq.get(i).and_then(|&c| encode_base(c)) {
+            Some(b) => {
+                run_len += 1;
+                t = ((t << 2) | b as usize) & 63;
+                if run_len >= 3 {
+                    let window_start = run_len.saturating_sub(window) + (i + 1 - run_len);
+                    save_masked_regions(&mut result, &mut perfect, window_start);
+                    w.shift(t, window, threshold);
+                    if w.rw * 10 > w.suffix_len as u64 * threshold as u64 {
+                        w.find_perfect(&mut perfect, window_start, threshold);
+                    }
+                }
+            }
+            None => {
+                // A non-ACGT character or the end: save the remaining intervals and start over
+                let mut window_start = (run_len + 1).saturating_sub(window) + (i + 1 - run_len);
+                while !perfect.is_empty() {
+                    save_masked_regions(&mut result, &mut perfect, window_start);
+                    window_start += 1;
+                }
+                run_len = 0;
+                t = 0;
+                w = DustWindow::new();
+            }
+        }
+    }
+    result
+}
+
+// The windows of the given size whose normalized trinucleotide entropy is below the
+// threshold, merged into sorted, disjoint 0-based half-open intervals. Windows with fewer
+// than two ACGT trinucleotides are not masked. A sequence shorter than the window is one window.
+pub fn entropy_regions(seq: &[u8], window: usize, threshold: f64) -> Vec<(usize, usize)> {
+    assert!(window >= 3, "the entropy window must be at least 3");
+    let window = std::cmp::min(window, seq.len());
+    if window < 3 {
+        return vec![];
+    }
+    let triplets: Vec<Option<usize>> = seq.windows(3).map(|w| u64::encode(w).map(|x| x as usize)).collect();
+    let per_window = window - 2; // Trinucleotides in a window
+    let mut counts = [0_u32; 64];
+    let mut n = 0_u32;
+    let mut result: Vec<(usize, usize)> = vec![];
+    for (i, &t) in triplets.iter().enumerate() {
+        if let Some(t) = t {
+            counts[t] += 1;
+            n += 1;
+        }
+        if i >= per_window {
+            if let Some(s) = triplets[i - per_window] {
+                counts[s] -= 1;
+                n -= 1;
+            }
+        }
+        if i + 1 < per_window { continue }
+        let start = i + 1 - per_window;
+        if n >= 2 && normalized_entropy(&counts, n) < threshold {
+            match result.last_mut() {
+                Some(last) if start <= last.1 => last.1 = start + window,
+                _ => result.push((start, start + window)),
+            }
+        }
+    }
+    result
+}
+
+// Fraction of masked bases at which a sequence is low-complexity as a whole with DUST
+pub const DUST_DISCARD_FRACTION: f64 = 0.5;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ComplexityMethod {
+    Dust{window: usize, threshold: u32},
+    Entropy{window: usize, threshold: f64},
+}
+
+impl ComplexityMethod {
+    pub fn regions(&self, seq: &[u8]) -> Vec<(usize, usize)> {
+        match *self {
+            ComplexityMethod::Dust{window, threshold} => sdust(seq, window, threshold),
+            ComplexityMethod::Entropy{window, threshold} => entropy_regions(seq, window, threshold),
+        }
+    }
+
+    // Whether the sequence as a whole is below the complexity threshold. With DUST, this is
+    // when at least DUST_DISCARD_FRACTION of the bases are masked, since the score of the
+    // whole sequence grows with its length even if the sequence is random.
+    pub fn is_low_complexity(&self, seq: &[u8]) -> bool {
+        match *self {
+            ComplexityMethod::Dust{..} => {
+                let n_masked: usize = self.regions(seq).iter().map(|(start, end)| end - start).sum();
+                !seq.is_empty() && n_masked as f64 >= DUST_DISCARD_FRACTION * seq.len() as f64
+            }
+            ComplexityMethod::Entropy{threshold, ..} => trinucleotide_entropy(seq) < threshold,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MaskOptions {
+    pub method: ComplexityMethod,
+    pub hard: bool, // Replace the masked bases with N instead of converting them to lowercase
+    pub discard: bool, // Remove the low-complexity sequences instead of masking regions
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct MaskReport {
+    pub n_seqs: u64,
+    pub n_bases: u64,
+    pub n_masked_bases: u64,
+    pub n_discarded: u64,
+}
+
+pub fn mask_regions(seq: &mut [u8], regions: &[(usize, usize)], hard: bool) {
+    for &(start, end) in regions {
+        for c in seq[start..end].iter_mut() {
+            *c = if hard { b'N' } else { c.to_ascii_lowercase() };
+        }
+    }
+}
+
+// Masks the low-complexity regions of all sequences, or with options.discard, writes only
+// the sequences that are not low-complexity as a whole, unchanged.
+pub fn mask_reads(input: &mut DynamicFastXReader, output: &mut impl SeqRecordWriter, options: &MaskOptions) -> MaskReport {
+    let mut report = MaskReport::default();
+    while let Some(rec) = input.read_next().unwrap() {
+        report.n_seqs += 1;
+        report.n_bases += rec.seq.len() as u64;
+        if options.discard {
+            if options.method.is_low_complexity(rec.seq) {
+                report.n_discarded += 1;
+            } else {
+                output.write_ref_record(&rec).unwrap();
+            }
+            continue;
+        }
+        let mut rec: OwnedRecord = rec.to_owned();
+        let regions = options.method.regions(&rec.seq);
+        report.n_masked_bases += regions.iter().map(|(start, end)| (end - start) as u64).sum::<u64>();
+        mask_regions(&mut rec.seq, &regions, options.hard);
+        output.write_owned_record(&rec).unwrap();
+    }
+    output.flush().unwrap();
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::Rng;
+
+    #[test]
+    fn test_trinucleotide_entropy(){
+        assert_eq!(trinucleotide_entropy(b"AAAAAAAAAAAA"), 0.0);
+        assert_eq!(trinucleotide_entropy(b"AC"), 0.0);
+        // Three distinct trinucleotides is the maximum for three trinucleotides
+        assert!((trinucleotide_entropy(b"ACGTA") - 1.0).abs() < 1e-9);
+        let low = trinucleotide_entropy(b"ACACACACACACACACACACACAC");
+        let high = trinucleotide_entropy(b"ACGTTGCAAAGGTACCATGACGAT");
+        assert!(low < 0.3 && high > 0.8);
+    }
+
+    #[test]
+    fn test_masking(){
+        let random = b"GCTAAAGACAATTACATAACATACACGTCAGCACGAAACTTGTTGGCCCAGTGTGAATCG";
+        let mut seq = random.to_vec();
+        seq.extend_from_slice(&[b'A'; 30]);
+        seq.extend_from_slice(b"CTTAAGGGTTAAGTAAGTGTGATGCATACGN");
+        seq.extend_from_slice(&b"CAG".repeat(10));
+
+        assert!(sdust(random, 64, 20).is_empty());
+        assert!(entropy_regions(random, 20, 0.5).is_empty());
+
+        // The homopolymer, and the triplet repeat after the N
+        let regions = sdust(&seq, 64, 20);
+        assert_eq!(regions.len(), 2);
+        assert!(regions[0].0 <= 60 && (90..=92).contains(&regions[0].1));
+        assert_eq!(regions[1], (121, 151));
+
+        let regions = entropy_regions(&seq, 20, 0.5);
+        assert_eq!(regions.len(), 2);
+        assert!((45..=60).contains(&regions[0].0) && (90..=105).contains(&regions[0].1));
+        assert!((115..=121).contains(&regions[1].0) && regions[1].1 == 151); // Windows may span the N
+
+        mask_regions(&mut seq, &[(0, 2), (5, 6)], false);
+        assert_eq!(&seq[..7], b"gcTAAaG");
+        mask_regions(&mut seq, &[(1, 3)], true);
+        assert_eq!(&seq[..4], b"gNNA");
+
+        let dust = ComplexityMethod::Dust{window: 64, threshold: 20};
+        assert!(dust.is_low_complexity(&b"CAG".repeat(40)));
+        assert!(!dust.is_low_complexity(&seq)); // A third is masked
+        // Long random sequences are kept even though their trinucleotides repeat by chance
+        let mut rng = crate::seeded_rng(Some(1));
+        let long_random: Vec<u8> = (0..1000).map(|_| b"ACGT"[rng.random_range(0..4)]).collect();
+        assert!(!dust.is_low_complexity(&long_random[..400]));
+        assert!(!dust.is_low_complexity(&long_random));
+    }
+}
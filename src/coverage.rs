@@ -0,0 +1,200 @@
+// Depth of coverage along the references from the CIGAR strings of alignments.
+//
+// Like samtools depth, a reference base is covered by the M, = and X operations of an
+// alignment, but not by deletions or skipped regions. Overlapping mates are both counted.
+// The depths of all references are held in memory, four bytes per reference base, so the
+// input does not need to be sorted.
+
+use std::io::Write;
+use crate::sam::{AlignmentFilter, AlignmentReader};
+
+pub struct ReferenceDepth {
+    pub name: String,
+    pub depths: Vec<i32>, // Depth at each base
+}
+
+// Computes the depth at every base of every reference in the header. In SAM files without
+// @SQ lines, the references end at the end of their last alignment.
+pub fn compute_depths(reader: &mut AlignmentReader, filter: &AlignmentFilter, min_mapq: u8) -> Result<Vec<ReferenceDepth>, Box<dyn std::error::Error>> {
+    let mut diffs = Vec::<Vec<i32>>::new(); // Depth changes at each base, one extra at the end
+    while let Some(alignment) = reader.read_next()? {
+        if !filter.matches(&alignment, reader.references()) || alignment.mapq < min_mapq {
+            continue;
+        }
+        let (Some(ref_idx), true) = (alignment.reference, alignment.pos > 0) else { continue };
+        let ref_len = reader.references()[ref_idx].length as usize;
+        if diffs.len() <= ref_idx {
+            diffs.resize_with(ref_idx + 1, Vec::new);
+        }
+        let diff = &mut diffs[ref_idx];
+        if diff.len() < ref_len + 1 {
+            diff.resize(ref_len + 1, 0);
+        }
+
+        let mut pos = alignment.pos as usize - 1;
+        for &(len, op) in alignment.cigar.iter() {
+            let len = len as usize;
+            match op {
+                b'M' | b'=' | b'X' => {
+                    if diff.len() < pos + len + 1 {
+                        diff.resize(pos + len + 1, 0); // Past the end of the reference, or the length is not known
+                    }
+                    diff[pos] += 1;
+                    diff[pos + len] -= 1;
+                    pos += len;
+                }
+                b'D' | b'N' => pos += len,
+                _ => (), // I, S, H and P do not consume the reference
+            }
+        }
+    }
+
+    let references = reader.references();
+    diffs.resize_with(references.len(), Vec::new);
+    Ok(references.iter().zip(diffs).map(|(reference, mut diff)| {
+        let mut depth = 0;
+        for x in diff.iter_mut() {
+            depth += *x;
+            *x = depth;
+        }
+        // Alignments that extend past the end of a reference of known length are cut at the end
+        let length = if reference.length > 0 { reference.length as usize } else { diff.len().saturating_sub(1) };
+        diff.resize(length, 0);
+        ReferenceDepth{name: reference.name.clone(), depths: diff}
+    }).collect())
+}
+
+// Writes the depths as bedGraph with 0-based half-open intervals. Without a window, runs
+// of bases with the same depth are merged into one line. With a window, each line is the
+// mean depth in a window of that many bases, and the last window of a reference may be shorter.
+pub fn write_bedgraph(references: &[ReferenceDepth], window: Option<usize>, output: &mut impl Write) -> std::io::Result<()> {
+    for reference in references.iter() {
+        let depths = &reference.depths;
+        match window {
+            Some(window) => {
+                for (i, chunk) in depths.chunks(window).enumerate() {
+                    let mean = chunk.iter().map(|&d| d as u64).sum::<u64>() as f64 / chunk.len() as f64;
+                    writeln!(output, "{}\t{}\t{}\t{:.2}", reference.name, i * window, i * window + chunk.len(), mean)?;
+                }
+            }
+            None => {
+                let mut start = 0;
+                for end in 1..=depths.len() {
+                    if end == depths.len() || depths[end] != depths[start] {
+                        writeln!(output, "{}\t{}\t{}\t{}", reference.name, start, end, depths[start])?;
+                        start = end;
+                    }
+                }
+            }
+        }
+    }
+    output.flush()
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct CoverageStats {
+    pub name: String,
+    pub length: u64,
+    pub total_depth: u64, // Sum of the depths of all bases
+    pub covered_1x: u64, // Bases with depth at least 1
+    pub covered_10x: u64, // Bases with depth at least 10
+}
+
+impl CoverageStats {
+    pub fn new(reference: &ReferenceDepth) -> Self {
+        let depths = &reference.depths;
+        CoverageStats{
+            name: reference.name.clone(),
+            length: depths.len() as u64,
+            total_depth: depths.iter().map(|&d| d as u64).sum(),
+            covered_1x: depths.iter().filter(|&&d| d >= 1).count() as u64,
+            covered_10x: depths.iter().filter(|&&d| d >= 10).count() as u64,
+        }
+    }
+
+    // Combines the statistics of all references under the given name
+    pub fn total(stats: &[CoverageStats], name: &str) -> Self {
+        CoverageStats{
+            name: name.to_owned(),
+            length: stats.iter().map(|s| s.length).sum(),
+            total_depth: stats.iter().map(|s| s.total_depth).sum(),
+            covered_1x: stats.iter().map(|s| s.covered_1x).sum(),
+            covered_10x: stats.iter().map(|s| s.covered_10x).sum(),
+        }
+    }
+
+    fn fraction(&self, x: u64) -> f64 {
+        if self.length == 0 { 0.0 } else { x as f64 / self.length as f64 }
+    }
+
+    pub fn mean_depth(&self) -> f64 {
+        self.fraction(self.total_depth)
+    }
+
+    pub fn breadth_1x(&self) -> f64 {
+        self.fraction(self.covered_1x)
+    }
+
+    pub fn breadth_10x(&self) -> f64 {
+        self.fraction(self.covered_10x)
+    }
+}
+
+// Writes the statistics of each reference and their total as TSV
+pub fn write_coverage_stats_tsv(stats: &[CoverageStats], output: &mut impl Write) -> std::io::Result<()> {
+    writeln!(output, "reference\tlength\tmean_depth\tbreadth_1x\tbreadth_10x")?;
+    for s in stats.iter().chain(std::iter::once(&CoverageStats::total(stats, "total"))) {
+        writeln!(output, "{}\t{}\t{:.4}\t{:.4}\t{:.4}", s.name, s.length, s.mean_depth(), s.breadth_1x(), s.breadth_10x())?;
+    }
+    output.flush()
+}
+
+// Number of reference bases with each depth, up to the largest depth
+pub fn depth_histogram(references: &[ReferenceDepth]) -> Vec<u64> {
+    let mut histogram = Vec::<u64>::new();
+    for &d in references.iter().flat_map(|r| r.depths.iter()) {
+        if histogram.len() <= d as usize {
+            histogram.resize(d as usize + 1, 0);
+        }
+        histogram[d as usize] += 1;
+    }
+    histogram
+}
+
+pub fn write_depth_histogram_tsv(histogram: &[u64], output: &mut impl Write) -> std::io::Result<()> {
+    writeln!(output, "depth\tbases")?;
+    for (depth, count) in histogram.iter().enumerate() {
+        writeln!(output, "{}\t{}", depth, count)?;
+    }
+    output.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_depths(){
+        let sam = "@SQ\tSN:a\tLN:10\n@SQ\tSN:b\tLN:5\n\
+            r1\t0\ta\t2\t60\t2S3M2D2M\t*\t0\t0\t*\t*\n\
+            r2\t0\ta\t4\t60\t1M1I5M\t*\t0\t0\t*\t*\n\
+            r3\t4\t*\t0\t0\t*\t*\t0\t0\t*\t*\n\
+            r4\t0\tc\t3\t60\t2M\t*\t0\t0\t*\t*\n";
+        let mut reader = AlignmentReader::new(std::io::Cursor::new(sam)).unwrap();
+        let references = compute_depths(&mut reader, &AlignmentFilter::default(), 0).unwrap();
+        assert_eq!(references[0].depths, vec![0, 1, 1, 2, 1, 1, 2, 2, 1, 0]);
+        assert_eq!(references[1].depths, vec![0; 5]);
+        assert_eq!(references[2].depths, vec![0, 0, 1, 1]); // No @SQ line
+
+        let mut bedgraph = vec![];
+        write_bedgraph(&references[..1], None, &mut bedgraph).unwrap();
+        assert_eq!(String::from_utf8(bedgraph).unwrap(), "a\t0\t1\t0\na\t1\t3\t1\na\t3\t4\t2\na\t4\t6\t1\na\t6\t8\t2\na\t8\t9\t1\na\t9\t10\t0\n");
+        let mut bedgraph = vec![];
+        write_bedgraph(&references[..1], Some(4), &mut bedgraph).unwrap();
+        assert_eq!(String::from_utf8(bedgraph).unwrap(), "a\t0\t4\t1.00\na\t4\t8\t1.50\na\t8\t10\t0.50\n");
+
+        let stats = CoverageStats::new(&references[0]);
+        assert_eq!((stats.length, stats.total_depth, stats.covered_1x, stats.covered_10x), (10, 11, 8, 0));
+        assert_eq!(depth_histogram(&references), vec![9, 7, 3]);
+    }
+}
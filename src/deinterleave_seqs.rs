@@ -3,6 +3,7 @@ use jseqio::reader::*;
 use jseqio::writer::*;
 use std::env;
 
+// Superseded by `seqtools deinterleave`, which also checks that the mate names match.
 fn main(){
     let args: Vec<String> = env::args().collect();
     let infile = args[1].clone();
@@ -26,7 +27,7 @@ fn main(){
         n_seqs_read += 1;
     }
 
-    if n_seqs_read % 2 != 0 {
+    if !n_seqs_read.is_multiple_of(2) {
         panic!("Odd number of sequences in input file.")
     }
 
@@ -4,6 +4,7 @@ use jseqio::writer::DynamicFastXWriter;
 
 use std::env;
 
+// Superseded by `seqtools prefixes --ratio 2`, which writes all files in one pass.
 fn main(){
     let args: Vec<String> = env::args().collect();
     let infile = args[1].clone();
@@ -18,7 +19,7 @@ fn main(){
     for i in 0u64..n_subsamples {
         let howmany = n_seqs / 2u64.pow(i.try_into().unwrap()); // n / 2^i
         if howmany == 0 { break }
-        let outfile = format!("{}-{}.fna.gz", out_prefix, howmany.to_string());
+        let outfile = format!("{}-{}.fna.gz", out_prefix, howmany);
 
         eprintln!("Creating file {}", outfile);
 
@@ -0,0 +1,466 @@
+// Samtools-compatible .fai indexes for random access into FASTA and FASTQ files.
+//
+// Each line of a .fai file describes one sequence with the tab-separated columns
+// NAME, LENGTH, OFFSET, LINEBASES and LINEWIDTH, and for FASTQ additionally QUALOFFSET.
+// OFFSET is the byte offset of the first base, LINEBASES the number of bases on each
+// line and LINEWIDTH the number of bytes on each line including the newline. With these,
+// the byte offset of any base can be computed directly. For bgzip-compressed files the
+// offsets refer to the uncompressed data, and a .gzi index maps them to compressed blocks.
+
+use std::collections::HashMap;
+use std::io::{BufRead, Read, Seek, SeekFrom};
+use crate::bgzf::{BgzfReader, GziIndex};
+use jseqio::record::OwnedRecord;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FaiEntry {
+    pub name: String,
+    pub length: u64,
+    pub offset: u64,
+    pub line_bases: u64,
+    pub line_width: u64,
+    pub qual_offset: Option<u64>, // Only for FASTQ
+}
+
+pub struct FastaIndex {
+    pub entries: Vec<FaiEntry>,
+    name_to_idx: HashMap<String, usize>,
+}
+
+// Number of bytes in the line without the trailing "\n" or "\r\n".
+fn content_length(line: &[u8]) -> usize {
+    let mut len = line.len();
+    if len > 0 && line[len-1] == b'\n' { len -= 1 }
+    if len > 0 && line[len-1] == b'\r' { len -= 1 }
+    len
+}
+
+// Tracks the line layout of the sequence or quality lines of one record.
+struct LineLayout {
+    bases: u64,
+    width: u64,
+    total: u64,
+    saw_last_line: bool, // A line shorter than the others must be the last one
+}
+
+impl LineLayout {
+    fn new() -> Self {
+        LineLayout{bases: 0, width: 0, total: 0, saw_last_line: false}
+    }
+
+    fn add_line(&mut self, line: &[u8], name: &str) -> Result<(), Box<dyn std::error::Error>> {
+        let len = content_length(line) as u64;
+        if len == 0 {
+            self.saw_last_line = true; // Blank lines are allowed only at the end of a record
+            return Ok(());
+        }
+        // The last line of the file may have no newline, so its terminator is not compared
+        let terminated = line.last() == Some(&b'\n');
+        if self.bases == 0 {
+            self.bases = len;
+            self.width = if terminated { line.len() as u64 } else { len + 1 };
+        } else if self.saw_last_line || len > self.bases || (terminated && line.len() as u64 - len != self.width - self.bases) {
+            return Err(format!("Different line length in sequence '{}'. Indexing requires all lines of a record except the last to have the same length.", name).into());
+        } else if len < self.bases || !terminated {
+            self.saw_last_line = true;
+        }
+        self.total += len;
+        Ok(())
+    }
+}
+
+impl FastaIndex {
+
+    fn new(entries: Vec<FaiEntry>) -> Result<Self, Box<dyn std::error::Error>> {
+        let mut name_to_idx = HashMap::new();
+        for (i, e) in entries.iter().enumerate() {
+            if name_to_idx.insert(e.name.clone(), i).is_some() {
+                return Err(format!("Duplicate sequence name '{}'", e.name).into());
+            }
+        }
+        Ok(FastaIndex{entries, name_to_idx})
+    }
+
+    // Builds the index by streaming through uncompressed FASTA or FASTQ data.
+    pub fn build(mut input: impl BufRead) -> Result<Self, Box<dyn std::error::Error>> {
+        let is_fastq = match input.fill_buf()?.first() {
+            Some(b'>') | None => false,
+            Some(b'@') => true,
+            Some(_) => return Err("File does not start with '>' or '@'".into()),
+        };
+        if is_fastq {
+            Self::build_fastq(input)
+        } else {
+            Self::build_fasta(input)
+        }
+    }
+
+    fn build_fasta(mut input: impl BufRead) -> Result<Self, Box<dyn std::error::Error>> {
+        let mut entries = Vec::<FaiEntry>::new();
+        let mut layout = LineLayout::new();
+        let mut offset = 0_u64;
+        let mut line = Vec::<u8>::new();
+        loop {
+            line.clear();
+            let n = input.read_until(b'\n', &mut line)?;
+            if n == 0 { break }
+            offset += n as u64;
+
+            if line[0] == b'>' {
+                if let Some(prev) = entries.last_mut() {
+                    prev.length = layout.total;
+                    prev.line_bases = layout.bases;
+                    prev.line_width = layout.width;
+                }
+                layout = LineLayout::new();
+                let name = parse_name(&line[1..content_length(&line)]);
+                entries.push(FaiEntry{name, length: 0, offset, line_bases: 0, line_width: 0, qual_offset: None});
+            } else {
+                let name = &entries.last().ok_or("FASTA sequence data before the first header")?.name;
+                layout.add_line(&line, name)?;
+            }
+        }
+        if let Some(prev) = entries.last_mut() {
+            prev.length = layout.total;
+            prev.line_bases = layout.bases;
+            prev.line_width = layout.width;
+        }
+        Self::new(entries)
+    }
+
+    fn build_fastq(mut input: impl BufRead) -> Result<Self, Box<dyn std::error::Error>> {
+        let mut entries = Vec::<FaiEntry>::new();
+        let mut offset = 0_u64;
+        let mut line = Vec::<u8>::new();
+        loop {
+            // Header
+            line.clear();
+            let n = input.read_until(b'\n', &mut line)?;
+            if n == 0 { break }
+            offset += n as u64;
+            if content_length(&line) == 0 { continue } // Trailing blank lines
+            if line[0] != b'@' {
+                return Err(format!("FASTQ header line does not start with '@' at byte offset {}", offset - n as u64).into());
+            }
+            let name = parse_name(&line[1..content_length(&line)]);
+            let seq_offset = offset;
+
+            // Sequence lines up to the '+' line
+            let mut seq_layout = LineLayout::new();
+            loop {
+                line.clear();
+                let n = input.read_until(b'\n', &mut line)?;
+                if n == 0 { return Err(format!("Truncated FASTQ record '{}'", name).into()) }
+                offset += n as u64;
+                if line[0] == b'+' { break }
+                seq_layout.add_line(&line, &name)?;
+            }
+
+            // Quality lines until we have as many quality values as bases
+            let qual_offset = offset;
+            let mut qual_layout = LineLayout::new();
+            while qual_layout.total < seq_layout.total {
+                line.clear();
+                let n = input.read_until(b'\n', &mut line)?;
+                if n == 0 { return Err(format!("Truncated FASTQ record '{}'", name).into()) }
+                offset += n as u64;
+                qual_layout.add_line(&line, &name)?;
+            }
+            if qual_layout.total != seq_layout.total || qual_layout.bases != seq_layout.bases || qual_layout.width != seq_layout.width {
+                return Err(format!("Quality line layout differs from sequence line layout in FASTQ record '{}'", name).into());
+            }
+
+            entries.push(FaiEntry{name, length: seq_layout.total, offset: seq_offset, line_bases: seq_layout.bases, line_width: seq_layout.width, qual_offset: Some(qual_offset)});
+        }
+        Self::new(entries)
+    }
+
+    pub fn from_fai_file<P: AsRef<std::path::Path>>(path: P) -> Result<Self, Box<dyn std::error::Error>> {
+        let reader = std::io::BufReader::new(std::fs::File::open(path)?);
+        let mut entries = vec![];
+        for line in reader.lines() {
+            let line = line?;
+            let tokens: Vec<&str> = line.split('\t').collect();
+            if tokens.len() != 5 && tokens.len() != 6 {
+                return Err(format!("Invalid .fai line: {}", line).into());
+            }
+            entries.push(FaiEntry{
+                name: tokens[0].to_owned(),
+                length: tokens[1].parse()?,
+                offset: tokens[2].parse()?,
+                line_bases: tokens[3].parse()?,
+                line_width: tokens[4].parse()?,
+                qual_offset: match tokens.get(5) {
+                    Some(x) => Some(x.parse()?),
+                    None => None,
+                },
+            });
+        }
+        Self::new(entries)
+    }
+
+    pub fn write_fai_file<P: AsRef<std::path::Path>>(&self, path: P) -> Result<(), Box<dyn std::error::Error>> {
+        let mut out = std::io::BufWriter::new(std::fs::File::create(path)?);
+        for e in self.entries.iter() {
+            std::io::Write::write_all(&mut out, format!("{}\t{}\t{}\t{}\t{}", e.name, e.length, e.offset, e.line_bases, e.line_width).as_bytes())?;
+            if let Some(q) = e.qual_offset {
+                std::io::Write::write_all(&mut out, format!("\t{}", q).as_bytes())?;
+            }
+            std::io::Write::write_all(&mut out, b"\n")?;
+        }
+        std::io::Write::flush(&mut out)?;
+        Ok(())
+    }
+
+    pub fn get(&self, name: &str) -> Option<&FaiEntry> {
+        self.name_to_idx.get(name).map(|&i| &self.entries[i])
+    }
+
+    // Like Region::parse, but a string that is exactly a sequence name in the index is
+    // taken to mean the whole sequence even if it looks like it has coordinates.
+    pub fn parse_region(&self, s: &str) -> Result<Region, Box<dyn std::error::Error>> {
+        if self.get(s).is_some() {
+            Ok(Region{name: s.to_owned(), start: 0, end: None, reverse_complement: false})
+        } else {
+            Region::parse(s)
+        }
+    }
+}
+
+// The name is the part of the header up to the first whitespace
+fn parse_name(header: &[u8]) -> String {
+    let name = header.split(|c| c.is_ascii_whitespace()).next().unwrap_or(b"");
+    String::from_utf8_lossy(name).into_owned()
+}
+
+// A region of a named sequence. Coordinates are 0-based and the end is exclusive.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Region {
+    pub name: String,
+    pub start: u64,
+    pub end: Option<u64>, // None means up to the end of the sequence
+    pub reverse_complement: bool, // Minus strand
+}
+
+impl Region {
+    // Parses a region in samtools format: "name", "name:start" or "name:start-end", where the
+    // coordinates are 1-based and inclusive. Thousands separators (commas) are allowed.
+    pub fn parse(s: &str) -> Result<Region, Box<dyn std::error::Error>> {
+        if let Some((name, range)) = s.rsplit_once(':') {
+            let range = range.replace(',', "");
+            let (start, end) = match range.split_once('-') {
+                Some((a, b)) => (a.parse::<u64>(), b.parse::<u64>().map(Some)),
+                None => (range.parse::<u64>(), Ok(None)),
+            };
+            if let (Ok(start), Ok(end)) = (start, end) {
+                if start == 0 || end.is_some_and(|e| e < start) {
+                    return Err(format!("Invalid region {}: coordinates are 1-based and the end must not be before the start", s).into());
+                }
+                return Ok(Region{name: name.to_owned(), start: start - 1, end, reverse_complement: false});
+            }
+        }
+        // No valid coordinates: the whole string is a sequence name
+        Ok(Region{name: s.to_owned(), start: 0, end: None, reverse_complement: false})
+    }
+
+    // Parses a line of a BED file: chrom, start and end (0-based, end exclusive), optionally
+    // followed by name, score and strand columns. Returns None for comment, track and browser lines.
+    pub fn from_bed_line(line: &str) -> Result<Option<Region>, Box<dyn std::error::Error>> {
+        if line.trim().is_empty() || line.starts_with('#') || line.starts_with("track") || line.starts_with("browser") {
+            return Ok(None);
+        }
+        let tokens: Vec<&str> = line.split('\t').collect();
+        if tokens.len() < 3 {
+            return Err(format!("BED line has fewer than 3 columns: {}", line).into());
+        }
+        let start: u64 = tokens[1].trim().parse()?;
+        let end: u64 = tokens[2].trim().parse()?;
+        if end < start {
+            return Err(format!("BED line has end before start: {}", line).into());
+        }
+        let reverse_complement = match tokens.get(5).map(|s| s.trim()) {
+            Some("-") => true,
+            Some("+") | Some(".") | None => false,
+            Some(other) => return Err(format!("Invalid strand '{}' in BED line: {}", other, line).into()),
+        };
+        Ok(Some(Region{name: tokens[0].to_owned(), start, end: Some(end), reverse_complement}))
+    }
+
+    // Formats the region like samtools does for output headers, e.g. chr1:100-200 (1-based, inclusive).
+    // Regions on the minus strand get the suffix (-) like in bedtools.
+    pub fn to_header(&self, sequence_length: u64) -> String {
+        let mut header = if self.start == 0 && self.end.is_none() {
+            self.name.clone()
+        } else {
+            let end = self.end.map_or(sequence_length, |e| std::cmp::min(e, sequence_length));
+            format!("{}:{}-{}", self.name, self.start + 1, end)
+        };
+        if self.reverse_complement {
+            header.push_str("(-)");
+        }
+        header
+    }
+}
+
+pub trait ReadSeek: Read + Seek + Send {}
+impl<T: Read + Seek + Send> ReadSeek for T {}
+
+// A FASTA or FASTQ file opened for random access
+pub struct IndexedFastX {
+    source: Box<dyn ReadSeek>,
+    pub index: FastaIndex,
+}
+
+pub fn fai_path(path: &std::path::Path) -> std::path::PathBuf {
+    let mut p = path.as_os_str().to_owned();
+    p.push(".fai");
+    p.into()
+}
+
+pub fn gzi_path(path: &std::path::Path) -> std::path::PathBuf {
+    let mut p = path.as_os_str().to_owned();
+    p.push(".gzi");
+    p.into()
+}
+
+// Builds the .fai index of the file, and also the .gzi index if the file is compressed with bgzip.
+// The indexes are written next to the input file.
+pub fn build_index_files<P: AsRef<std::path::Path>>(path: P) -> Result<FastaIndex, Box<dyn std::error::Error>> {
+    let path = path.as_ref();
+    if crate::bgzf::is_bgzf(path)? {
+        GziIndex::build(std::io::BufReader::new(std::fs::File::open(path)?))?.write_to_file(gzi_path(path))?;
+    }
+    let index = FastaIndex::build(std::io::BufReader::new(open_seekable(path)?))?;
+    index.write_fai_file(fai_path(path))?;
+    Ok(index)
+}
+
+// Opens the file for seeking to offsets in the uncompressed data. Bgzip-compressed files
+// are read through their .gzi index, which is built if it does not exist.
+pub fn open_seekable<P: AsRef<std::path::Path>>(path: P) -> Result<Box<dyn ReadSeek>, Box<dyn std::error::Error>> {
+    let path = path.as_ref();
+    let file = std::fs::File::open(path)?;
+    if crate::bgzf::is_bgzf(path)? {
+        if !gzi_path(path).exists() {
+            GziIndex::build(std::io::BufReader::new(std::fs::File::open(path)?))?.write_to_file(gzi_path(path))?;
+        }
+        Ok(Box::new(BgzfReader::new(file, GziIndex::from_file(gzi_path(path))?)))
+    } else {
+        let mut magic = [0u8; 2];
+        if std::fs::File::open(path)?.read(&mut magic)? == 2 && magic == [0x1f, 0x8b] {
+            return Err("Random access is not possible in gzipped files. Please compress with bgzip instead.".into());
+        }
+        Ok(Box::new(std::io::BufReader::new(file)))
+    }
+}
+
+impl IndexedFastX {
+
+    // Opens the file using the existing .fai (and .gzi) index, or builds them if they do not exist.
+    pub fn open<P: AsRef<std::path::Path>>(path: P) -> Result<Self, Box<dyn std::error::Error>> {
+        let path = path.as_ref();
+        let index = if fai_path(path).exists() && (!crate::bgzf::is_bgzf(path)? || gzi_path(path).exists()) {
+            FastaIndex::from_fai_file(fai_path(path))?
+        } else {
+            eprintln!("Building index {}", fai_path(path).display());
+            build_index_files(path)?
+        };
+
+        let source = open_seekable(path)?;
+        Ok(IndexedFastX{source, index})
+    }
+
+    // Reads the bytes of positions [start, end) in a record with the given layout, skipping newlines.
+    fn read_bases(&mut self, base_offset: u64, entry: &FaiEntry, start: u64, end: u64) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+        if start >= end { return Ok(vec![]) }
+        let byte_offset = |pos: u64| base_offset + pos / entry.line_bases * entry.line_width + pos % entry.line_bases;
+        let first = byte_offset(start);
+        let last = byte_offset(end - 1);
+        self.source.seek(SeekFrom::Start(first))?;
+        let mut bytes = vec![0u8; (last - first + 1) as usize];
+        self.source.read_exact(&mut bytes)?;
+        bytes.retain(|&c| c != b'\n' && c != b'\r');
+        Ok(bytes)
+    }
+
+    // Returns the region as a record whose header is the region in samtools format.
+    pub fn fetch(&mut self, region: &Region) -> Result<OwnedRecord, Box<dyn std::error::Error>> {
+        let entry = self.index.get(&region.name).ok_or(format!("Sequence '{}' not found in the index", region.name))?.clone();
+        let end = region.end.map_or(entry.length, |e| std::cmp::min(e, entry.length));
+        if region.start >= entry.length {
+            return Err(format!("Region start {} is past the end of sequence '{}' of length {}", region.start + 1, region.name, entry.length).into());
+        }
+
+        let mut seq = self.read_bases(entry.offset, &entry, region.start, end)?;
+        seq.make_ascii_uppercase(); // Like the sequential parser
+        let mut qual = match entry.qual_offset {
+            Some(q) => Some(self.read_bases(q, &entry, region.start, end)?),
+            None => None,
+        };
+        if region.reverse_complement {
+            jseqio::reverse_complement_in_place(&mut seq);
+            if let Some(q) = qual.as_mut() {
+                q.reverse(); // Also reverse the quality values
+            }
+        }
+        Ok(OwnedRecord{head: region.to_header(entry.length).into_bytes(), seq, qual})
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_build_and_fetch(){
+        let fasta = b">chr1 first\nACGTA\nCCGGT\nTT\n>chr2\nGGGG\nCC\n";
+        let index = FastaIndex::build(&fasta[..]).unwrap();
+        assert_eq!(index.entries, vec![
+            FaiEntry{name: "chr1".to_owned(), length: 12, offset: 12, line_bases: 5, line_width: 6, qual_offset: None},
+            FaiEntry{name: "chr2".to_owned(), length: 6, offset: 33, line_bases: 4, line_width: 5, qual_offset: None},
+        ]);
+
+        let mut indexed = IndexedFastX{source: Box::new(std::io::Cursor::new(fasta.to_vec())), index};
+        let rec = indexed.fetch(&Region::parse("chr1:4-11").unwrap()).unwrap();
+        assert_eq!(rec, OwnedRecord{head: b"chr1:4-11".to_vec(), seq: b"TACCGGTT".to_vec(), qual: None});
+        let rec = indexed.fetch(&Region::parse("chr2:5").unwrap()).unwrap();
+        assert_eq!(rec.seq, b"CC");
+        assert_eq!(rec.head, b"chr2:5-6");
+
+        let fastq = b"@r1\nACGT\n+\nIIHH\n@r2\nGG\n+r2\n#I\n";
+        let index = FastaIndex::build(&fastq[..]).unwrap();
+        assert_eq!(index.entries[1], FaiEntry{name: "r2".to_owned(), length: 2, offset: 20, line_bases: 2, line_width: 3, qual_offset: Some(27)});
+        let mut indexed = IndexedFastX{source: Box::new(std::io::Cursor::new(fastq.to_vec())), index};
+        let rec = indexed.fetch(&Region::parse("r1:2-3").unwrap()).unwrap();
+        assert_eq!(rec.seq, b"CG");
+        assert_eq!(rec.qual.unwrap(), b"IH");
+        let rec = indexed.fetch(&Region::from_bed_line("r1\t0\t3\t.\t.\t-").unwrap().unwrap()).unwrap();
+        assert_eq!(rec.head, b"r1:1-3(-)");
+        assert_eq!(rec.seq, b"CGT");
+        assert_eq!(rec.qual.unwrap(), b"HII");
+
+        assert!(FastaIndex::build(&b">x\nACG\nACGT\n"[..]).is_err()); // Inconsistent line lengths
+
+        // No newline at the end of the file
+        let fasta = b">a\nACGTACGT\nACGTACGT\nACG";
+        let index = FastaIndex::build(&fasta[..]).unwrap();
+        assert_eq!(index.entries[0], FaiEntry{name: "a".to_owned(), length: 19, offset: 3, line_bases: 8, line_width: 9, qual_offset: None});
+        let mut indexed = IndexedFastX{source: Box::new(std::io::Cursor::new(fasta.to_vec())), index};
+        assert_eq!(indexed.fetch(&Region::parse("a:15-19").unwrap()).unwrap().seq, b"GTACG");
+        let index = FastaIndex::build(&b">a\nACGT"[..]).unwrap();
+        assert_eq!((index.entries[0].line_bases, index.entries[0].line_width), (4, 5));
+        assert!(FastaIndex::build(&b">a\nACG\nACGT"[..]).is_err());
+
+        let index = FastaIndex::build(&b">HLA-A*01:01\nACGT\n"[..]).unwrap();
+        assert_eq!(index.parse_region("HLA-A*01:01").unwrap().end, None); // A name, not coordinates
+    }
+
+    #[test]
+    fn test_parse_region(){
+        assert_eq!(Region::parse("chr7:100,000-100,500").unwrap(), Region{name: "chr7".to_owned(), start: 99999, end: Some(100500), reverse_complement: false});
+        assert_eq!(Region::parse("chr7").unwrap(), Region{name: "chr7".to_owned(), start: 0, end: None, reverse_complement: false});
+        assert!(Region::from_bed_line("track name=x").unwrap().is_none());
+        assert_eq!(Region::from_bed_line("chr1\t99\t200\tgene\t0\t-").unwrap().unwrap(), Region{name: "chr1".to_owned(), start: 99, end: Some(200), reverse_complement: true});
+        assert!(Region::parse("chr1:0-10").is_err());
+    }
+}
@@ -0,0 +1,156 @@
+// Filtering of reads by length, base composition, quality and complexity.
+
+use jseqio::reader::DynamicFastXReader;
+use jseqio::record::OwnedRecord;
+use jseqio::writer::SeqRecordWriter;
+use crate::gc::BaseCounts;
+
+const PHRED_OFFSET: u8 = 33;
+
+// The criteria in the order in which they are checked
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FilterReason {
+    TooShort,
+    TooLong,
+    LowGc,
+    HighGc,
+    TooManyN,
+    LowMeanQuality,
+    TooManyExpectedErrors,
+    LowComplexity,
+}
+
+impl FilterReason {
+    pub const ALL: [FilterReason; 8] = [
+        FilterReason::TooShort, FilterReason::TooLong, FilterReason::LowGc, FilterReason::HighGc,
+        FilterReason::TooManyN, FilterReason::LowMeanQuality, FilterReason::TooManyExpectedErrors, FilterReason::LowComplexity,
+    ];
+
+    pub fn description(&self) -> &'static str {
+        match self {
+            FilterReason::TooShort => "too short",
+            FilterReason::TooLong => "too long",
+            FilterReason::LowGc => "GC content too low",
+            FilterReason::HighGc => "GC content too high",
+            FilterReason::TooManyN => "too many Ns",
+            FilterReason::LowMeanQuality => "mean quality too low",
+            FilterReason::TooManyExpectedErrors => "too many expected errors",
+            FilterReason::LowComplexity => "low complexity",
+        }
+    }
+}
+
+// Criteria that are None are not checked
+#[derive(Debug, Clone, Default)]
+pub struct FilterOptions {
+    pub min_length: Option<usize>,
+    pub max_length: Option<usize>,
+    pub min_gc: Option<f64>, // Percentage of GC among ACGT characters. Reads without ACGT characters fail GC criteria.
+    pub max_gc: Option<f64>,
+    pub max_n: Option<u64>, // Characters other than ACGT
+    pub max_n_fraction: Option<f64>,
+    pub min_mean_quality: Option<f64>, // Mean of the phred scores
+    pub max_expected_errors: Option<f64>, // Sum of the error probabilities of the bases, as in usearch -fastq_maxee
+    pub min_entropy: Option<f64>, // Normalized trinucleotide entropy, see complexity::trinucleotide_entropy
+    pub paired_interleaved: bool, // A pair is removed if either mate fails
+}
+
+impl FilterOptions {
+    pub fn uses_qualities(&self) -> bool {
+        self.min_mean_quality.is_some() || self.max_expected_errors.is_some()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct FilterReport {
+    pub n_reads: u64,
+    pub n_removed: u64,
+    pub n_removed_by: [u64; FilterReason::ALL.len()], // Reads removed because of each criterion, indexed by FilterReason as usize
+}
+
+// Expected number of errors in the read: the sum of the error probabilities of the bases
+pub fn expected_errors(qual: &[u8]) -> f64 {
+    qual.iter().map(|&q| 10_f64.powf(-(q.saturating_sub(PHRED_OFFSET) as f64) / 10.0)).sum()
+}
+
+// Returns the first criterion that the read fails, or None if it passes all. Reads without
+// qualities pass the quality criteria.
+pub fn check_read(rec: &OwnedRecord, options: &FilterOptions) -> Option<FilterReason> {
+    let len = rec.seq.len();
+    if options.min_length.is_some_and(|min| len < min) { return Some(FilterReason::TooShort) }
+    if options.max_length.is_some_and(|max| len > max) { return Some(FilterReason::TooLong) }
+
+    let counts = BaseCounts::new(&rec.seq);
+    let gc = counts.gc_percent();
+    if let Some(min) = options.min_gc {
+        if gc.is_none_or(|gc| gc < min) { return Some(FilterReason::LowGc) }
+    }
+    if let Some(max) = options.max_gc {
+        if gc.is_none_or(|gc| gc > max) { return Some(FilterReason::HighGc) }
+    }
+    if options.max_n.is_some_and(|max| counts.other > max) { return Some(FilterReason::TooManyN) }
+    if options.max_n_fraction.is_some_and(|max| len > 0 && counts.other as f64 / len as f64 > max) { return Some(FilterReason::TooManyN) }
+
+    if let Some(qual) = rec.qual.as_ref() {
+        if let Some(min) = options.min_mean_quality {
+            let sum: u64 = qual.iter().map(|&q| q.saturating_sub(PHRED_OFFSET) as u64).sum();
+            if qual.is_empty() || (sum as f64 / qual.len() as f64) < min { return Some(FilterReason::LowMeanQuality) }
+        }
+        if options.max_expected_errors.is_some_and(|max| expected_errors(qual) > max) { return Some(FilterReason::TooManyExpectedErrors) }
+    }
+
+    if options.min_entropy.is_some_and(|min| crate::complexity::trinucleotide_entropy(&rec.seq) < min) { return Some(FilterReason::LowComplexity) }
+    None
+}
+
+// Writes the reads that pass all criteria. A removed read is counted under the first criterion
+// it fails. With paired_interleaved, both mates are removed if either fails, and the mate
+// that passed is counted under the criterion that the other mate failed.
+pub fn filter_reads(input: &mut DynamicFastXReader, output: &mut impl SeqRecordWriter, options: &FilterOptions) -> Result<FilterReport, Box<dyn std::error::Error>> {
+    let mut report = FilterReport::default();
+    while let Some(group) = crate::paired::read_group(input, options.paired_interleaved)? {
+
+        report.n_reads += group.len() as u64;
+        match group.iter().find_map(|rec| check_read(rec, options)) {
+            Some(reason) => {
+                report.n_removed += group.len() as u64;
+                report.n_removed_by[reason as usize] += group.len() as u64;
+            }
+            None => {
+                for rec in group.iter() {
+                    output.write_owned_record(rec)?;
+                }
+            }
+        }
+    }
+    output.flush()?;
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check_read(){
+        let rec = |seq: &[u8], qual: &[u8]| OwnedRecord{head: b"r".to_vec(), seq: seq.to_vec(), qual: Some(qual.to_vec())};
+        let options = FilterOptions{min_length: Some(4), max_gc: Some(60.0), max_n: Some(1), min_mean_quality: Some(20.0), max_expected_errors: Some(0.5), ..Default::default()};
+        assert_eq!(check_read(&rec(b"ACG", b"III"), &options), Some(FilterReason::TooShort));
+        assert_eq!(check_read(&rec(b"GCGCAT", b"IIIIII"), &options), Some(FilterReason::HighGc));
+        assert_eq!(check_read(&rec(b"ACGTNN", b"IIIIII"), &options), Some(FilterReason::TooManyN));
+        assert_eq!(check_read(&rec(b"ACGTAT", b"555555"), &options), None); // Mean quality 20 is enough
+        assert_eq!(check_read(&rec(b"ACGTAT", b"55555+"), &options), Some(FilterReason::LowMeanQuality));
+        assert_eq!(check_read(&rec(b"ACGTATTA", b"IIIIII##"), &options), Some(FilterReason::TooManyExpectedErrors));
+
+        // Quality criteria do not apply to fasta
+        let fasta = OwnedRecord{head: b"r".to_vec(), seq: b"ACGTAT".to_vec(), qual: None};
+        assert_eq!(check_read(&fasta, &options), None);
+
+        // No ACGT characters fails a GC criterion
+        let options = FilterOptions{min_gc: Some(0.0), ..Default::default()};
+        assert_eq!(check_read(&fasta, &options), None);
+        assert_eq!(check_read(&rec(b"NNN", b"III"), &options), Some(FilterReason::LowGc));
+
+        assert!((expected_errors(b"+5") - 0.11).abs() < 1e-9);
+    }
+}
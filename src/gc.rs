@@ -0,0 +1,121 @@
+// GC content of individual sequences and of windows along them.
+//
+// The GC content is the fraction of G and C among the ACGT characters, so other characters
+// such as N do not lower it. They are reported separately as the N percentage, which counts
+// all characters other than ACGT.
+
+use std::io::Write;
+use jseqio::reader::DynamicFastXReader;
+use jseqio::record::Record;
+use crate::histogram::HistogramOptions;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct BaseCounts {
+    pub gc: u64,
+    pub at: u64,
+    pub other: u64, // Characters other than ACGT
+}
+
+impl BaseCounts {
+    pub fn new(seq: &[u8]) -> Self {
+        let mut counts = BaseCounts::default();
+        for &c in seq {
+            match c.to_ascii_uppercase() {
+                b'G' | b'C' => counts.gc += 1,
+                b'A' | b'T' => counts.at += 1,
+                _ => counts.other += 1,
+            }
+        }
+        counts
+    }
+
+    // Percentage of GC among ACGT characters, or None if there are none
+    pub fn gc_percent(&self) -> Option<f64> {
+        (self.gc + self.at > 0).then(|| 100.0 * self.gc as f64 / (self.gc + self.at) as f64)
+    }
+
+    // Percentage of characters other than ACGT
+    pub fn n_percent(&self) -> f64 {
+        let total = self.gc + self.at + self.other;
+        if total == 0 { 0.0 } else { 100.0 * self.other as f64 / total as f64 }
+    }
+}
+
+fn format_percent(x: Option<f64>) -> String {
+    x.map_or("NA".to_owned(), |x| format!("{:.2}", x))
+}
+
+// Writes the name, length, GC percentage and N percentage of each sequence as TSV.
+// The GC percentage is NA for sequences without ACGT characters.
+pub fn write_gc_per_sequence(reader: &mut DynamicFastXReader, output: &mut impl Write) -> std::io::Result<()> {
+    writeln!(output, "name\tlength\tgc_percent\tn_percent")?;
+    while let Some(rec) = reader.read_next().unwrap() {
+        let counts = BaseCounts::new(rec.seq);
+        writeln!(output, "{}\t{}\t{}\t{:.2}", String::from_utf8_lossy(rec.name()), rec.seq.len(), format_percent(counts.gc_percent()), counts.n_percent())?;
+    }
+    output.flush()
+}
+
+// Writes the GC percentage in windows of the given size starting every step bases along
+// each sequence as bedGraph. The last window of a sequence may be shorter. Windows without
+// ACGT characters are left out.
+pub fn write_gc_windows(reader: &mut DynamicFastXReader, window: usize, step: usize, output: &mut impl Write) -> std::io::Result<()> {
+    assert!(window > 0 && step > 0, "window and step must be positive");
+    // Counts of G/C and ACGT characters in seq[lo..hi], updated as the window slides
+    let base_counts = |c: u8| match c.to_ascii_uppercase() {
+        b'G' | b'C' => (1, 1),
+        b'A' | b'T' => (0, 1),
+        _ => (0, 0),
+    };
+    while let Some(rec) = reader.read_next().unwrap() {
+        let name = String::from_utf8_lossy(rec.name());
+        let (mut gc, mut acgt) = (0_usize, 0_usize);
+        let (mut lo, mut hi) = (0_usize, 0_usize);
+        for start in (0..rec.seq.len()).step_by(step) {
+            let end = std::cmp::min(start + window, rec.seq.len());
+            for &c in &rec.seq[hi..end] {
+                let (g, a) = base_counts(c);
+                gc += g;
+                acgt += a;
+            }
+            for &c in &rec.seq[lo..start] {
+                let (g, a) = base_counts(c);
+                gc -= g;
+                acgt -= a;
+            }
+            (lo, hi) = (start, end);
+            if acgt > 0 {
+                let gc_percent = 100.0 * gc as f64 / acgt as f64;
+                writeln!(output, "{}\t{}\t{}\t{:.2}", name, start, end, gc_percent)?;
+            }
+            if end == rec.seq.len() { break }
+        }
+    }
+    output.flush()
+}
+
+// Prints the histogram of the GC percentages of the sequences, rounded to integers.
+// Sequences without ACGT characters are left out. The range is 0-100 unless given.
+pub fn print_gc_histogram(reader: &mut DynamicFastXReader, options: &HistogramOptions) {
+    let mut values = Vec::<i64>::new();
+    while let Some(rec) = reader.read_next().unwrap() {
+        values.extend(BaseCounts::new(rec.seq).gc_percent().map(|x| x.round() as i64));
+    }
+    let options = HistogramOptions{min: options.min.or(Some(0)), max: options.max.or(Some(100)), ..options.clone()};
+    crate::histogram::print_histogram(values.into_iter(), &options);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_base_counts(){
+        let counts = BaseCounts::new(b"ACgtNNGGcx");
+        assert_eq!(counts, BaseCounts{gc: 5, at: 2, other: 3});
+        assert!((counts.gc_percent().unwrap() - 500.0 / 7.0).abs() < 1e-9);
+        assert!((counts.n_percent() - 30.0).abs() < 1e-9);
+        assert_eq!(BaseCounts::new(b"NNN").gc_percent(), None);
+        assert_eq!(BaseCounts::new(b"").n_percent(), 0.0);
+    }
+}
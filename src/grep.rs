@@ -0,0 +1,33 @@
+// Selection of records by a header regex and sequence motifs.
+
+use crate::motif::Motif;
+
+pub struct GrepOptions {
+    pub header_regex: Option<regex::bytes::Regex>,
+    pub motifs: Vec<Motif>,
+    pub max_mismatches: usize,
+    pub both_strands: bool,
+    pub invert: bool,
+}
+
+// A record matches if the header matches the regex (if given) and the sequence contains
+// any of the motifs (if given). With invert, the result is negated.
+pub fn record_matches(rec: &jseqio::record::RefRecord, options: &GrepOptions) -> bool {
+    let header_ok = options.header_regex.as_ref().is_none_or(|re| re.is_match(rec.head));
+    let seq_ok = options.motifs.is_empty() || options.motifs.iter().any(|m| m.is_found(rec.seq, options.max_mismatches, options.both_strands));
+    (header_ok && seq_ok) != options.invert
+}
+
+// Writes the matching records to the output, if given. Returns the number of matching records.
+pub fn grep(reader: &mut impl jseqio::reader::SeqStream, mut output: Option<&mut impl jseqio::writer::SeqRecordWriter>, options: &GrepOptions) -> u64 {
+    let mut n_matches = 0_u64;
+    while let Some(rec) = reader.read_next().unwrap() {
+        if record_matches(&rec, options) {
+            n_matches += 1;
+            if let Some(out) = output.as_mut() {
+                out.write_ref_record(&rec).unwrap();
+            }
+        }
+    }
+    n_matches
+}
@@ -1,28 +1,150 @@
+use std::collections::BTreeMap;
 use std::io::Write;
 
+#[derive(Debug, Clone, PartialEq)]
+pub struct HistogramOptions {
+    pub min: Option<i64>, // Smaller values go to the first bin. The smallest value if None.
+    pub max: Option<i64>, // Larger values go to the last bin. The largest value if None.
+    pub n_bins: usize,
+    pub log_scale: bool, // Bin widths grow exponentially from max(min, 1) upwards, for long-tailed distributions
+    pub width: usize, // Number of characters in the longest bar
+    pub tsv: bool, // Print the bins as TSV instead of bars
+}
+
+impl Default for HistogramOptions {
+    fn default() -> Self {
+        HistogramOptions{min: None, max: None, n_bins: 20, log_scale: false, width: 40, tsv: false}
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HistogramBin {
+    pub start: i64,
+    pub end: i64, // Inclusive
+    pub count: u64,
+}
+
+// Start values of the bins, in increasing order. Linear bins have widths that differ by at
+// most one: the remainder of dividing the range by the bin count is spread over the first
+// bins. There are fewer than n_bins bins only if the range does not have enough integers.
+fn bin_starts(min: i64, max: i64, n_bins: usize, log_scale: bool) -> Vec<i64> {
+    let n_bins = std::cmp::max(n_bins, 1);
+    if log_scale {
+        let lo = std::cmp::max(min, 1);
+        let ratio = (std::cmp::max(max, lo) as f64 / lo as f64).powf(1.0 / n_bins as f64);
+        let mut starts: Vec<i64> = (0..n_bins).map(|i| (lo as f64 * ratio.powi(i as i32)).round() as i64).collect();
+        starts.dedup();
+        starts
+    } else {
+        let range = max - min + 1;
+        let n_bins = std::cmp::min(n_bins as i64, range);
+        let (bin_width, remainder) = (range / n_bins, range % n_bins);
+        (0..n_bins).map(|i| min + i * bin_width + std::cmp::min(i, remainder)).collect()
+    }
+}
+
+// Bins the values, given as the number of occurrences of each value. Values outside of the
+// range go to the first or the last bin, which are then extended to cover them. Returns no
+// bins if there are no values and the range is not given.
+pub fn histogram_bins(counts: &BTreeMap<i64, u64>, options: &HistogramOptions) -> Vec<HistogramBin> {
+    let (Some(min), Some(max)) = (
+        options.min.or(counts.keys().next().copied()),
+        options.max.or(counts.keys().next_back().copied()),
+    ) else {
+        return vec![];
+    };
+    let max = std::cmp::max(min, max);
+
+    let starts = bin_starts(min, max, options.n_bins, options.log_scale);
+    let mut bins: Vec<HistogramBin> = starts.iter().enumerate().map(|(i, &start)| {
+        let end = starts.get(i + 1).map_or(std::cmp::max(max, start), |next| next - 1);
+        HistogramBin{start, end, count: 0}
+    }).collect();
+    for (&x, &c) in counts.iter() {
+        let bin = &mut bins[starts.partition_point(|&s| s <= x).saturating_sub(1)];
+        bin.count += c;
+        bin.start = std::cmp::min(bin.start, x);
+        bin.end = std::cmp::max(bin.end, x);
+    }
+    bins
+}
+
+pub fn write_histogram(bins: &[HistogramBin], options: &HistogramOptions, output: &mut impl Write) -> std::io::Result<()> {
+    let total: u64 = bins.iter().map(|b| b.count).sum();
+    let percentage = |count: u64| if total == 0 { 0.0 } else { 100.0 * count as f64 / total as f64 };
+    if options.tsv {
+        writeln!(output, "start\tend\tcount\tpercentage")?;
+        for b in bins.iter() {
+            writeln!(output, "{}\t{}\t{}\t{:.2}", b.start, b.end, b.count, percentage(b.count))?;
+        }
+    } else {
+        let max_count = bins.iter().map(|b| b.count).max().unwrap_or(0);
+        for b in bins.iter() {
+            let n_chars = if max_count == 0 { 0 } else { (b.count as f64 / max_count as f64 * options.width as f64) as usize };
+            let label = if b.start == b.end { b.start.to_string() } else { format!("{}-{}", b.start, b.end) };
+            writeln!(output, "{}\t{}\t{:.1}%\t{}", label, b.count, percentage(b.count), "#".repeat(n_chars))?;
+        }
+    }
+    output.flush()
+}
+
+// Prints the histogram of values given with their number of occurrences to stdout
+pub fn print_weighted_histogram(values: impl Iterator<Item = (i64, u64)>, options: &HistogramOptions){
+    let mut counts = BTreeMap::<i64, u64>::new();
+    for (x, c) in values {
+        *counts.entry(x).or_insert(0) += c;
+    }
+    let bins = histogram_bins(&counts, options);
+    write_histogram(&bins, options, &mut std::io::BufWriter::new(std::io::stdout())).unwrap();
+}
+
 // Takes an iterator that produces i64 values, and prints the histogram
 // of those values to stdout.
-pub fn print_histogram(value_iterator: impl Iterator<Item = i64>, min: i64, max: i64, n_bins: i64){
-    let mut counters: Vec<i64> = vec![0; n_bins as usize];
-    let bin_width = (max-min+1) / n_bins;
-
-    for x in value_iterator{
-        let mut bin = (x - min) / bin_width;
+pub fn print_histogram(values: impl Iterator<Item = i64>, options: &HistogramOptions){
+    print_weighted_histogram(values.map(|x| (x, 1)), options);
+}
 
-        // Clamp to [0, n_bins-1]
-        bin = std::cmp::max(0, bin);
-        bin = std::cmp::min(n_bins-1, bin);
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-        counters[bin as usize] += 1;
+    fn counts(values: &[i64]) -> BTreeMap<i64, u64> {
+        let mut counts = BTreeMap::new();
+        for &x in values {
+            *counts.entry(x).or_insert(0) += 1;
+        }
+        counts
     }
 
-    let max_counter: i64 = *counters.iter().max().unwrap();
-    let n_columns: i64 = 40;
+    #[test]
+    fn test_histogram_bins(){
+        let bin = |start, end, count| HistogramBin{start, end, count};
+
+        // Automatic range
+        let options = HistogramOptions{n_bins: 3, ..Default::default()};
+        assert_eq!(histogram_bins(&counts(&[5, 6, 7, 7, 13]), &options), vec![bin(5, 7, 4), bin(8, 10, 0), bin(11, 13, 1)]);
+        assert_eq!(histogram_bins(&counts(&[]), &options), vec![]);
+
+        // More bins than values in the range, and values outside of the range
+        let options = HistogramOptions{min: Some(0), max: Some(2), n_bins: 10, ..Default::default()};
+        assert_eq!(histogram_bins(&counts(&[-3, 1, 5]), &options), vec![bin(-3, 0, 1), bin(1, 1, 1), bin(2, 5, 1)]);
+
+        // A range that is not divisible by the number of bins gives the first bins one more value
+        let options = HistogramOptions{min: Some(0), max: Some(29), n_bins: 20, ..Default::default()};
+        let bins = histogram_bins(&counts(&[0, 29]), &options);
+        assert_eq!(bins.len(), 20);
+        assert!(bins[..10].iter().all(|b| b.end - b.start == 1));
+        assert!(bins[10..].iter().all(|b| b.end == b.start));
+        assert_eq!((bins[9], bins[10], bins[19]), (bin(18, 19, 0), bin(20, 20, 0), bin(29, 29, 1)));
+
+        // Log scale
+        let options = HistogramOptions{min: Some(1), max: Some(1000), n_bins: 3, log_scale: true, ..Default::default()};
+        assert_eq!(histogram_bins(&counts(&[0, 50, 100, 999]), &options), vec![bin(0, 9, 1), bin(10, 99, 1), bin(100, 1000, 2)]);
 
-    for (i, c) in counters.iter().enumerate(){
-        let n_chars = ((*c as f64 / max_counter as f64) * n_columns as f64) as i64;
-        print!("{}\t", (min + (i as i64)*bin_width) as usize);
-        std::io::stdout().write_all(vec![b'#'; n_chars as usize].as_slice()).ok();
-        println!();
+        // All counts zero
+        let options = HistogramOptions{min: Some(0), max: Some(9), n_bins: 2, ..Default::default()};
+        let mut output = vec![];
+        write_histogram(&histogram_bins(&counts(&[]), &options), &options, &mut output).unwrap();
+        assert_eq!(String::from_utf8(output).unwrap(), "0-4\t0\t0.0%\t\n5-9\t0\t0.0%\t\n");
     }
 }
@@ -3,6 +3,7 @@ use jseqio::reader::*;
 use jseqio::writer::*;
 use std::env;
 
+// Superseded by `seqtools interleave`, which also checks that the mate names match.
 fn main(){
     let args: Vec<String> = env::args().collect();
     let seqs1 = args[1].clone();
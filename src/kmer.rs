@@ -0,0 +1,186 @@
+// Packed k-mers with two bits per nucleotide: A = 0, C = 1, G = 2, T = 3. The first
+// nucleotide is in the most significant position, so comparing packed k-mers gives the
+// same order as comparing the strings. A u64 holds k-mers up to k = 32 and a u128 up to k = 64.
+
+use std::ops::{BitAnd, BitOr, Not, Shl, Shr};
+
+pub trait PackedKmer:
+    Copy + Eq + Ord + std::hash::Hash + std::fmt::Debug + Send + Sync + 'static
+    + From<u8> + Shl<usize, Output = Self> + Shr<usize, Output = Self>
+    + BitAnd<Output = Self> + BitOr<Output = Self> + Not<Output = Self>
+{
+    const MAX_K: usize;
+
+    // Mask with the lowest 2k bits set
+    fn mask(k: usize) -> Self {
+        !Self::from(0) >> (2 * (Self::MAX_K - k))
+    }
+
+    // Low 64 bits xor high 64 bits, for hashing
+    fn fold_to_u64(self) -> u64;
+
+    fn to_u128(self) -> u128;
+
+    fn encode(seq: &[u8]) -> Option<Self> {
+        let mut x = Self::from(0);
+        for &c in seq {
+            x = (x << 2) | Self::from(encode_base(c)?);
+        }
+        Some(x)
+    }
+
+    fn decode(self, k: usize) -> Vec<u8> {
+        (0..k).map(|i| {
+            let code = (self >> (2 * (k - 1 - i))) & Self::from(3);
+            b"ACGT"[code.fold_to_u64() as usize]
+        }).collect()
+    }
+}
+
+impl PackedKmer for u64 {
+    const MAX_K: usize = 32;
+    fn fold_to_u64(self) -> u64 { self }
+    fn to_u128(self) -> u128 { self as u128 }
+}
+
+impl PackedKmer for u128 {
+    const MAX_K: usize = 64;
+    fn fold_to_u64(self) -> u64 { (self as u64) ^ ((self >> 64) as u64) }
+    fn to_u128(self) -> u128 { self }
+}
+
+pub fn encode_base(c: u8) -> Option<u8> {
+    match c {
+        b'A' | b'a' => Some(0),
+        b'C' | b'c' => Some(1),
+        b'G' | b'g' => Some(2),
+        b'T' | b't' => Some(3),
+        _ => None,
+    }
+}
+
+// The finalizer of MurmurHash3: a bijection on 64-bit integers that mixes all input bits
+// into all output bits.
+pub fn mix64(mut x: u64) -> u64 {
+    x ^= x >> 33;
+    x = x.wrapping_mul(0xff51afd7ed558ccd);
+    x ^= x >> 33;
+    x = x.wrapping_mul(0xc4ceb9fe1a85ec53);
+    x ^= x >> 33;
+    x
+}
+
+// Hash of a packed k-mer, used for sketching. The same k-mer gets the same hash
+// regardless of the integer type it is packed into.
+pub fn hash_kmer<T: PackedKmer>(x: T) -> u64 {
+    mix64(x.fold_to_u64())
+}
+
+// Orders of k-mers for choosing minimizers
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KmerOrder {
+    Lexicographic,
+    Hash, // Order by hash_kmer, which avoids favoring low-complexity k-mers like AAA...A
+}
+
+impl KmerOrder {
+    // Key such that smaller keys come first in the order
+    pub fn key<T: PackedKmer>(&self, x: T) -> u128 {
+        match self {
+            KmerOrder::Lexicographic => x.to_u128(),
+            KmerOrder::Hash => hash_kmer(x) as u128,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KmerOccurrence<T> {
+    pub pos: usize, // Start position in the sequence
+    pub forward: T,
+    pub reverse_complement: T,
+}
+
+impl<T: PackedKmer> KmerOccurrence<T> {
+    // The smaller of the two orientations
+    pub fn canonical(&self) -> T {
+        std::cmp::min(self.forward, self.reverse_complement)
+    }
+}
+
+// Iterates over the k-mers of a sequence, updating the forward and reverse complement
+// k-mers in constant time per position. k-mers that contain characters other than ACGT are skipped.
+pub struct KmerIter<'a, T> {
+    seq: &'a [u8],
+    k: usize,
+    next_pos: usize, // Next position to push into the k-mer
+    valid_len: usize, // Number of consecutive ACGT characters ending at next_pos-1
+    forward: T,
+    reverse_complement: T,
+    mask: T,
+}
+
+impl<'a, T: PackedKmer> KmerIter<'a, T> {
+    pub fn new(seq: &'a [u8], k: usize) -> Self {
+        assert!(k > 0 && k <= T::MAX_K, "k must be between 1 and {}", T::MAX_K);
+        KmerIter{seq, k, next_pos: 0, valid_len: 0, forward: T::from(0), reverse_complement: T::from(0), mask: T::mask(k)}
+    }
+}
+
+impl<T: PackedKmer> Iterator for KmerIter<'_, T> {
+    type Item = KmerOccurrence<T>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.next_pos < self.seq.len() {
+            let c = self.seq[self.next_pos];
+            self.next_pos += 1;
+            match encode_base(c) {
+                Some(b) => {
+                    self.forward = ((self.forward << 2) | T::from(b)) & self.mask;
+                    self.reverse_complement = (self.reverse_complement >> 2) | (T::from(3 - b) << (2 * (self.k - 1)));
+                    self.valid_len += 1;
+                    if self.valid_len >= self.k {
+                        return Some(KmerOccurrence{pos: self.next_pos - self.k, forward: self.forward, reverse_complement: self.reverse_complement});
+                    }
+                }
+                None => self.valid_len = 0, // Start over after the non-ACGT character
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn check_against_naive<T: PackedKmer>(seq: &[u8], k: usize) {
+        let occurrences: Vec<KmerOccurrence<T>> = KmerIter::new(seq, k).collect();
+        let mut expected = vec![];
+        for i in 0..(seq.len() + 1).saturating_sub(k) {
+            let kmer = &seq[i..i+k];
+            if let Some(fw) = T::encode(kmer) {
+                let rc = T::encode(&jseqio::reverse_complement(kmer)).unwrap();
+                expected.push(KmerOccurrence{pos: i, forward: fw, reverse_complement: rc});
+            }
+        }
+        assert_eq!(occurrences, expected);
+        for occ in occurrences.iter() {
+            assert_eq!(occ.forward.decode(k), &seq[occ.pos..occ.pos+k]);
+        }
+    }
+
+    #[test]
+    fn test_kmer_iter(){
+        let seq = b"ACGTTGCANNACGGTACCATGACGATCGATCGGCTAGCTAGCTAGGATCGATTTACGATCGATCGANCGATCGACTAGCTAGCTCGATCGATCGATCGATCGACGA";
+        for k in [1, 3, 31, 32] {
+            check_against_naive::<u64>(seq, k);
+        }
+        for k in [1, 5, 33, 63, 64] {
+            check_against_naive::<u128>(seq, k);
+        }
+
+        // Order of packed k-mers is the same as the order of strings
+        assert!(u64::encode(b"ACGT").unwrap() < u64::encode(b"AGAA").unwrap());
+        assert_eq!(KmerIter::<u64>::new(b"GGG", 3).next().unwrap().canonical(), u64::encode(b"CCC").unwrap());
+    }
+}
@@ -1,46 +1,32 @@
-
 use jseqio::reader::*;
-
-use std::collections::HashSet;
-
-use std::cmp::max;
+use seq_tools::kmers::{hash_kmers, SetComparison};
 
 use std::env;
 
-fn hash_kmers(filename: &String, k: usize) -> HashSet<Vec<u8>>{
-    let mut reader = DynamicFastXReader::from_file(&filename).unwrap();
-    let mut kmers: HashSet<Vec<u8>> = HashSet::new();
-    while let Some(rec) = reader.read_next().unwrap(){
-        let seq = rec.seq;
-        let n = seq.len() as i64;
-        let k = k as i64;
-        let m = max(0, n-k+1) as usize; // Number of kmers in the sequence
-        for i in 0..m{
-            let kmer = seq[i..i + k as usize].to_owned();
-            let rc_kmer = jseqio::reverse_complement(&kmer);
-            kmers.insert(std::cmp::min(kmer, rc_kmer)); // Add the canonical orientation
-        }
-    }
-    kmers
-}
-
+// Superseded by `seqtools kmer-compare`, which takes any number of files.
 #[allow(non_snake_case)]
 fn main(){
     let args: Vec<String> = env::args().collect();
+    if args.len() != 4 {
+        eprintln!("Usage: {} <file1> <file2> <k>", args[0]);
+        std::process::exit(1);
+    }
     let seqs1 = args[1].clone();
     let seqs2 = args[2].clone();
     let k: usize = args[3].parse().unwrap();
 
-    let K1 = hash_kmers(&seqs1, k);
-    let K2 = hash_kmers(&seqs2, k);
+    if k == 0 || k > 64 {
+        panic!("k must be between 1 and 64");
+    }
+    let K1 = hash_kmers::<u128>(&mut DynamicFastXReader::from_file(&seqs1).unwrap(), k);
+    let K2 = hash_kmers::<u128>(&mut DynamicFastXReader::from_file(&seqs2).unwrap(), k);
 
-    let intersection_size = K1.intersection(&K2).count();
-    let union_size = K1.union(&K2).count();
+    let comparison = SetComparison::new(&K1, &K2);
 
     eprintln!("File 1 has {} distinct {}-mers", K1.len(), k);
     eprintln!("File 2 has {} distinct {}-mers", K2.len(), k);
-    eprintln!("The intersection has {} distinct {}-mers", intersection_size, k);
-    eprintln!("The union has {} distinct {}-mers", union_size, k);
-    eprintln!("Jaccard index: {}", intersection_size as f64 / union_size as f64);
+    eprintln!("The intersection has {} distinct {}-mers", comparison.intersection, k);
+    eprintln!("The union has {} distinct {}-mers", comparison.union, k);
+    eprintln!("Jaccard index: {}", comparison.jaccard());
 
-}
\ No newline at end of file
+}
@@ -0,0 +1,220 @@
+// k-mer sets and comparisons between them.
+
+use std::collections::{HashMap, HashSet};
+use jseqio::reader::DynamicFastXReader;
+use crate::kmer::{KmerIter, PackedKmer};
+
+// Returns the set of distinct canonical k-mers in the input. k-mers with
+// characters other than ACGT are skipped. Requires k <= T::MAX_K.
+pub fn hash_kmers<T: PackedKmer>(reader: &mut DynamicFastXReader, k: usize) -> HashSet<T>{
+    let mut kmers: HashSet<T> = HashSet::new();
+    while let Some(rec) = reader.read_next().unwrap(){
+        for occ in KmerIter::<T>::new(rec.seq, k){
+            kmers.insert(occ.canonical());
+        }
+    }
+    kmers
+}
+
+// Counts the canonical k-mers in the input. k-mers with characters other than ACGT are
+// skipped. Requires k <= T::MAX_K.
+pub fn count_kmers<T: PackedKmer>(reader: &mut DynamicFastXReader, k: usize) -> HashMap<T, u32>{
+    let mut counts: HashMap<T, u32> = HashMap::new();
+    while let Some(rec) = reader.read_next().unwrap(){
+        for occ in KmerIter::<T>::new(rec.seq, k){
+            let c = counts.entry(occ.canonical()).or_insert(0);
+            *c = c.saturating_add(1);
+        }
+    }
+    counts
+}
+
+// The k-mer spectrum: element c is the number of distinct k-mers that occur c times.
+pub fn kmer_spectrum<T>(counts: &HashMap<T, u32>) -> Vec<u64>{
+    let max_count = counts.values().copied().max().unwrap_or(0) as usize;
+    let mut spectrum = vec![0_u64; max_count + 1];
+    for &c in counts.values(){
+        spectrum[c as usize] += 1;
+    }
+    spectrum
+}
+
+// Prints the spectrum as TSV: count and the number of distinct k-mers with that count,
+// leaving out counts that no k-mer has.
+pub fn write_spectrum_tsv(spectrum: &[u64], output: &mut impl std::io::Write){
+    for (c, &n) in spectrum.iter().enumerate().skip(1).filter(|(_, &n)| n > 0) {
+        writeln!(output, "{}\t{}", c, n).unwrap();
+    }
+    output.flush().unwrap();
+}
+
+// Prints the spectrum using the histogram printer, counting distinct k-mers in each bin
+pub fn print_spectrum_histogram(spectrum: &[u64], options: &crate::histogram::HistogramOptions){
+    let values = spectrum.iter().enumerate().skip(1).map(|(c, &n)| (c as i64, n));
+    crate::histogram::print_weighted_histogram(values, options);
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KmerDumpFormat {
+    Tsv, // k-mer and count on each line
+    Fasta, // The count as the header and the k-mer as the sequence, like jellyfish dump
+}
+
+// Writes the k-mers with count at least min_count in sorted order.
+pub fn write_kmer_counts<T: PackedKmer>(counts: &HashMap<T, u32>, k: usize, min_count: u32, format: KmerDumpFormat, output: &mut impl std::io::Write){
+    let mut kmers: Vec<(T, u32)> = counts.iter().filter(|(_, &c)| c >= min_count).map(|(&x, &c)| (x, c)).collect();
+    kmers.sort_unstable();
+    for (x, c) in kmers {
+        let kmer = x.decode(k);
+        match format {
+            KmerDumpFormat::Tsv => writeln!(output, "{}\t{}", String::from_utf8_lossy(&kmer), c).unwrap(),
+            KmerDumpFormat::Fasta => writeln!(output, ">{}\n{}", c, String::from_utf8_lossy(&kmer)).unwrap(),
+        }
+    }
+    output.flush().unwrap();
+}
+
+// Counts the canonical k-mers of the input and writes the ones with count at least
+// min_count to kmers_out, if given. Returns the k-mer spectrum. Supports k up to 64.
+pub fn kmer_count(reader: &mut DynamicFastXReader, k: usize, min_count: u32, kmers_out: Option<(&mut dyn std::io::Write, KmerDumpFormat)>) -> Vec<u64>{
+    if k <= u64::MAX_K {
+        kmer_count_packed::<u64>(reader, k, min_count, kmers_out)
+    } else if k <= u128::MAX_K {
+        kmer_count_packed::<u128>(reader, k, min_count, kmers_out)
+    } else {
+        panic!("Error: k can be at most {}", u128::MAX_K);
+    }
+}
+
+fn kmer_count_packed<T: PackedKmer>(reader: &mut DynamicFastXReader, k: usize, min_count: u32, kmers_out: Option<(&mut dyn std::io::Write, KmerDumpFormat)>) -> Vec<u64>{
+    let counts = count_kmers::<T>(reader, k);
+    eprintln!("{} distinct {}-mers", counts.len(), k);
+    if let Some((mut output, format)) = kmers_out {
+        write_kmer_counts(&counts, k, min_count, format, &mut output);
+    }
+    kmer_spectrum(&counts)
+}
+
+pub struct KmerFilterOptions {
+    pub k: usize,
+    pub min_shared: usize, // A read matches if it has at least this many k-mers in the reference...
+    pub min_fraction: f64, // ...and at least this fraction of its k-mers are in the reference
+    pub keep_matching: bool, // Keep the matching reads instead of removing them
+    pub paired_interleaved: bool, // A pair matches if either of the mates matches
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct KmerFilterReport {
+    pub n_reads: u64,
+    pub n_matching: u64,
+    pub n_removed: u64,
+}
+
+fn matches_reference<T: PackedKmer>(seq: &[u8], reference: &HashSet<T>, options: &KmerFilterOptions) -> bool {
+    let (mut n_kmers, mut n_shared) = (0_usize, 0_usize);
+    for occ in KmerIter::<T>::new(seq, options.k) {
+        n_kmers += 1;
+        if reference.contains(&occ.canonical()) {
+            n_shared += 1;
+        }
+    }
+    n_kmers > 0 && n_shared >= options.min_shared && n_shared as f64 >= options.min_fraction * n_kmers as f64
+}
+
+// Removes the reads that share k-mers with the reference, or keeps only them if
+// options.keep_matching is set. Supports k up to 64.
+pub fn kmer_filter(reference: &mut DynamicFastXReader, input: &mut DynamicFastXReader, output: &mut impl jseqio::writer::SeqRecordWriter, options: &KmerFilterOptions) -> Result<KmerFilterReport, Box<dyn std::error::Error>> {
+    if options.k <= u64::MAX_K {
+        kmer_filter_packed::<u64>(reference, input, output, options)
+    } else if options.k <= u128::MAX_K {
+        kmer_filter_packed::<u128>(reference, input, output, options)
+    } else {
+        panic!("Error: k can be at most {}", u128::MAX_K);
+    }
+}
+
+fn kmer_filter_packed<T: PackedKmer>(reference: &mut DynamicFastXReader, input: &mut DynamicFastXReader, output: &mut impl jseqio::writer::SeqRecordWriter, options: &KmerFilterOptions) -> Result<KmerFilterReport, Box<dyn std::error::Error>> {
+    let reference_kmers = hash_kmers::<T>(reference, options.k);
+    eprintln!("Loaded {} distinct {}-mers from the reference", reference_kmers.len(), options.k);
+
+    let mut report = KmerFilterReport::default();
+    while let Some(group) = crate::paired::read_group(input, options.paired_interleaved)? {
+        let is_match = group.iter().any(|rec| matches_reference(&rec.seq, &reference_kmers, options));
+        report.n_reads += group.len() as u64;
+        if is_match {
+            report.n_matching += group.len() as u64;
+        }
+        if is_match == options.keep_matching {
+            for rec in group.iter() {
+                output.write_owned_record(rec)?;
+            }
+        } else {
+            report.n_removed += group.len() as u64;
+        }
+    }
+    output.flush()?;
+    Ok(report)
+}
+
+pub struct SetComparison {
+    pub size1: usize,
+    pub size2: usize,
+    pub intersection: usize,
+    pub union: usize,
+}
+
+impl SetComparison {
+    pub fn new<T: std::hash::Hash + Eq>(set1: &HashSet<T>, set2: &HashSet<T>) -> Self {
+        // Iterate over the smaller set
+        let (small, large) = if set1.len() <= set2.len() { (set1, set2) } else { (set2, set1) };
+        let intersection = small.iter().filter(|x| large.contains(x)).count();
+        SetComparison{size1: set1.len(), size2: set2.len(), intersection, union: set1.len() + set2.len() - intersection}
+    }
+
+    pub fn jaccard(&self) -> f64 {
+        if self.union == 0 { return 0.0 }
+        self.intersection as f64 / self.union as f64
+    }
+
+    // Fraction of the first set contained in the second
+    pub fn containment_1_in_2(&self) -> f64 {
+        if self.size1 == 0 { return 0.0 }
+        self.intersection as f64 / self.size1 as f64
+    }
+
+    // Fraction of the second set contained in the first
+    pub fn containment_2_in_1(&self) -> f64 {
+        if self.size2 == 0 { return 0.0 }
+        self.intersection as f64 / self.size2 as f64
+    }
+}
+
+// Computes the k-mer sets of the files in parallel and prints all pairwise comparisons
+// as TSV, one pair per line. Supports k up to 64.
+pub fn kmer_compare(files: &[String], k: usize, n_threads: usize, output: &mut impl std::io::Write){
+    if k <= u64::MAX_K {
+        kmer_compare_packed::<u64>(files, k, n_threads, output);
+    } else if k <= u128::MAX_K {
+        kmer_compare_packed::<u128>(files, k, n_threads, output);
+    } else {
+        panic!("Error: k can be at most {}", u128::MAX_K);
+    }
+}
+
+fn kmer_compare_packed<T: PackedKmer>(files: &[String], k: usize, n_threads: usize, output: &mut impl std::io::Write){
+    let sets = crate::parallel_map(files, n_threads, |filename| {
+        let mut reader = DynamicFastXReader::from_file(filename).unwrap();
+        let set = hash_kmers::<T>(&mut reader, k);
+        eprintln!("{} has {} distinct {}-mers", filename, set.len(), k);
+        set
+    });
+
+    let pairs: Vec<(usize, usize)> = (0..files.len()).flat_map(|i| (i+1..files.len()).map(move |j| (i, j))).collect();
+    let comparisons = crate::parallel_map(&pairs, n_threads, |&(i, j)| SetComparison::new(&sets[i], &sets[j]));
+
+    writeln!(output, "file1\tfile2\tkmers1\tkmers2\tintersection\tunion\tjaccard\tcontainment1in2\tcontainment2in1").unwrap();
+    for (&(i, j), c) in pairs.iter().zip(comparisons.iter()) {
+        writeln!(output, "{}\t{}\t{}\t{}\t{}\t{}\t{:.6}\t{:.6}\t{:.6}", files[i], files[j], c.size1, c.size2, c.intersection, c.union, c.jaccard(), c.containment_1_in_2(), c.containment_2_in_1()).unwrap();
+    }
+    output.flush().unwrap();
+}
@@ -1,7 +1,28 @@
 use jseqio::{reader::*, record::*, writer::*, reverse_complement_in_place};
 
-mod histogram;
+pub mod histogram;
 pub mod trim_adapters;
+pub mod bgzf;
+pub mod fasta_index;
+pub mod read_index;
+pub mod motif;
+pub mod grep;
+pub mod locate;
+pub mod kmer;
+pub mod kmers;
+pub mod sketch;
+pub mod minimizers;
+pub mod paired;
+pub mod merge_pairs;
+pub mod split;
+pub mod prefixes;
+pub mod sam;
+pub mod coverage;
+pub mod gc;
+pub mod complexity;
+pub mod filter;
+pub mod sort;
+pub mod shuffle;
 
 use rand_chacha::rand_core::SeedableRng;
 use std::cmp::{max,min};
@@ -34,34 +55,87 @@ pub fn extract_region(mut reader: DynamicFastXReader, mut writer: DynamicFastXWr
     writer.write_ref_record(&region_rec).unwrap();
 }
 
-pub fn extract_reads_by_names(reader: DynamicFastXReader, names: &Vec<String>){
+// Extracts regions given in samtools format (e.g. chr7:100000-100500) and the regions in
+// the BED file, if given, using a .fai index. The index is built if it does not exist yet.
+pub fn extract_indexed_regions(infile: &str, region_strings: &[String], bed_file: Option<&str>, writer: &mut DynamicFastXWriter){
+    let mut indexed = fasta_index::IndexedFastX::open(infile).unwrap();
+    let mut regions: Vec<fasta_index::Region> = region_strings.iter().map(|r| indexed.index.parse_region(r).unwrap()).collect();
+    if let Some(bed_file) = bed_file {
+        let bed_reader = std::io::BufReader::new(std::fs::File::open(bed_file).unwrap());
+        for line in std::io::BufRead::lines(bed_reader) {
+            if let Some(region) = fasta_index::Region::from_bed_line(&line.unwrap()).unwrap() {
+                regions.push(region);
+            }
+        }
+    }
+
+    for region in regions.iter() {
+        let rec = indexed.fetch(region).unwrap();
+        writer.write_owned_record(&rec).unwrap();
+    }
+}
+
+// Removes a trailing /1 or /2 mate suffix from a read name, if present.
+pub fn strip_mate_suffix(name: &[u8]) -> &[u8]{
+    match name {
+        [rest @ .., b'/', b'1'] | [rest @ .., b'/', b'2'] => rest,
+        _ => name,
+    }
+}
+
+// If strip_suffixes is true, names are compared with /1 and /2 suffixes removed.
+pub fn extract_reads_by_names(reader: DynamicFastXReader, names: &Vec<String>, strip_suffixes: bool){
     let filetype = reader.filetype();
 
     let db = reader.into_db().unwrap();
 
     // There may be multiple records with the same name, like in interleaved paired-end fastq data
 
+    let key = |name: &[u8]| if strip_suffixes { strip_mate_suffix(name).to_owned() } else { name.to_owned() };
     let mut name_to_seqs = std::collections::BTreeMap::<Vec<u8>, Vec<RefRecord>>::new();
     for name in names{
-        name_to_seqs.insert(name.as_bytes().to_owned(), vec![]);
+        name_to_seqs.insert(key(name.as_bytes()), vec![]);
     }
     
     for rec in db.iter(){
-        if name_to_seqs.contains_key(rec.name()){
-            name_to_seqs.get_mut(rec.name()).unwrap().push(rec);
+        if let Some(recs) = name_to_seqs.get_mut(&key(rec.name())){
+            recs.push(rec);
         }
     }
 
-    // Print in order to stdout
+    // Print in order to stdout. A key is printed only at its first occurrence in the list, so
+    // that listing both X/1 and X/2, or the same name twice, does not print the reads twice.
     let mut writer = jseqio::writer::DynamicFastXWriter::new_to_stdout(filetype, jseqio::CompressionType::None);
     for name in names {
-        for rec in name_to_seqs.get(name.as_bytes()).unwrap(){
-            writer.write(rec).unwrap();
+        for rec in name_to_seqs.remove(&key(name.as_bytes())).unwrap_or_default(){
+            writer.write(&rec).unwrap();
         }
     }
 
 }
 
+// Streams the reads and prints those whose name is in the list, in file order. Only the
+// names are held in memory. If invert is true, prints the reads whose name is not in the list.
+// If strip_suffixes is true, names are compared with /1 and /2 suffixes removed.
+pub fn extract_reads_by_names_streaming(reader: &mut DynamicFastXReader, names: &[String], invert: bool, strip_suffixes: bool){
+    let name_set: std::collections::HashSet<&[u8]> = names.iter().map(|name| {
+        if strip_suffixes { strip_mate_suffix(name.as_bytes()) } else { name.as_bytes() }
+    }).collect();
+
+    let mut writer = jseqio::writer::DynamicFastXWriter::new_to_stdout(reader.filetype(), jseqio::CompressionType::None);
+    let mut n_written = 0_u64;
+    let mut n_total = 0_u64;
+    while let Some(rec) = reader.read_next().unwrap() {
+        n_total += 1;
+        let name = if strip_suffixes { strip_mate_suffix(rec.name()) } else { rec.name() };
+        if name_set.contains(name) != invert {
+            writer.write(&rec).unwrap();
+            n_written += 1;
+        }
+    }
+    eprintln!("Wrote {} out of {} reads", n_written, n_total);
+}
+
 pub fn extract_reads_by_ranks(mut reader: DynamicFastXReader, ranks: &Vec<usize>){
     let filetype = reader.filetype();
 
@@ -97,6 +171,29 @@ pub fn extract_reads_by_ranks(mut reader: DynamicFastXReader, ranks: &Vec<usize>
 
 }
 
+// Like extract_reads_by_names, but looks up the reads using an on-disk index
+// next to the input file. The index is built if it does not exist.
+pub fn extract_reads_by_names_indexed(infile: &str, names: &Vec<String>){
+    let mut indexed = read_index::IndexedReads::open(infile).unwrap();
+    let mut writer = jseqio::writer::DynamicFastXWriter::new_to_stdout(indexed.index.filetype, jseqio::CompressionType::None);
+    for name in names {
+        for rec in indexed.get_by_name(name.as_bytes()).unwrap() {
+            writer.write(&rec).unwrap();
+        }
+    }
+}
+
+// Like extract_reads_by_ranks, but seeks directly to the reads using an on-disk index
+// next to the input file. The index is built if it does not exist.
+pub fn extract_reads_by_ranks_indexed(infile: &str, ranks: &Vec<usize>){
+    let mut indexed = read_index::IndexedReads::open(infile).unwrap();
+    let mut writer = jseqio::writer::DynamicFastXWriter::new_to_stdout(indexed.index.filetype, jseqio::CompressionType::None);
+    for rank in ranks {
+        let rec = indexed.get_by_rank(*rank as u64).unwrap().unwrap_or_else(|| panic!("Error: did not find read with rank {}", rank));
+        writer.write(&rec).unwrap();
+    }
+}
+
 pub fn print_lengths(reader: &mut DynamicFastXReader){
     while let Some(rec) = reader.read_next().unwrap() {
         println!("{}", rec.seq.len());
@@ -104,22 +201,22 @@ pub fn print_lengths(reader: &mut DynamicFastXReader){
 }
 
 pub fn gc_content(reader: &mut DynamicFastXReader){
-    let mut n_GC = 0_usize;
-    let mut n_AT = 0_usize;
+    let mut n_gc = 0_usize;
+    let mut n_at = 0_usize;
     let mut n_other = 0_usize;
     while let Some(rec) = reader.read_next().unwrap() {
         for c in rec.seq {
             match c.to_ascii_uppercase() {
-                b'G' => n_GC += 1, 
-                b'C' => n_GC += 1, 
-                b'A' => n_AT += 1,
-                b'T' => n_AT += 1,
+                b'G' => n_gc += 1, 
+                b'C' => n_gc += 1, 
+                b'A' => n_at += 1,
+                b'T' => n_at += 1,
                 _ => n_other += 1
             }
         }
     }
-    println!("{} nucleotides ignored ({}%)", n_other, n_other as f64 / (n_other + n_GC + n_AT) as f64 * 100.0); 
-    println!("GC content: {:.2}%", n_GC as f64 / (n_GC + n_AT) as f64 * 100.0);
+    println!("{} nucleotides ignored ({}%)", n_other, n_other as f64 / (n_other + n_gc + n_at) as f64 * 100.0); 
+    println!("GC content: {:.2}%", n_gc as f64 / (n_gc + n_at) as f64 * 100.0);
 
 }
 
@@ -179,9 +276,9 @@ pub fn remove_duplicates(reader: &mut DynamicFastXReader, writer: &mut DynamicFa
 }
 
 
-pub fn print_length_histogram(reader: &mut DynamicFastXReader, min: i64, max: i64, n_bins: i64){
+pub fn print_length_histogram(reader: &mut DynamicFastXReader, options: &histogram::HistogramOptions){
     let it = LengthIterator{reader};
-    histogram::print_histogram(it, min, max, n_bins);
+    histogram::print_histogram(it, options);
 }
 
 pub fn count_sequences(mut input: DynamicFastXReader) -> u64{
@@ -192,10 +289,27 @@ pub fn count_sequences(mut input: DynamicFastXReader) -> u64{
     count
 }
 
-// Returns a random permutation of [0..n_elements)
-fn get_random_permutation(n_elements: usize, seed_option: Option<u64>) -> Vec<usize> {
+// Applies f to all items using n_threads threads. The results are in the same order as the items.
+pub fn parallel_map<T: Sync, R: Send>(items: &[T], n_threads: usize, f: impl Fn(&T) -> R + Sync) -> Vec<R> {
+    let next_item = std::sync::atomic::AtomicUsize::new(0);
+    let results: std::sync::Mutex<Vec<Option<R>>> = std::sync::Mutex::new(items.iter().map(|_| None).collect());
+    std::thread::scope(|scope| {
+        for _ in 0..max(1, min(n_threads, items.len())) {
+            scope.spawn(|| loop {
+                let i = next_item.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+                if i >= items.len() { break }
+                let r = f(&items[i]);
+                results.lock().unwrap()[i] = Some(r);
+            });
+        }
+    });
+    results.into_inner().unwrap().into_iter().map(|r| r.unwrap()).collect()
+}
 
-    // Generate random seed from current time if not given
+// Returns the ChaCha20 random number generator used by all randomized operations, so that
+// the same seed gives the same results. If no seed is given, a seed is generated from the
+// current time.
+pub(crate) fn seeded_rng(seed_option: Option<u64>) -> rand_chacha::ChaCha20Rng {
     let seed = match seed_option{
         Some(s) => s,
         None => {
@@ -208,12 +322,16 @@ fn get_random_permutation(n_elements: usize, seed_option: Option<u64>) -> Vec<us
 
     // Put the seed into a 32-byte array
     let mut seed_array: [u8; 32] = [0; 32];
-    for i in 0..8{
-        seed_array[i] = ((seed >> (i * 8)) & 0xFF) as u8;
+    for (i, byte) in seed_array.iter_mut().take(8).enumerate(){
+        *byte = ((seed >> (i * 8)) & 0xFF) as u8;
     }
 
-    // Create a random number generator with the fixed seed
-    let mut rng = rand_chacha::ChaCha20Rng::from_seed(seed_array);
+    rand_chacha::ChaCha20Rng::from_seed(seed_array)
+}
+
+// Returns a random permutation of [0..n_elements)
+pub(crate) fn get_random_permutation(n_elements: usize, seed_option: Option<u64>) -> Vec<usize> {
+    let mut rng = seeded_rng(seed_option);
 
     eprintln!("Generating random numbers...");
     let mut v: Vec<(u64, usize)> = vec![]; // Pairs (random u64, index)
@@ -237,7 +355,7 @@ pub fn random_subsample(input1: DynamicFastXReader, input2: DynamicFastXReader,
     let n_seqs = count_sequences(input1) as usize; // Consumes the input
 
     let mut subsample_seqs: usize = (n_seqs as f64 * fraction) as usize;
-    if paired_interleaved && subsample_seqs % 2 > 0{
+    if paired_interleaved && !subsample_seqs.is_multiple_of(2){
         subsample_seqs -= 1; // Get to an even number
     }
 
@@ -255,10 +373,10 @@ pub fn get_subsample_keep_marks(n_seqs: usize, subsample_seqs: usize, seed: Opti
 }
 
 pub fn get_subsample_pair_keep_marks(n_seqs: usize, subsample_seqs: usize, seed: Option<u64>) -> Vec<u8>{
-    if subsample_seqs % 2 != 0{
+    if !subsample_seqs.is_multiple_of(2){
         panic!("Error: the number of sequences to subsample must be even when subsampling paired-end interleaved data");
     }
-    if n_seqs % 2 != 0{
+    if !n_seqs.is_multiple_of(2){
         panic!("Error: paired-end interleaved data has an odd number of sequences");
     }
 
@@ -278,7 +396,7 @@ pub fn get_subsample_pair_keep_marks(n_seqs: usize, subsample_seqs: usize, seed:
 
 // Seed is the random seed. If not given, a seed is generated from the current time.
 pub fn random_subsample_howmany(mut input: DynamicFastXReader, out: &mut DynamicFastXWriter, total_seqs: usize, subsample_seqs: usize, seed: Option<u64>, paired_interleaved: bool){
-    if paired_interleaved && total_seqs % 2 != 0{
+    if paired_interleaved && !total_seqs.is_multiple_of(2){
         panic!("Error: the number of sequences must be even when subsampling paired-end interleaved data");
     }
 
@@ -301,7 +419,7 @@ pub fn random_subsample_howmany(mut input: DynamicFastXReader, out: &mut Dynamic
 pub fn convert(input: &mut DynamicFastXReader, output: &mut DynamicFastXWriter){
     let mut dummy_qual_values: Vec<u8> = vec![]; // A buffer for dummy quality values for fasta -> fastq conversion 
     while let Some(mut rec) = input.read_next().unwrap(){
-        if matches!(rec.qual, None){
+        if rec.qual.is_none(){
             // Potentially doing Fasta to Fastq conversion.
             // Put dummy quality values to rec.qual.
             while dummy_qual_values.len() < rec.seq.len(){
@@ -319,7 +437,7 @@ pub fn convert(input: &mut DynamicFastXReader, output: &mut DynamicFastXWriter){
 pub fn reverse_complement(input: &mut DynamicFastXReader, output: &mut DynamicFastXWriter){
     let mut dummy_qual_values: Vec<u8> = vec![]; // A buffer for dummy quality values for fasta -> fastq conversion 
     while let Some(mut rec) = input.read_next_mut().unwrap(){
-        if matches!(rec.qual, None){
+        if rec.qual.is_none(){
             // Potentially doing Fasta to Fastq conversion.
             // Put dummy quality values to rec.qual.
             while dummy_qual_values.len() < rec.seq.len(){
@@ -331,7 +449,9 @@ pub fn reverse_complement(input: &mut DynamicFastXReader, output: &mut DynamicFa
             rec.qual = Some(&mut dummy_qual_values.as_mut_slice()[0..rec.seq.len()]);
         }
         reverse_complement_in_place(rec.seq);
-        rec.qual.as_mut().map(|q| q.reverse()); // Also reverse the quality values
+        if let Some(q) = rec.qual.as_mut() {
+            q.reverse(); // Also reverse the quality values
+        }
 
         output.write(&rec.into_shared_ref()).unwrap();
     }   
@@ -416,6 +536,7 @@ pub fn get_writer(args: &clap::ArgMatches) -> DynamicFastXWriter{
     }
 }
 
+#[cfg(test)]
 mod tests{
     use super::*;
 
@@ -0,0 +1,24 @@
+// Locating motif occurrences in sequences as BED intervals.
+
+use std::io::Write;
+use crate::motif::Motif;
+
+// Writes every occurrence of the motifs as a BED6 line: sequence name, start (0-based),
+// end (exclusive), motif, number of mismatches (in the score column) and strand.
+// Returns the number of occurrences.
+pub fn locate(reader: &mut impl jseqio::reader::SeqStream, output: &mut impl Write, motifs: &[Motif], max_mismatches: usize, both_strands: bool) -> u64 {
+    let mut n_hits = 0_u64;
+    while let Some(rec) = reader.read_next().unwrap() {
+        let name = String::from_utf8_lossy(jseqio::record::Record::name(&rec)).into_owned();
+        for motif in motifs.iter() {
+            let pattern = std::str::from_utf8(&motif.pattern).unwrap(); // Motifs are validated ASCII
+            for hit in motif.find_all(rec.seq, max_mismatches, both_strands) {
+                let strand = if hit.reverse { '-' } else { '+' };
+                writeln!(output, "{}\t{}\t{}\t{}\t{}\t{}", name, hit.start, hit.end, pattern, hit.mismatches, strand).unwrap();
+                n_hits += 1;
+            }
+        }
+    }
+    output.flush().unwrap();
+    n_hits
+}
@@ -2,6 +2,7 @@ extern crate flate2;
 
 use seq_tools::*;
 use trim_adapters::TrimMode;
+use jseqio::writer::{DynamicFastXWriter, SeqRecordWriter};
 
 mod cli;
 
@@ -11,6 +12,26 @@ fn read_lines(filename: &str) -> Vec<String>{
 }
 
 
+fn histogram_options(sub_matches: &clap::ArgMatches) -> seq_tools::histogram::HistogramOptions {
+    let options = seq_tools::histogram::HistogramOptions{
+        min: sub_matches.get_one::<i64>("min").copied(),
+        max: sub_matches.get_one::<i64>("max").copied(),
+        n_bins: *sub_matches.get_one::<usize>("nbins").unwrap(),
+        log_scale: sub_matches.get_flag("log-bins"),
+        width: *sub_matches.get_one::<usize>("width").unwrap(),
+        tsv: sub_matches.get_flag("tsv"),
+    };
+    if options.n_bins == 0 {
+        panic!("Error: --nbins must be positive");
+    }
+    if let (Some(min), Some(max)) = (options.min, options.max) {
+        if min > max {
+            panic!("Error: --min can not be larger than --max");
+        }
+    }
+    options
+}
+
 fn main() {
 
     let matches = cli::build_cli().get_matches();
@@ -18,12 +39,9 @@ fn main() {
     match matches.subcommand() {
         Some(("length-histogram", sub_matches)) => { 
             let mut reader = get_reader(&matches).unwrap();
-            let min: i64 = sub_matches.get_one::<String>("min").unwrap().parse::<i64>().unwrap();
-            let max: i64 = sub_matches.get_one::<String>("max").unwrap().parse::<i64>().unwrap();
-            let nbins: i64 = sub_matches.get_one::<String>("nbins").unwrap().parse::<i64>().unwrap();
-            print_length_histogram(&mut reader, min, max, nbins);
+            print_length_histogram(&mut reader, &histogram_options(sub_matches));
         }
-        Some(("print-lengths", sub_matches)) => { 
+        Some(("print-lengths", _)) => { 
             let mut reader = get_reader(&matches).unwrap();
             print_lengths(&mut reader);
         }
@@ -32,33 +50,532 @@ fn main() {
             print_stats(&mut reader);
         }
 
-        Some(("gc-content", _)) => { 
+        Some(("gc-content", sub_matches)) => { 
             let mut reader = get_reader(&matches).unwrap();
-            gc_content(&mut reader);
+            let mut output = std::io::BufWriter::new(std::io::stdout());
+            if sub_matches.get_flag("per-sequence") {
+                seq_tools::gc::write_gc_per_sequence(&mut reader, &mut output).unwrap();
+            } else if let Some(&window) = sub_matches.get_one::<usize>("window") {
+                let step = sub_matches.get_one::<usize>("step").copied().unwrap_or(window);
+                if window == 0 || step == 0 {
+                    panic!("Error: --window and --step must be positive");
+                }
+                seq_tools::gc::write_gc_windows(&mut reader, window, step, &mut output).unwrap();
+            } else if sub_matches.get_flag("histogram") {
+                seq_tools::gc::print_gc_histogram(&mut reader, &histogram_options(sub_matches));
+            } else {
+                gc_content(&mut reader);
+            }
         }
         Some(("extract-reads", sub_matches)) => { 
-            let reader = get_reader(&matches).unwrap();
-            if let Some(ranks) = sub_matches.get_many::<String>("rank"){
-                let list: Vec<usize> = ranks.map(|s| s.parse::<usize>().unwrap()).collect();
-                extract_reads_by_ranks(reader, &list);
-            } else if let Some(ranks_listfilename) = sub_matches.get_one::<String>("ranks-listfile"){
-                let ranks_reader = std::io::BufReader::new(std::fs::File::open(ranks_listfilename).unwrap());
-                let list = std::io::BufRead::lines(ranks_reader).map(|s| s.unwrap().parse::<usize>().unwrap()).collect();
-                extract_reads_by_ranks(reader, &list);
-            } else if let Some(names) = sub_matches.get_many::<String>("name"){
-                let list: Vec<String> = names.map(|s| s.to_owned()).collect();
-                extract_reads_by_names(reader, &list);
-            } else if let Some(names_listfilename) = sub_matches.get_one::<String>("names-listfile"){
-                let list = read_lines(names_listfilename);
-                extract_reads_by_names(reader, &list);
+            let ranks: Option<Vec<usize>> = if let Some(ranks) = sub_matches.get_many::<String>("rank"){
+                Some(ranks.map(|s| s.parse::<usize>().unwrap()).collect())
+            } else { 
+                sub_matches.get_one::<String>("ranks-listfile").map(|ranks_listfilename| {
+                    read_lines(ranks_listfilename).iter().map(|s| s.parse::<usize>().unwrap()).collect()
+                })
+            };
+            let names: Option<Vec<String>> = if let Some(names) = sub_matches.get_many::<String>("name"){
+                Some(names.map(|s| s.to_owned()).collect())
+            } else {
+                sub_matches.get_one::<String>("names-listfile").map(|names_listfilename| read_lines(names_listfilename))
+            };
+
+            if sub_matches.get_flag("index") {
+                let infile = matches.get_one::<String>("input").expect("Indexed lookup requires the input to be a file");
+                if let Some(list) = ranks {
+                    extract_reads_by_ranks_indexed(infile, &list);
+                } else if let Some(list) = names {
+                    extract_reads_by_names_indexed(infile, &list);
+                }
+            } else {
+                let mut reader = get_reader(&matches).unwrap();
+                let invert = sub_matches.get_flag("invert");
+                let strip_suffixes = sub_matches.get_flag("strip-mate-suffix");
+                if let Some(list) = ranks {
+                    extract_reads_by_ranks(reader, &list);
+                } else if let Some(list) = names {
+                    if sub_matches.get_flag("stream") || invert {
+                        extract_reads_by_names_streaming(&mut reader, &list, invert, strip_suffixes);
+                    } else {
+                        extract_reads_by_names(reader, &list, strip_suffixes);
+                    }
+                }
+            }
+        }
+        Some(("grep", sub_matches)) => { 
+            let mut reader = get_reader(&matches).unwrap();
+            let options = seq_tools::grep::GrepOptions{
+                header_regex: sub_matches.get_one::<String>("header-regex").map(|r| regex::bytes::Regex::new(r).unwrap()),
+                motifs: sub_matches.get_many::<String>("motif").map(|motifs| {
+                    motifs.map(|m| seq_tools::motif::Motif::new(m.as_bytes()).unwrap()).collect()
+                }).unwrap_or_default(),
+                max_mismatches: *sub_matches.get_one::<usize>("max-mismatches").unwrap(),
+                both_strands: !sub_matches.get_flag("forward-only"),
+                invert: sub_matches.get_flag("invert"),
+            };
+            if sub_matches.get_flag("count") {
+                let n = seq_tools::grep::grep(&mut reader, None::<&mut DynamicFastXWriter>, &options);
+                println!("{}", n);
+            } else {
+                let mut writer = get_writer(sub_matches);
+                seq_tools::grep::grep(&mut reader, Some(&mut writer), &options);
+            }
+        }
+        Some(("locate", sub_matches)) => { 
+            let mut reader = get_reader(&matches).unwrap();
+            let mut motif_strings: Vec<String> = sub_matches.get_many::<String>("motif").map(|m| m.cloned().collect()).unwrap_or_default();
+            if let Some(motifs_file) = sub_matches.get_one::<String>("motifs-file") {
+                motif_strings.extend(read_lines(motifs_file).into_iter().map(|line| line.trim().to_owned()).filter(|line| !line.is_empty()));
+            }
+            if motif_strings.is_empty() {
+                panic!("Error: no motifs given");
+            }
+            let motifs: Vec<seq_tools::motif::Motif> = motif_strings.iter().map(|m| seq_tools::motif::Motif::new(m.as_bytes()).unwrap()).collect();
+            let max_mismatches = *sub_matches.get_one::<usize>("max-mismatches").unwrap();
+            let both_strands = !sub_matches.get_flag("forward-only");
+
+            let mut out = std::io::BufWriter::new(std::io::stdout());
+            let n_hits = seq_tools::locate::locate(&mut reader, &mut out, &motifs, max_mismatches, both_strands);
+            eprintln!("Found {} occurrences", n_hits);
+        }
+        Some(("kmer-compare", sub_matches)) => { 
+            let mut files = vec![matches.get_one::<String>("input").unwrap().to_owned()];
+            files.extend(sub_matches.get_many::<String>("files").expect("Error: at least two files are needed").cloned());
+            let k = *sub_matches.get_one::<usize>("k").unwrap();
+            if k == 0 || k > 64 {
+                panic!("Error: k must be between 1 and 64");
+            }
+            for f in files.iter() {
+                if !std::path::Path::new(f).exists() {
+                    panic!("Error: file {} does not exist", f);
+                }
+            }
+            let n_threads = sub_matches.get_one::<usize>("threads").copied().unwrap_or_else(|| std::thread::available_parallelism().map_or(1, |n| n.get()));
+            seq_tools::kmers::kmer_compare(&files, k, n_threads, &mut std::io::stdout());
+        }
+        Some(("kmer-count", sub_matches)) => { 
+            let k = *sub_matches.get_one::<usize>("k").unwrap();
+            if k == 0 || k > 64 {
+                panic!("Error: k must be between 1 and 64");
+            }
+            let max = *sub_matches.get_one::<i64>("max").unwrap();
+            let nbins = *sub_matches.get_one::<usize>("nbins").unwrap();
+            if max < 1 || nbins < 1 {
+                panic!("Error: --max and --nbins must be positive");
+            }
+            let histogram = seq_tools::histogram::HistogramOptions{
+                min: Some(1),
+                max: Some(max),
+                n_bins: nbins,
+                log_scale: sub_matches.get_flag("log-bins"),
+                width: *sub_matches.get_one::<usize>("width").unwrap(),
+                tsv: false,
+            };
+            let format = match sub_matches.get_one::<String>("format").unwrap().as_str() {
+                "fasta" => seq_tools::kmers::KmerDumpFormat::Fasta,
+                _ => seq_tools::kmers::KmerDumpFormat::Tsv,
+            };
+            let min_count = *sub_matches.get_one::<u32>("min-count").unwrap();
+            let mut kmers_out = sub_matches.get_one::<String>("kmers-out").map(|f| std::io::BufWriter::new(std::fs::File::create(f).unwrap()));
+
+            let mut reader = get_reader(&matches).unwrap();
+            let spectrum = seq_tools::kmers::kmer_count(&mut reader, k, min_count, kmers_out.as_mut().map(|w| (w as &mut dyn std::io::Write, format)));
+            if sub_matches.get_flag("spectrum-tsv") {
+                seq_tools::kmers::write_spectrum_tsv(&spectrum, &mut std::io::stdout());
+            } else {
+                seq_tools::kmers::print_spectrum_histogram(&spectrum, &histogram);
+            }
+        }
+        Some(("interleave", sub_matches)) => { 
+            let mates2 = sub_matches.get_one::<String>("mates2").expect("Error: the file with the second mates must be given");
+            let mut input1 = get_reader(&matches).unwrap();
+            let mut input2 = jseqio::reader::DynamicFastXReader::from_file(mates2).unwrap();
+            let mut output = get_writer(sub_matches);
+            let mut orphans = sub_matches.get_one::<String>("orphans").map(|f| DynamicFastXWriter::new_to_file(f).unwrap());
+            let report = seq_tools::paired::interleave(&mut input1, &mut input2, &mut output, orphans.as_mut());
+            eprintln!("Wrote {} pairs and {} orphans", report.n_pairs, report.n_orphans);
+        }
+        Some(("deinterleave", sub_matches)) => { 
+            let mut input = get_reader(&matches).unwrap();
+            let mut output1 = DynamicFastXWriter::new_to_file(sub_matches.get_one::<String>("out1").unwrap()).unwrap();
+            let mut output2 = DynamicFastXWriter::new_to_file(sub_matches.get_one::<String>("out2").unwrap()).unwrap();
+            let mut orphans = sub_matches.get_one::<String>("orphans").map(|f| DynamicFastXWriter::new_to_file(f).unwrap());
+            let report = seq_tools::paired::deinterleave(&mut input, &mut output1, &mut output2, orphans.as_mut());
+            eprintln!("Wrote {} pairs and {} orphans", report.n_pairs, report.n_orphans);
+        }
+        Some(("repair", sub_matches)) => { 
+            let mut input1 = get_reader(&matches).unwrap();
+            let mut input2 = sub_matches.get_one::<String>("mates2").map(|f| jseqio::reader::DynamicFastXReader::from_file(f).unwrap());
+            let mut singletons = sub_matches.get_one::<String>("singletons").map(|f| DynamicFastXWriter::new_to_file(f).unwrap());
+            let window = *sub_matches.get_one::<u64>("window").unwrap();
+            let report = match (sub_matches.get_one::<String>("out1"), sub_matches.get_one::<String>("out2")) {
+                (Some(out1), Some(out2)) => {
+                    let mut output1 = DynamicFastXWriter::new_to_file(out1).unwrap();
+                    let mut output2 = DynamicFastXWriter::new_to_file(out2).unwrap();
+                    let report = seq_tools::paired::repair(&mut input1, input2.as_mut(), window, |r1, r2| {
+                        output1.write_owned_record(r1).unwrap();
+                        output2.write_owned_record(r2).unwrap();
+                    }, singletons.as_mut());
+                    output1.flush().unwrap();
+                    output2.flush().unwrap();
+                    report
+                }
+                _ => {
+                    let mut output = get_writer(sub_matches);
+                    let report = seq_tools::paired::repair(&mut input1, input2.as_mut(), window, |r1, r2| {
+                        output.write_owned_record(r1).unwrap();
+                        output.write_owned_record(r2).unwrap();
+                    }, singletons.as_mut());
+                    output.flush().unwrap();
+                    report
+                }
+            };
+            eprintln!("Wrote {} pairs and {} singletons. At most {} reads were waiting for their mate at a time.", report.n_pairs, report.n_orphans, report.max_pending);
+        }
+        Some(("merge-pairs", sub_matches)) => { 
+            let options = seq_tools::merge_pairs::MergeOptions{
+                min_overlap: *sub_matches.get_one::<usize>("min-overlap").unwrap(),
+                max_mismatch_rate: *sub_matches.get_one::<f64>("max-mismatch-rate").unwrap(),
+            };
+            if options.min_overlap == 0 || !(0.0..=1.0).contains(&options.max_mismatch_rate) {
+                panic!("Error: --min-overlap must be positive and --max-mismatch-rate must be between 0 and 1");
+            }
+            let mut input1 = get_reader(&matches).unwrap();
+            let mut input2 = sub_matches.get_one::<String>("mates2").map(|f| jseqio::reader::DynamicFastXReader::from_file(f).unwrap());
+            let mut output = get_writer(sub_matches);
+            let mut unmerged = sub_matches.get_one::<String>("unmerged").map(|f| DynamicFastXWriter::new_to_file(f).unwrap());
+            let report = seq_tools::merge_pairs::merge_pairs(&mut input1, input2.as_mut(), &mut output, unmerged.as_mut(), &options);
+            let percentage = if report.n_pairs == 0 { 0.0 } else { 100.0 * report.n_merged as f64 / report.n_pairs as f64 };
+            let mean_overlap = if report.n_merged == 0 { 0.0 } else { report.total_overlap as f64 / report.n_merged as f64 };
+            eprintln!("Merged {} out of {} pairs ({:.2}%). Mean overlap length: {:.1}", report.n_merged, report.n_pairs, percentage, mean_overlap);
+        }
+        Some(("split", sub_matches)) => { 
+            use seq_tools::split::SplitMode;
+            let paired_interleaved = sub_matches.get_flag("paired-interleaved");
+            let mode = if let Some(re) = sub_matches.get_one::<String>("by-header-regex") {
+                SplitMode::HeaderRegex(regex::bytes::Regex::new(re).unwrap_or_else(|e| panic!("Error: invalid regular expression: {}", e)))
+            } else if let Some(&n_parts) = sub_matches.get_one::<usize>("parts") {
+                if n_parts == 0 {
+                    panic!("Error: --parts must be positive");
+                }
+                if sub_matches.get_flag("contiguous") {
+                    let infile = matches.get_one::<String>("input").expect("Error: --contiguous requires the input to be a file");
+                    let n_seqs = count_sequences(jseqio::reader::DynamicFastXReader::from_file(infile).unwrap());
+                    SplitMode::Contiguous(n_parts, if paired_interleaved { n_seqs / 2 } else { n_seqs })
+                } else {
+                    SplitMode::RoundRobin(n_parts)
+                }
+            } else if let Some(&size) = sub_matches.get_one::<u64>("chunk-size") {
+                if size == 0 {
+                    panic!("Error: --chunk-size must be positive");
+                }
+                match sub_matches.get_one::<String>("chunk-unit").unwrap().as_str() {
+                    "bases" => SplitMode::ChunkBases(size),
+                    _ => SplitMode::ChunkReads(size),
+                }
+            } else {
+                let bins = sub_matches.get_one::<String>("by-length-bins").unwrap();
+                let bins: Vec<u64> = bins.split(',').map(|b| b.trim().parse::<u64>().unwrap_or_else(|_| panic!("Error: invalid bin boundary {}", b))).collect();
+                if !bins.windows(2).all(|w| w[0] < w[1]) {
+                    panic!("Error: the length bin boundaries must be increasing");
+                }
+                SplitMode::LengthBins(bins)
+            };
+            let options = seq_tools::split::SplitOptions{
+                mode,
+                outdir: std::path::PathBuf::from(sub_matches.get_one::<String>("outdir").unwrap()),
+                prefix: sub_matches.get_one::<String>("prefix").unwrap().to_owned(),
+                paired_interleaved,
+                max_open_files: *sub_matches.get_one::<usize>("max-open-files").unwrap(),
+                gzip: sub_matches.get_flag("gzip"),
+            };
+            if options.max_open_files == 0 {
+                panic!("Error: --max-open-files must be positive");
+            }
+            let mut reader = get_reader(&matches).unwrap();
+            let (n_units, n_files) = seq_tools::split::split(&mut reader, &options);
+            eprintln!("Wrote {} {} into {} files", n_units, if paired_interleaved { "pairs" } else { "reads" }, n_files);
+        }
+        Some(("prefixes", sub_matches)) => { 
+            use seq_tools::prefixes::{PrefixSizes, PrefixUnit};
+            let infile = matches.get_one::<String>("input").expect("Error: the input must be a file");
+            let steps = sub_matches.get_one::<usize>("steps").copied();
+            let sizes = if let Some(&ratio) = sub_matches.get_one::<f64>("ratio") {
+                if ratio <= 1.0 {
+                    panic!("Error: --ratio must be larger than 1");
+                }
+                PrefixSizes::Ratio{ratio, steps}
+            } else if let Some(&step) = sub_matches.get_one::<u64>("linear") {
+                if step == 0 {
+                    panic!("Error: --linear must be positive");
+                }
+                PrefixSizes::Linear{step, steps}
+            } else {
+                let sizes = sub_matches.get_one::<String>("sizes").unwrap();
+                PrefixSizes::List(sizes.split(',').map(|s| s.trim().parse::<u64>().unwrap_or_else(|_| panic!("Error: invalid size {}", s))).collect())
+            };
+            let options = seq_tools::prefixes::PrefixOptions{
+                sizes,
+                unit: match sub_matches.get_one::<String>("unit").unwrap().as_str() {
+                    "bases" => PrefixUnit::Bases,
+                    _ => PrefixUnit::Reads,
+                },
+                random: sub_matches.get_flag("random"),
+                seed: sub_matches.get_one::<u64>("seed").copied(),
+                out_prefix: sub_matches.get_one::<String>("out-prefix").unwrap().to_owned(),
+                gzip: sub_matches.get_flag("gzip"),
+            };
+            for (filename, n_reads, n_bases) in seq_tools::prefixes::write_prefixes(infile, &options) {
+                eprintln!("Wrote {} reads and {} bases to {}", n_reads, n_bases, filename);
+            }
+        }
+        Some(("kmer-filter", sub_matches)) => { 
+            let options = seq_tools::kmers::KmerFilterOptions{
+                k: *sub_matches.get_one::<usize>("k").unwrap(),
+                min_shared: *sub_matches.get_one::<usize>("min-shared").unwrap(),
+                min_fraction: *sub_matches.get_one::<f64>("min-fraction").unwrap(),
+                keep_matching: sub_matches.get_flag("keep-matching"),
+                paired_interleaved: sub_matches.get_flag("paired-interleaved"),
+            };
+            if options.k == 0 || options.k > 64 {
+                panic!("Error: k must be between 1 and 64");
+            }
+            if !(0.0..=1.0).contains(&options.min_fraction) {
+                panic!("Error: --min-fraction must be between 0 and 1");
+            }
+            let mut reference = jseqio::reader::DynamicFastXReader::from_file(sub_matches.get_one::<String>("reference").unwrap()).unwrap();
+            let mut reader = get_reader(&matches).unwrap();
+            let mut writer = get_writer(sub_matches);
+            let report = seq_tools::kmers::kmer_filter(&mut reference, &mut reader, &mut writer, &options).unwrap_or_else(|e| panic!("Error: {}", e));
+            eprintln!("Removed {} out of {} reads ({} matched the reference)", report.n_removed, report.n_reads, report.n_matching);
+        }
+        Some(("filter", sub_matches)) => { 
+            use seq_tools::filter::{FilterOptions, FilterReason};
+            let options = FilterOptions{
+                min_length: sub_matches.get_one::<usize>("min-length").copied(),
+                max_length: sub_matches.get_one::<usize>("max-length").copied(),
+                min_gc: sub_matches.get_one::<f64>("min-gc").copied(),
+                max_gc: sub_matches.get_one::<f64>("max-gc").copied(),
+                max_n: sub_matches.get_one::<u64>("max-n").copied(),
+                max_n_fraction: sub_matches.get_one::<f64>("max-n-fraction").copied(),
+                min_mean_quality: sub_matches.get_one::<f64>("min-mean-quality").copied(),
+                max_expected_errors: sub_matches.get_one::<f64>("max-expected-errors").copied(),
+                min_entropy: sub_matches.get_one::<f64>("min-complexity").copied(),
+                paired_interleaved: sub_matches.get_flag("paired-interleaved"),
+            };
+            let mut reader = get_reader(&matches).unwrap();
+            if options.uses_qualities() && matches!(reader.filetype(), jseqio::FileType::FASTA) {
+                panic!("Error: --min-mean-quality and --max-expected-errors require fastq input");
+            }
+            let mut writer = get_writer(sub_matches);
+            let report = seq_tools::filter::filter_reads(&mut reader, &mut writer, &options).unwrap_or_else(|e| panic!("Error: {}", e));
+            eprintln!("Removed {} out of {} reads", report.n_removed, report.n_reads);
+            for reason in FilterReason::ALL {
+                let n = report.n_removed_by[reason as usize];
+                if n > 0 {
+                    eprintln!("  {}: {}", reason.description(), n);
+                }
+            }
+        }
+        Some(("mask", sub_matches)) => { 
+            use seq_tools::complexity::{ComplexityMethod, MaskOptions};
+            let window = *sub_matches.get_one::<usize>("window").unwrap();
+            let method = match sub_matches.get_one::<String>("method").unwrap().as_str() {
+                "entropy" => {
+                    if window < 3 {
+                        panic!("Error: the window must be at least 3");
+                    }
+                    ComplexityMethod::Entropy{window, threshold: *sub_matches.get_one::<f64>("entropy-threshold").unwrap()}
+                }
+                _ => {
+                    if !(4..=seq_tools::complexity::MAX_DUST_WINDOW).contains(&window) {
+                        panic!("Error: the dust window must be between 4 and {}", seq_tools::complexity::MAX_DUST_WINDOW);
+                    }
+                    ComplexityMethod::Dust{window, threshold: *sub_matches.get_one::<u32>("dust-threshold").unwrap()}
+                }
+            };
+            let options = MaskOptions{method, hard: sub_matches.get_flag("hard"), discard: sub_matches.get_flag("discard")};
+            let mut reader = get_reader(&matches).unwrap();
+            let mut writer = get_writer(sub_matches);
+            let report = seq_tools::complexity::mask_reads(&mut reader, &mut writer, &options);
+            if options.discard {
+                eprintln!("Removed {} out of {} sequences", report.n_discarded, report.n_seqs);
+            } else {
+                let percentage = if report.n_bases == 0 { 0.0 } else { 100.0 * report.n_masked_bases as f64 / report.n_bases as f64 };
+                eprintln!("Masked {} out of {} bases ({:.2}%)", report.n_masked_bases, report.n_bases, percentage);
+            }
+        }
+        Some(("sort", sub_matches)) => { 
+            use seq_tools::sort::{SortKey, SortOptions};
+            let key = match sub_matches.get_one::<String>("key").unwrap().as_str() {
+                "name" => SortKey::Name{natural: sub_matches.get_one::<String>("name-order").unwrap() == "natural"},
+                "sequence" => SortKey::Sequence,
+                "gc" => SortKey::Gc,
+                _ => SortKey::Length,
+            };
+            let options = SortOptions{
+                key,
+                reverse: sub_matches.get_flag("reverse"),
+                paired_interleaved: sub_matches.get_flag("paired-interleaved"),
+                max_memory: sub_matches.get_one::<usize>("max-memory").unwrap() * (1 << 20),
+                temp_dir: sub_matches.get_one::<std::path::PathBuf>("temp-dir").cloned(),
+            };
+            let mut reader = get_reader(&matches).unwrap();
+            let mut writer = get_writer(sub_matches);
+            let report = seq_tools::sort::sort_reads(&mut reader, &mut writer, &options).unwrap_or_else(|e| panic!("Error: {}", e));
+            if report.n_runs > 0 {
+                eprintln!("Sorted {} sequences in {} parts on disk", report.n_seqs, report.n_runs);
+            }
+        }
+        Some(("shuffle", sub_matches)) => { 
+            let options = seq_tools::shuffle::ShuffleOptions{
+                seed: sub_matches.get_one::<u64>("seed").copied(),
+                paired_interleaved: sub_matches.get_flag("paired-interleaved"),
+                max_memory: sub_matches.get_one::<usize>("max-memory").unwrap() * (1 << 20),
+                temp_dir: sub_matches.get_one::<std::path::PathBuf>("temp-dir").cloned(),
+            };
+            let mut reader = get_reader(&matches).unwrap();
+            let mut writer = get_writer(sub_matches);
+            let report = seq_tools::shuffle::shuffle_reads(&mut reader, &mut writer, &options).unwrap_or_else(|e| panic!("Error: {}", e));
+            if report.n_buckets > 0 {
+                eprintln!("Shuffled {} sequences in {} parts on disk", report.n_seqs, report.n_buckets);
+            }
+        }
+        Some(("minimizers", sub_matches)) => { 
+            let k = *sub_matches.get_one::<usize>("k").unwrap();
+            let w = *sub_matches.get_one::<usize>("w").unwrap();
+            if k == 0 || k > 64 || w == 0 {
+                panic!("Error: k must be between 1 and 64 and w must be positive");
+            }
+            let order = match sub_matches.get_one::<String>("order").unwrap().as_str() {
+                "lex" => seq_tools::kmer::KmerOrder::Lexicographic,
+                _ => seq_tools::kmer::KmerOrder::Hash,
+            };
+            let output_type = match sub_matches.get_flag("super-kmers") {
+                true => seq_tools::minimizers::MinimizerOutput::SuperKmers,
+                false => seq_tools::minimizers::MinimizerOutput::Tsv,
+            };
+            let mut output: Box<dyn std::io::Write> = match sub_matches.get_one::<String>("output") {
+                Some(f) => Box::new(std::io::BufWriter::new(std::fs::File::create(f).unwrap())),
+                None => Box::new(std::io::BufWriter::new(std::io::stdout())),
+            };
+            let mut reader = get_reader(&matches).unwrap();
+            seq_tools::minimizers::minimizers(&mut reader, k, w, order, sub_matches.get_flag("canonical"), output_type, &mut output);
+        }
+        Some(("sketch", sub_matches)) => { 
+            let mut files = vec![matches.get_one::<String>("input").expect("Error: no input file given").to_owned()];
+            files.extend(sub_matches.get_many::<String>("files").into_iter().flatten().cloned());
+            let k = *sub_matches.get_one::<usize>("k").unwrap();
+            if k == 0 || k > 64 {
+                panic!("Error: k must be between 1 and 64");
+            }
+            let kind = match sub_matches.get_one::<u64>("scaled") {
+                Some(0) => panic!("Error: the scale must be positive"),
+                Some(&scale) => seq_tools::sketch::SketchKind::Scaled(scale),
+                None => match *sub_matches.get_one::<usize>("size").unwrap() {
+                    0 => panic!("Error: the sketch size must be positive"),
+                    size => seq_tools::sketch::SketchKind::BottomK(size),
+                },
+            };
+            let n_threads = sub_matches.get_one::<usize>("threads").copied().unwrap_or_else(|| std::thread::available_parallelism().map_or(1, |n| n.get()));
+            let sketches = parallel_map(&files, n_threads, |f| {
+                let mut reader = jseqio::reader::DynamicFastXReader::from_file(f).unwrap();
+                let sketch = seq_tools::sketch::Sketch::build(f, &mut reader, k, kind);
+                eprintln!("{}: {} hashes", f, sketch.hashes.len());
+                sketch
+            });
+            seq_tools::sketch::write_sketches(sub_matches.get_one::<String>("output").unwrap(), &sketches).unwrap();
+        }
+        Some(("sketch-compare", sub_matches)) => { 
+            let mut files = vec![matches.get_one::<String>("input").expect("Error: no input file given").to_owned()];
+            files.extend(sub_matches.get_many::<String>("files").into_iter().flatten().cloned());
+            let mut sketches = vec![];
+            for f in files.iter() {
+                sketches.extend(seq_tools::sketch::read_sketches(f).unwrap_or_else(|e| panic!("Error reading {}: {}", f, e)));
+            }
+            let n_threads = sub_matches.get_one::<usize>("threads").copied().unwrap_or_else(|| std::thread::available_parallelism().map_or(1, |n| n.get()));
+            if let Err(e) = seq_tools::sketch::sketch_compare(&sketches, n_threads, &mut std::io::stdout()) {
+                panic!("Error: {}", e);
             }
         }
         Some(("extract-region", sub_matches)) => { 
-            let reader = get_reader(&matches).unwrap();
-            let writer = get_writer(&sub_matches);
-            let s = *sub_matches.get_one::<usize>("start").unwrap();
-            let e = *sub_matches.get_one::<usize>("end").unwrap();
-            extract_region(reader, writer, s, e);
+            let regions: Vec<String> = sub_matches.get_many::<String>("region").map(|r| r.cloned().collect()).unwrap_or_default();
+            let bed_file = sub_matches.get_one::<String>("bed");
+            if !regions.is_empty() || bed_file.is_some() {
+                let infile = matches.get_one::<String>("input").expect("Extracting named regions requires the input to be a file");
+                let mut writer = get_writer(sub_matches);
+                extract_indexed_regions(infile, &regions, bed_file.map(|s| s.as_str()), &mut writer);
+            } else {
+                let reader = get_reader(&matches).unwrap();
+                let writer = get_writer(sub_matches);
+                let s = *sub_matches.get_one::<usize>("start").unwrap();
+                let e = *sub_matches.get_one::<usize>("end").unwrap();
+                extract_region(reader, writer, s, e);
+            }
+        }
+        Some(("alignment-histogram", sub_matches)) => { 
+            use seq_tools::sam::{AlignmentField, AlignmentFilter, AlignmentReader, parse_flags};
+            let mut reader = match matches.get_one::<String>("input") {
+                Some(infile) => AlignmentReader::from_file(infile),
+                None => AlignmentReader::new(std::io::stdin()),
+            }.unwrap_or_else(|e| panic!("Error: {}", e));
+            let field = match sub_matches.get_one::<String>("field").unwrap().as_str() {
+                "mapq" => AlignmentField::MappingQuality,
+                "tlen" => AlignmentField::TemplateLength,
+                "aligned-length" => AlignmentField::AlignedLength,
+                _ => AlignmentField::Position,
+            };
+            let filter = AlignmentFilter{
+                require_flags: parse_flags(sub_matches.get_one::<String>("require-flags").unwrap()).unwrap_or_else(|e| panic!("Error: {}", e)),
+                exclude_flags: parse_flags(sub_matches.get_one::<String>("exclude-flags").unwrap()).unwrap_or_else(|e| panic!("Error: {}", e)),
+                references: sub_matches.get_many::<String>("reference").map(|r| r.cloned().collect()).unwrap_or_default(),
+            };
+            seq_tools::sam::print_alignment_histogram(&mut reader, field, &filter, &histogram_options(sub_matches)).unwrap_or_else(|e| panic!("Error: {}", e));
+        }
+        Some(("coverage", sub_matches)) => { 
+            use seq_tools::coverage::*;
+            use seq_tools::sam::{AlignmentFilter, AlignmentReader, parse_flags};
+            let mut reader = match matches.get_one::<String>("input") {
+                Some(infile) => AlignmentReader::from_file(infile),
+                None => AlignmentReader::new(std::io::stdin()),
+            }.unwrap_or_else(|e| panic!("Error: {}", e));
+            let filter = AlignmentFilter{
+                require_flags: parse_flags(sub_matches.get_one::<String>("require-flags").unwrap()).unwrap_or_else(|e| panic!("Error: {}", e)),
+                exclude_flags: parse_flags(sub_matches.get_one::<String>("exclude-flags").unwrap()).unwrap_or_else(|e| panic!("Error: {}", e)),
+                references: sub_matches.get_many::<String>("reference").map(|r| r.cloned().collect()).unwrap_or_default(),
+            };
+            let window = sub_matches.get_one::<usize>("window").copied();
+            if window == Some(0) {
+                panic!("Error: --window must be positive");
+            }
+            let min_mapq = *sub_matches.get_one::<u8>("min-mapq").unwrap();
+
+            let mut references = compute_depths(&mut reader, &filter, min_mapq).unwrap_or_else(|e| panic!("Error: {}", e));
+            if !filter.references.is_empty() {
+                references.retain(|r| filter.references.contains(&r.name));
+            }
+            let mut output: Box<dyn std::io::Write> = match sub_matches.get_one::<String>("output") {
+                Some(path) => Box::new(std::io::BufWriter::new(std::fs::File::create(path).unwrap())),
+                None => Box::new(std::io::BufWriter::new(std::io::stdout())),
+            };
+            write_bedgraph(&references, window, &mut output).unwrap();
+
+            let stats: Vec<CoverageStats> = references.iter().map(CoverageStats::new).collect();
+            if let Some(path) = sub_matches.get_one::<String>("summary") {
+                write_coverage_stats_tsv(&stats, &mut std::io::BufWriter::new(std::fs::File::create(path).unwrap())).unwrap();
+            }
+            if let Some(path) = sub_matches.get_one::<String>("depth-histogram") {
+                write_depth_histogram_tsv(&depth_histogram(&references), &mut std::io::BufWriter::new(std::fs::File::create(path).unwrap())).unwrap();
+            }
+            let total = CoverageStats::total(&stats, "total");
+            eprintln!("Mean depth {:.2} over {} bases. Breadth of coverage {:.2}% at 1x and {:.2}% at 10x.",
+                total.mean_depth(), total.length, 100.0 * total.breadth_1x(), 100.0 * total.breadth_10x());
+        }
+        Some(("faidx", _)) => { 
+            let infile = matches.get_one::<String>("input").expect("Indexing requires the input to be a file");
+            let index = seq_tools::fasta_index::build_index_files(infile).unwrap();
+            eprintln!("Indexed {} sequences", index.entries.len());
         }
         Some(("subsample", sub_matches)) => { // TODO: Untested
             if matches.get_one::<String>("input").is_none() {
@@ -0,0 +1,202 @@
+// Merging of overlapping paired-end mates into single reads.
+//
+// The reverse complement of the second mate is slid along the first mate without gaps.
+// It may start inside the first mate, or before it when the insert is shorter than the
+// reads and both mates read through into the adapter. In the latter case the overhangs,
+// which are adapter sequence, are trimmed from the merged read. The overlap with the best
+// score (matches minus four times mismatches) among those with at most the maximum
+// mismatch rate is used. In the overlap, the
+// consensus base is the one with the highest posterior probability given the two bases
+// and their qualities, and its quality is the posterior probability of error.
+// Mates with no qualities (fasta) are treated as if all their bases had quality 20.
+
+use jseqio::reader::DynamicFastXReader;
+use jseqio::record::OwnedRecord;
+use jseqio::writer::SeqRecordWriter;
+use crate::paired::{check_mates, read_pair};
+
+const PHRED_OFFSET: u8 = 33;
+const DEFAULT_QUALITY: u8 = 20;
+const MAX_QUALITY: u8 = 41; // Cap for consensus qualities, as in Illumina 1.8+
+const MISMATCH_PENALTY: isize = 4;
+
+pub struct MergeOptions {
+    pub min_overlap: usize,
+    pub max_mismatch_rate: f64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Overlap {
+    pub offset: isize, // Start of the reverse complement of mate 2 relative to the start of mate 1
+    pub len: usize,
+    pub mismatches: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct MergeReport {
+    pub n_pairs: u64,
+    pub n_merged: u64,
+    pub total_overlap: u64, // Sum of the overlap lengths of the merged pairs
+}
+
+fn is_base(c: u8) -> bool {
+    matches!(c.to_ascii_uppercase(), b'A' | b'C' | b'G' | b'T')
+}
+
+// Finds the best ungapped overlap of seq1 and seq2, where seq2 either starts inside seq1 and
+// extends at least to its end, or starts before seq1. N characters count as neither matches
+// nor mismatches. Shorter overlaps are tried first and win ties.
+pub fn find_overlap(seq1: &[u8], seq2: &[u8], options: &MergeOptions) -> Option<Overlap> {
+    let (n1, n2) = (seq1.len() as isize, seq2.len() as isize);
+    let min_overlap = options.min_overlap as isize;
+    let mut best: Option<(isize, Overlap)> = None;
+    for offset in (min_overlap - n2..=n1 - min_overlap).rev() {
+        let (start, end) = (std::cmp::max(offset, 0), std::cmp::min(n1, offset + n2)); // Overlap in seq1
+        if offset >= 0 && end < n1 { continue } // seq2 is inside seq1 but does not reach its end
+        let len = (end - start) as usize;
+        let (mut matches, mut mismatches) = (0_usize, 0_usize);
+        let max_mismatches = (options.max_mismatch_rate * len as f64).floor() as usize;
+        let part2 = &seq2[(start - offset) as usize..(end - offset) as usize];
+        for (&a, &b) in seq1[start as usize..end as usize].iter().zip(part2.iter()) {
+            if !is_base(a) || !is_base(b) { continue }
+            if a.eq_ignore_ascii_case(&b) {
+                matches += 1;
+            } else {
+                mismatches += 1;
+                if mismatches > max_mismatches { break }
+            }
+        }
+        if mismatches > max_mismatches { continue }
+        let score = matches as isize - MISMATCH_PENALTY * mismatches as isize;
+        if best.is_none_or(|(best_score, _)| score > best_score) {
+            best = Some((score, Overlap{offset, len, mismatches}));
+        }
+    }
+    best.map(|(_, overlap)| overlap)
+}
+
+fn error_prob(q: u8) -> f64 {
+    10_f64.powf(-(q as f64) / 10.0)
+}
+
+// Consensus of two aligned bases with phred qualities (without offset). Returns the base
+// with the highest posterior probability and the phred quality of its posterior error probability.
+pub fn consensus_base(b1: u8, q1: u8, b2: u8, q2: u8) -> (u8, u8) {
+    match (is_base(b1), is_base(b2)) {
+        (false, false) => return (b'N', 0),
+        (true, false) => return (b1, q1),
+        (false, true) => return (b2, q2),
+        (true, true) => (),
+    }
+    let (b1, b2) = (b1.to_ascii_uppercase(), b2.to_ascii_uppercase());
+    let (e1, e2) = (error_prob(q1), error_prob(q2));
+
+    // Likelihood of each true base, assuming errors are uniform over the other three bases
+    let likelihood = |x: u8| {
+        let p1 = if x == b1 { 1.0 - e1 } else { e1 / 3.0 };
+        let p2 = if x == b2 { 1.0 - e2 } else { e2 / 3.0 };
+        p1 * p2
+    };
+    let total: f64 = b"ACGT".iter().map(|&x| likelihood(x)).sum();
+    let base = if b1 == b2 || q1 >= q2 { b1 } else { b2 };
+    let posterior_error = 1.0 - likelihood(base) / total;
+    let q = (-10.0 * posterior_error.max(1e-10).log10()).round();
+    (base, (q as u8).min(MAX_QUALITY))
+}
+
+// Merges the mates if they overlap. The merged read has the header of the first mate.
+pub fn merge_pair(rec1: &OwnedRecord, rec2: &OwnedRecord, options: &MergeOptions) -> Option<(OwnedRecord, Overlap)> {
+    let seq2 = jseqio::reverse_complement(&rec2.seq);
+    let overlap = find_overlap(&rec1.seq, &seq2, options)?;
+
+    let quals = |rec: &OwnedRecord| rec.qual.as_ref().map(|q| q.iter().map(|&c| c.saturating_sub(PHRED_OFFSET)).collect::<Vec<u8>>()).unwrap_or_else(|| vec![DEFAULT_QUALITY; rec.seq.len()]);
+    let qual1 = quals(rec1);
+    let mut qual2 = quals(rec2);
+    qual2.reverse();
+
+    // The part of mate 1 before the overlap, the consensus of the overlap, and the part of
+    // mate 2 after the end of mate 1. Overhangs of mate 2 before mate 1 and of mate 1 after
+    // mate 2 are adapter read-through and left out.
+    let start1 = std::cmp::max(overlap.offset, 0) as usize; // Start of the overlap in mate 1
+    let start2 = std::cmp::max(-overlap.offset, 0) as usize; // Start of the overlap in the reverse complement of mate 2
+    let mut seq = rec1.seq[..start1].to_vec();
+    let mut qual = qual1[..start1].to_vec();
+    for i in 0..overlap.len {
+        let (b, q) = consensus_base(rec1.seq[start1 + i], qual1[start1 + i], seq2[start2 + i], qual2[start2 + i]);
+        seq.push(b);
+        qual.push(q);
+    }
+    seq.extend_from_slice(&seq2[start2 + overlap.len..]);
+    qual.extend_from_slice(&qual2[start2 + overlap.len..]);
+
+    let qual = rec1.qual.as_ref().map(|_| qual.iter().map(|&q| q + PHRED_OFFSET).collect());
+    Some((OwnedRecord{head: rec1.head.clone(), seq, qual}, overlap))
+}
+
+// Merges the pairs from two files of first and second mates, or from one interleaved file
+// if input2 is None. Merged reads are written to output and the pairs that could not be
+// merged to the unmerged output, interleaved.
+pub fn merge_pairs(input1: &mut DynamicFastXReader, mut input2: Option<&mut DynamicFastXReader>, output: &mut impl SeqRecordWriter, mut unmerged: Option<&mut impl SeqRecordWriter>, options: &MergeOptions) -> MergeReport {
+    let mut report = MergeReport::default();
+    while let Some((rec1, rec2)) = read_pair(input1, input2.as_deref_mut()) {
+        check_mates(&rec1, &rec2, report.n_pairs).unwrap_or_else(|e| panic!("Error: {}. Use repair to restore the pairing first.", e));
+        report.n_pairs += 1;
+        match merge_pair(&rec1, &rec2, options) {
+            Some((merged, overlap)) => {
+                output.write_owned_record(&merged).unwrap();
+                report.n_merged += 1;
+                report.total_overlap += overlap.len as u64;
+            }
+            None => {
+                if let Some(out) = unmerged.as_mut() {
+                    out.write_owned_record(&rec1).unwrap();
+                    out.write_owned_record(&rec2).unwrap();
+                }
+            }
+        }
+    }
+    if let Some(out) = unmerged.as_mut() {
+        out.flush().unwrap();
+    }
+    output.flush().unwrap();
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_merge_pair(){
+        let options = MergeOptions{min_overlap: 5, max_mismatch_rate: 0.2};
+        // Fragment ACGTACGGTTCAGGCATTAC, mate 1 has an error at position 12 (G -> T) with low quality
+        let rec1 = OwnedRecord{head: b"r".to_vec(), seq: b"ACGTACGGTTCATGC".to_vec(), qual: Some(b"IIIIIIIIIIII#II".to_vec())};
+        let rec2 = OwnedRecord{head: b"r".to_vec(), seq: jseqio::reverse_complement(b"GTTCAGGCATTAC"), qual: Some(b"5555555555555".to_vec())};
+        let (merged, overlap) = merge_pair(&rec1, &rec2, &options).unwrap();
+        assert_eq!(overlap, Overlap{offset: 7, len: 8, mismatches: 1});
+        assert_eq!(merged.seq, b"ACGTACGGTTCAGGCATTAC");
+        // Matching bases get higher qualities, the mismatch gets a low one
+        assert_eq!(merged.qual.as_ref().unwrap()[..7], *b"IIIIIII");
+        assert_eq!(merged.qual.as_ref().unwrap()[7], b'J'); // Capped
+        assert!(merged.qual.as_ref().unwrap()[12] < b'5');
+        assert_eq!(merged.qual.as_ref().unwrap()[15..], *b"55555");
+
+        // Too many mismatches
+        let options = MergeOptions{min_overlap: 5, max_mismatch_rate: 0.1};
+        assert!(merge_pair(&rec1, &rec2, &options).is_none());
+
+        // Insert shorter than the reads: both mates read into adapters, which are trimmed
+        let fragment = b"TTGACCGTAGCAAGTC";
+        let rec1 = OwnedRecord{head: b"r".to_vec(), seq: [&fragment[..], b"AGATCGGAAG"].concat(), qual: None};
+        let rec2 = OwnedRecord{head: b"r".to_vec(), seq: [jseqio::reverse_complement(fragment), b"AGATCGGAAG".to_vec()].concat(), qual: None};
+        let (merged, overlap) = merge_pair(&rec1, &rec2, &options).unwrap();
+        assert_eq!(overlap, Overlap{offset: -10, len: 16, mismatches: 0});
+        assert_eq!(merged.seq, fragment);
+
+        assert_eq!(consensus_base(b'A', 30, b'A', 30), (b'A', 41));
+        assert_eq!(consensus_base(b'N', 30, b'C', 20), (b'C', 20));
+        let (b, q) = consensus_base(b'A', 30, b'C', 10);
+        assert_eq!(b, b'A');
+        assert!(q < 30);
+    }
+}
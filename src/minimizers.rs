@@ -0,0 +1,157 @@
+// (w,k)-minimizers and super-k-mers.
+//
+// The minimizer of a window of w consecutive k-mers is the smallest k-mer of the window
+// in the chosen order, the leftmost one in case of ties. A super-k-mer is a maximal run of
+// consecutive windows that have the same minimizer. It spans from the start of its first
+// window to the end of its last window, so consecutive super-k-mers overlap by w+k-2 bases.
+// k-mers with characters other than ACGT are skipped, and stretches of ACGT characters with
+// fewer than w k-mers have no minimizers.
+
+use std::collections::VecDeque;
+use std::io::Write;
+use jseqio::reader::DynamicFastXReader;
+use jseqio::record::Record;
+use crate::kmer::{KmerIter, KmerOrder, PackedKmer};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SuperKmer<T> {
+    pub start: usize,
+    pub end: usize, // Exclusive
+    pub minimizer_pos: usize,
+    pub minimizer: T, // In canonical mode, the orientation that is smaller in the order
+    pub reverse: bool, // True if the minimizer is the reverse complement of the sequence at minimizer_pos
+}
+
+// Returns the super-k-mers of the sequence in order. In canonical mode, each k-mer is
+// compared using the smaller of its two orientations.
+pub fn super_kmers<T: PackedKmer>(seq: &[u8], k: usize, w: usize, order: KmerOrder, canonical: bool) -> Vec<SuperKmer<T>> {
+    assert!(w > 0, "w must be positive");
+    let mut result = vec![];
+    let mut window = VecDeque::<(u128, usize, T, bool)>::new(); // (key, pos, k-mer, reverse) with increasing keys
+    let mut current: Option<SuperKmer<T>> = None;
+    let mut run_start = 0; // Position of the first k-mer of the current run of consecutive k-mers
+
+    for occ in KmerIter::<T>::new(seq, k) {
+        if window.back().is_none_or(|&(_, pos, _, _)| pos + 1 != occ.pos) {
+            // Skipped a non-ACGT character, so start a new run
+            result.extend(current.take());
+            window.clear();
+            run_start = occ.pos;
+        }
+
+        let fw_key = order.key(occ.forward);
+        let (key, kmer, reverse) = match canonical {
+            true => {
+                let rc_key = order.key(occ.reverse_complement);
+                if rc_key < fw_key { (rc_key, occ.reverse_complement, true) } else { (fw_key, occ.forward, false) }
+            }
+            false => (fw_key, occ.forward, false),
+        };
+        while window.back().is_some_and(|&(back_key, _, _, _)| back_key > key) {
+            window.pop_back();
+        }
+        window.push_back((key, occ.pos, kmer, reverse));
+
+        if occ.pos + 1 - run_start < w { continue } // The first window is not full yet
+        let window_start = occ.pos + 1 - w;
+        while window.front().unwrap().1 < window_start {
+            window.pop_front();
+        }
+
+        let &(_, minimizer_pos, minimizer, reverse) = window.front().unwrap();
+        match current.as_mut() {
+            Some(sk) if sk.minimizer_pos == minimizer_pos => sk.end = occ.pos + k,
+            _ => {
+                result.extend(current.take());
+                current = Some(SuperKmer{start: window_start, end: occ.pos + k, minimizer_pos, minimizer, reverse});
+            }
+        }
+    }
+    result.extend(current);
+    result
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MinimizerOutput {
+    Tsv, // Sequence name, position, minimizer and strand of each minimizer
+    SuperKmers, // The super-k-mers as fasta
+}
+
+// Computes the minimizers of all sequences in the input. Supports k up to 64.
+pub fn minimizers(reader: &mut DynamicFastXReader, k: usize, w: usize, order: KmerOrder, canonical: bool, output_type: MinimizerOutput, output: &mut impl Write) {
+    if k <= u64::MAX_K {
+        minimizers_packed::<u64>(reader, k, w, order, canonical, output_type, output);
+    } else if k <= u128::MAX_K {
+        minimizers_packed::<u128>(reader, k, w, order, canonical, output_type, output);
+    } else {
+        panic!("Error: k can be at most {}", u128::MAX_K);
+    }
+}
+
+fn minimizers_packed<T: PackedKmer>(reader: &mut DynamicFastXReader, k: usize, w: usize, order: KmerOrder, canonical: bool, output_type: MinimizerOutput, output: &mut impl Write) {
+    if output_type == MinimizerOutput::Tsv {
+        writeln!(output, "name\tposition\tminimizer\tstrand").unwrap();
+    }
+    while let Some(rec) = reader.read_next().unwrap() {
+        let name = String::from_utf8_lossy(rec.name());
+        for sk in super_kmers::<T>(rec.seq, k, w, order, canonical) {
+            let minimizer = String::from_utf8_lossy(&sk.minimizer.decode(k)).into_owned();
+            match output_type {
+                MinimizerOutput::Tsv => {
+                    writeln!(output, "{}\t{}\t{}\t{}", name, sk.minimizer_pos, minimizer, if sk.reverse { '-' } else { '+' }).unwrap();
+                }
+                MinimizerOutput::SuperKmers => {
+                    // Region in 1-based inclusive coordinates like in extract-region
+                    writeln!(output, ">{}:{}-{} minimizer={}", name, sk.start + 1, sk.end, minimizer).unwrap();
+                    output.write_all(&rec.seq[sk.start..sk.end]).unwrap();
+                    writeln!(output).unwrap();
+                }
+            }
+        }
+    }
+    output.flush().unwrap();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Minimizer position of every window by brute force
+    fn naive_minimizers(seq: &[u8], k: usize, w: usize, order: KmerOrder, canonical: bool) -> Vec<usize> {
+        let mut positions = vec![];
+        for start in 0..(seq.len() + 1).saturating_sub(w + k - 1) {
+            let keys: Option<Vec<u128>> = (start..start + w).map(|i| {
+                let fw = u64::encode(&seq[i..i+k])?;
+                let rc = u64::encode(&jseqio::reverse_complement(&seq[i..i+k]))?;
+                Some(if canonical { std::cmp::min(order.key(fw), order.key(rc)) } else { order.key(fw) })
+            }).collect();
+            if let Some(keys) = keys {
+                let min_key = keys.iter().min().unwrap();
+                positions.push(start + keys.iter().position(|x| x == min_key).unwrap());
+            }
+        }
+        positions
+    }
+
+    #[test]
+    fn test_super_kmers(){
+        let seq = b"ACGTTGCAAAGGTACCATGACGATCGNATCGGCTAGCTAGCTAGGATCGATTTACGATCGATCGANCGATCGACTAGCTAGCTCGATTTTTTTTTTTTGA";
+        for order in [KmerOrder::Lexicographic, KmerOrder::Hash] {
+            for canonical in [false, true] {
+                for (k, w) in [(1, 1), (3, 4), (5, 10), (7, 1)] {
+                    let sks = super_kmers::<u64>(seq, k, w, order, canonical);
+                    let mut expected = naive_minimizers(seq, k, w, order, canonical);
+                    expected.dedup();
+                    assert_eq!(sks.iter().map(|sk| sk.minimizer_pos).collect::<Vec<_>>(), expected);
+                    for sk in sks.iter() {
+                        assert!(sk.start <= sk.minimizer_pos && sk.minimizer_pos + k <= sk.end);
+                        assert!(sk.end - sk.start >= w + k - 1);
+                        let mut kmer = seq[sk.minimizer_pos..sk.minimizer_pos+k].to_vec();
+                        if sk.reverse { kmer = jseqio::reverse_complement(&kmer) }
+                        assert_eq!(sk.minimizer.decode(k), kmer);
+                    }
+                }
+            }
+        }
+    }
+}
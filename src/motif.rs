@@ -0,0 +1,146 @@
+// Matching of degenerate (IUPAC) nucleotide motifs with mismatches on both strands.
+
+// Bit mask of the nucleotides that an IUPAC code stands for: A = 1, C = 2, G = 4, T = 8.
+fn iupac_mask(c: u8) -> Option<u8> {
+    let mask = match c.to_ascii_uppercase() {
+        b'A' => 1,
+        b'C' => 2,
+        b'G' => 4,
+        b'T' | b'U' => 8,
+        b'R' => 1 | 4, // Purine
+        b'Y' => 2 | 8, // Pyrimidine
+        b'S' => 2 | 4,
+        b'W' => 1 | 8,
+        b'K' => 4 | 8,
+        b'M' => 1 | 2,
+        b'B' => 2 | 4 | 8, // Not A
+        b'D' => 1 | 4 | 8, // Not C
+        b'H' => 1 | 2 | 8, // Not G
+        b'V' => 1 | 2 | 4, // Not T
+        b'N' => 15,
+        _ => return None,
+    };
+    Some(mask)
+}
+
+// Mask of a base in the sequence. Characters other than ACGT get 0 and
+// only match N in the motif.
+fn base_mask(c: u8) -> u8 {
+    match c.to_ascii_uppercase() {
+        b'A' => 1,
+        b'C' => 2,
+        b'G' => 4,
+        b'T' | b'U' => 8,
+        _ => 0,
+    }
+}
+
+// Complement of a set of nucleotides: swaps A <-> T and C <-> G
+fn complement_mask(mask: u8) -> u8 {
+    ((mask & 1) << 3) | ((mask & 8) >> 3) | ((mask & 2) << 1) | ((mask & 4) >> 1)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MotifHit {
+    pub start: usize, // 0-based on the forward strand
+    pub end: usize, // Exclusive
+    pub reverse: bool, // True if the reverse complement of the motif matched
+    pub mismatches: usize,
+}
+
+pub struct Motif {
+    pub pattern: Vec<u8>,
+    masks: Vec<u8>,
+    rc_masks: Vec<u8>, // Masks of the reverse complement of the motif
+}
+
+impl Motif {
+    pub fn new(pattern: &[u8]) -> Result<Motif, String> {
+        if pattern.is_empty() {
+            return Err("Empty motif".to_owned());
+        }
+        let masks = pattern.iter().map(|&c| iupac_mask(c).ok_or(format!("Invalid IUPAC character '{}' in motif {}", c as char, String::from_utf8_lossy(pattern)))).collect::<Result<Vec<u8>, String>>()?;
+        let rc_masks = masks.iter().rev().map(|&m| complement_mask(m)).collect();
+        Ok(Motif{pattern: pattern.to_ascii_uppercase(), masks, rc_masks})
+    }
+
+    pub fn len(&self) -> usize {
+        self.masks.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.masks.is_empty()
+    }
+
+    // Number of mismatches of the masks against seq, or None if it exceeds the budget
+    fn mismatches(masks: &[u8], seq: &[u8], max_mismatches: usize) -> Option<usize> {
+        let mut mismatches = 0;
+        for (&m, &c) in masks.iter().zip(seq.iter()) {
+            if m != 15 && m & base_mask(c) == 0 {
+                mismatches += 1;
+                if mismatches > max_mismatches { return None }
+            }
+        }
+        Some(mismatches)
+    }
+
+    // Calls the callback for every match. If the callback returns false, the search stops.
+    fn for_each_hit(&self, seq: &[u8], max_mismatches: usize, both_strands: bool, mut callback: impl FnMut(MotifHit) -> bool) {
+        let m = self.len();
+        if seq.len() < m { return }
+        for start in 0..=seq.len() - m {
+            let window = &seq[start..start + m];
+            if let Some(mismatches) = Self::mismatches(&self.masks, window, max_mismatches) {
+                if !callback(MotifHit{start, end: start + m, reverse: false, mismatches}) { return }
+            }
+            if both_strands {
+                if let Some(mismatches) = Self::mismatches(&self.rc_masks, window, max_mismatches) {
+                    if !callback(MotifHit{start, end: start + m, reverse: true, mismatches}) { return }
+                }
+            }
+        }
+    }
+
+    // Returns all matches with at most max_mismatches mismatches. A palindromic motif is
+    // reported on both strands.
+    pub fn find_all(&self, seq: &[u8], max_mismatches: usize, both_strands: bool) -> Vec<MotifHit> {
+        let mut hits = vec![];
+        self.for_each_hit(seq, max_mismatches, both_strands, |hit| { hits.push(hit); true });
+        hits
+    }
+
+    pub fn is_found(&self, seq: &[u8], max_mismatches: usize, both_strands: bool) -> bool {
+        let mut found = false;
+        self.for_each_hit(seq, max_mismatches, both_strands, |_| { found = true; false });
+        found
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_motif_matching(){
+        let seq = b"TTGAATTCAAGGRCNNACGTT";
+        let motif = Motif::new(b"GAWTTC").unwrap(); // EcoRI-like with W
+        let hits = motif.find_all(seq, 0, true);
+        assert_eq!(hits, vec![
+            MotifHit{start: 2, end: 8, reverse: false, mismatches: 0},
+            MotifHit{start: 2, end: 8, reverse: true, mismatches: 0}, // Palindrome
+        ]);
+
+        let motif = Motif::new(b"CCTT").unwrap(); // Reverse complement AAGG is at 8
+        assert_eq!(motif.find_all(seq, 0, true), vec![MotifHit{start: 8, end: 12, reverse: true, mismatches: 0}]);
+        assert!(motif.find_all(seq, 0, false).is_empty());
+
+        let motif = Motif::new(b"ACGA").unwrap(); // ACGT with one mismatch
+        assert_eq!(motif.find_all(seq, 1, false), vec![MotifHit{start: 16, end: 20, reverse: false, mismatches: 1}]);
+        assert!(!motif.is_found(seq, 0, false));
+
+        // N in the sequence only matches N in the motif
+        assert!(Motif::new(b"NN").unwrap().is_found(b"NN", 0, false));
+        assert!(!Motif::new(b"AN").unwrap().is_found(b"NN", 0, false));
+        assert!(Motif::new(b"AXG").is_err());
+    }
+}
@@ -0,0 +1,375 @@
+// Pairing of mates in paired-end data.
+
+use std::collections::{HashMap, VecDeque};
+use jseqio::reader::DynamicFastXReader;
+use jseqio::record::OwnedRecord;
+use jseqio::writer::SeqRecordWriter;
+
+// The name that two mates share: the header up to the first whitespace, which drops
+// Illumina comment fields like "1:N:0:ATCACG", with a /1 or /2 suffix removed.
+pub fn mate_name(header: &[u8]) -> &[u8] {
+    let name = header.split(|&c| c == b' ' || c == b'\t').next().unwrap();
+    crate::strip_mate_suffix(name)
+}
+
+// Mate number 1 or 2 from a /1 or /2 suffix of the name, or from an Illumina comment field
+// like "2:N:0:ATCACG" after the name.
+pub fn mate_number(header: &[u8]) -> Option<usize> {
+    let mut fields = header.split(|&c| c == b' ' || c == b'\t');
+    let name = fields.next().unwrap();
+    match name {
+        [.., b'/', b'1'] => return Some(1),
+        [.., b'/', b'2'] => return Some(2),
+        _ => (),
+    }
+    match fields.next() {
+        Some([b'1', b':', ..]) => Some(1),
+        Some([b'2', b':', ..]) => Some(2),
+        _ => None,
+    }
+}
+
+// Number of reads after which a read whose mate has not been seen is taken to be a singleton
+pub const DEFAULT_PAIRING_WINDOW: u64 = 100_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct PairingReport {
+    pub n_pairs: u64,
+    pub n_orphans: u64,
+    pub max_pending: usize, // Largest number of reads held in memory while waiting for their mates
+}
+
+// Matches mates that arrive in any order. Records are held until their mate is seen or
+// until they are taken out with expire, so memory use is proportional to how far apart the
+// mates are in the input.
+#[derive(Default)]
+pub struct MatePairer {
+    pending: [HashMap<Vec<u8>, (u64, u64, OwnedRecord)>; 2], // Unpaired mates 1 and 2 by name, with arrival number and index among the same mates
+    arrivals: VecDeque<(u64, usize, Vec<u8>)>, // Arrival number, mate and name of the pending records, oldest first
+    n_added: u64,
+    n_added_mate: [u64; 2],
+    n_before_last_pair: [u64; 2], // Number of mates 1 and 2 up to and including those of the last pair
+}
+
+impl MatePairer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Adds a record as mate 1 (mate = 0) or mate 2 (mate = 1). Returns the pair (mate 1, mate 2)
+    // if the other mate has already been added.
+    pub fn add(&mut self, rec: OwnedRecord, mate: usize) -> Option<(OwnedRecord, OwnedRecord)> {
+        let name = mate_name(&rec.head).to_vec();
+        self.n_added += 1;
+        self.n_added_mate[mate] += 1;
+        match self.pending[1 - mate].remove(&name) {
+            Some((_, index, other)) => {
+                self.n_before_last_pair[mate] = self.n_added_mate[mate];
+                self.n_before_last_pair[1 - mate] = index + 1;
+                Some(if mate == 0 { (rec, other) } else { (other, rec) })
+            }
+            None => {
+                self.arrivals.push_back((self.n_added, mate, name.clone()));
+                if self.pending[mate].insert(name, (self.n_added, self.n_added_mate[mate] - 1, rec)).is_some() {
+                    eprintln!("Warning: duplicate mate {} name, keeping the later record", mate + 1);
+                }
+                None
+            }
+        }
+    }
+
+    // Adds a record from an interleaved file. The mate number is taken from a /1 or /2 suffix
+    // or an Illumina comment like "1:N:0:ATCACG". If neither is present, the first record
+    // with a name is mate 1 and the second is mate 2.
+    pub fn add_interleaved(&mut self, rec: OwnedRecord) -> Option<(OwnedRecord, OwnedRecord)> {
+        let mate = match mate_number(&rec.head) {
+            Some(m) => m - 1,
+            None => self.pending[0].contains_key(mate_name(&rec.head)) as usize,
+        };
+        self.add(rec, mate)
+    }
+
+    // The mate (0 or 1) whose input is behind when mates 1 and 2 come from separate inputs in
+    // mostly the same order. The mates added after the last pair are the lead of each input
+    // over the other, counted in pairs by weighting them with the fraction of the mates
+    // that have been paired, since the singletons among them do not move the input forward.
+    pub fn behind(&self) -> usize {
+        let lead = |mate: usize| (self.n_added_mate[mate] - self.n_before_last_pair[mate]) as u128;
+        // lead(0) * n_pairs / n_added_mate[0] compared to the same for mate 2, with 1 added
+        // to the counts to avoid dividing by zero. The number of pairs cancels out.
+        let [n1, n2] = self.n_added_mate.map(|n| n as u128 + 1);
+        (lead(0) * n2 > lead(1) * n1) as usize
+    }
+
+    // Removes and returns the pending records that were added more than window records ago,
+    // in the order they were added. Their mates are taken to be missing.
+    pub fn expire(&mut self, window: u64) -> Vec<OwnedRecord> {
+        let mut expired = vec![];
+        while let Some((arrival, mate, name)) = self.arrivals.front() {
+            if arrival + window > self.n_added { break }
+            // The record may have been paired or replaced by a duplicate since
+            if self.pending[*mate].get(name).is_some_and(|(a, _, _)| a == arrival) {
+                expired.push(self.pending[*mate].remove(name).unwrap().2);
+            }
+            self.arrivals.pop_front();
+        }
+        expired
+    }
+
+    pub fn n_pending(&self) -> usize {
+        self.pending[0].len() + self.pending[1].len()
+    }
+
+    // Returns the records whose mate was never seen, in the order they were added.
+    pub fn into_orphans(self) -> Vec<OwnedRecord> {
+        let [p1, p2] = self.pending;
+        let mut orphans: Vec<(u64, u64, OwnedRecord)> = p1.into_values().chain(p2.into_values()).collect();
+        orphans.sort_by_key(|(i, _, _)| *i);
+        orphans.into_iter().map(|(_, _, rec)| rec).collect()
+    }
+}
+
+// Returns an error message if the records are not mates. pair_idx is used in the message.
+pub fn check_mates(rec1: &OwnedRecord, rec2: &OwnedRecord, pair_idx: u64) -> Result<(), String> {
+    if mate_name(&rec1.head) != mate_name(&rec2.head) {
+        return Err(format!("mate names do not match in pair {}: {} and {}",
+            pair_idx + 1, String::from_utf8_lossy(&rec1.head), String::from_utf8_lossy(&rec2.head)));
+    }
+    Ok(())
+}
+
+// Reads the next pair from two files of first and second mates, or from one interleaved
+// file if input2 is None. Returns None at the end of the input.
+pub fn read_pair(input1: &mut DynamicFastXReader, input2: Option<&mut DynamicFastXReader>) -> Option<(OwnedRecord, OwnedRecord)> {
+    let rec1 = input1.read_next().unwrap().map(|r| r.to_owned());
+    let rec2 = match input2 {
+        Some(input) => input.read_next().unwrap().map(|r| r.to_owned()),
+        None => input1.read_next().unwrap().map(|r| r.to_owned()),
+    };
+    match (rec1, rec2) {
+        (Some(r1), Some(r2)) => Some((r1, r2)),
+        (None, None) => None,
+        (Some(_), None) => panic!("Error: missing the second mate of the last pair. The files have different numbers of reads, or the interleaved input has an odd number of reads."),
+        (None, Some(_)) => panic!("Error: file 1 has fewer records than file 2"),
+    }
+}
+
+pub(crate) const ODD_INTERLEAVED_ERROR: &str = "paired-end interleaved data has an odd number of sequences";
+
+// Reads the next group of records that are kept together: two consecutive records if
+// paired_interleaved is set, and one record otherwise. Returns None at the end of the input.
+pub fn read_group(input: &mut DynamicFastXReader, paired_interleaved: bool) -> Result<Option<Vec<OwnedRecord>>, Box<dyn std::error::Error>> {
+    let group_size = if paired_interleaved { 2 } else { 1 };
+    let mut group = Vec::<OwnedRecord>::with_capacity(group_size);
+    while group.len() < group_size {
+        match input.read_next()? {
+            Some(rec) => group.push(rec.to_owned()),
+            None => break,
+        }
+    }
+    match group.len() {
+        0 => Ok(None),
+        n if n < group_size => Err(ODD_INTERLEAVED_ERROR.into()),
+        _ => Ok(Some(group)),
+    }
+}
+
+// Interleaves the mates of two files. Without an orphan output, the files must list the
+// same pairs in the same order. With it, the mates are paired by name as in repair and the
+// reads whose mate is missing are written to the orphan output.
+pub fn interleave(input1: &mut DynamicFastXReader, input2: &mut DynamicFastXReader, output: &mut impl SeqRecordWriter, orphans: Option<&mut impl SeqRecordWriter>) -> PairingReport {
+    if orphans.is_some() {
+        let report = repair(input1, Some(input2), DEFAULT_PAIRING_WINDOW, |r1, r2| {
+            output.write_owned_record(r1).unwrap();
+            output.write_owned_record(r2).unwrap();
+        }, orphans);
+        output.flush().unwrap();
+        return report;
+    }
+
+    let mut report = PairingReport::default();
+    while let Some((rec1, rec2)) = read_pair(input1, Some(input2)) {
+        check_mates(&rec1, &rec2, report.n_pairs).unwrap_or_else(|e| panic!("Error: {}. Use --orphans to write unpaired reads to a separate file.", e));
+        output.write_owned_record(&rec1).unwrap();
+        output.write_owned_record(&rec2).unwrap();
+        report.n_pairs += 1;
+    }
+    output.flush().unwrap();
+    report
+}
+
+// Restores the pairing of reads by name, from two files of first and second mates or from
+// one interleaved file (input2 = None). Each pair is passed to write_pair as soon as both
+// mates have been seen. A read whose mate has not been seen within the next window reads
+// is taken to be a singleton, so memory use is bounded by the window. Singletons are
+// written to the singleton output, if given, in the order they were read. Of two files, the
+// one that is behind is read next, as estimated by MatePairer::behind.
+pub fn repair(input1: &mut DynamicFastXReader, mut input2: Option<&mut DynamicFastXReader>, window: u64, mut write_pair: impl FnMut(&OwnedRecord, &OwnedRecord), mut singletons: Option<&mut impl SeqRecordWriter>) -> PairingReport {
+    let mut report = PairingReport::default();
+    let mut pairer = MatePairer::new();
+    let mut at_end = [false, input2.is_none()];
+    let mut next = 0; // The file to read from
+    loop {
+        if at_end[next] {
+            next = 1 - next;
+            if at_end[next] { break }
+        }
+        let rec = match next {
+            0 => input1.read_next().unwrap().map(|r| r.to_owned()),
+            _ => input2.as_mut().unwrap().read_next().unwrap().map(|r| r.to_owned()),
+        };
+        let Some(rec) = rec else {
+            at_end[next] = true;
+            continue;
+        };
+
+        let pair = match input2.is_some() {
+            true => pairer.add(rec, next),
+            false => pairer.add_interleaved(rec),
+        };
+        if let Some((r1, r2)) = pair {
+            write_pair(&r1, &r2);
+            report.n_pairs += 1;
+        }
+        if input2.is_some() {
+            next = pairer.behind();
+        }
+
+        for rec in pairer.expire(window) {
+            if let Some(out) = singletons.as_mut() {
+                out.write_owned_record(&rec).unwrap();
+            }
+            report.n_orphans += 1;
+        }
+        report.max_pending = std::cmp::max(report.max_pending, pairer.n_pending());
+    }
+
+    let orphans = pairer.into_orphans();
+    report.n_orphans += orphans.len() as u64;
+    if let Some(out) = singletons {
+        for rec in orphans.iter() {
+            out.write_owned_record(rec).unwrap();
+        }
+        out.flush().unwrap();
+    }
+    report
+}
+
+// Splits interleaved pairs into two files. Without an orphan output, every two consecutive
+// records must be mates. With it, a record that is not followed by its mate is written to
+// the orphan output.
+pub fn deinterleave(input: &mut DynamicFastXReader, output1: &mut impl SeqRecordWriter, output2: &mut impl SeqRecordWriter, mut orphans: Option<&mut impl SeqRecordWriter>) -> PairingReport {
+    let mut report = PairingReport::default();
+    let mut first: Option<OwnedRecord> = None; // Waiting for its mate
+    while let Some(rec) = input.read_next().unwrap() {
+        let rec = rec.to_owned();
+        let Some(rec1) = first.take() else {
+            first = Some(rec);
+            continue;
+        };
+        match orphans.as_mut() {
+            Some(out) if mate_name(&rec1.head) != mate_name(&rec.head) => {
+                // The mate of rec1 is missing, and rec may start the next pair
+                out.write_owned_record(&rec1).unwrap();
+                report.n_orphans += 1;
+                first = Some(rec);
+            }
+            _ => {
+                check_mates(&rec1, &rec, report.n_pairs).unwrap_or_else(|e| panic!("Error: {}. Use --orphans to write unpaired reads to a separate file.", e));
+                output1.write_owned_record(&rec1).unwrap();
+                output2.write_owned_record(&rec).unwrap();
+                report.n_pairs += 1;
+            }
+        }
+    }
+
+    if let Some(rec) = first {
+        match orphans.as_mut() {
+            Some(out) => {
+                out.write_owned_record(&rec).unwrap();
+                report.n_orphans += 1;
+            }
+            None => panic!("Error: {}", ODD_INTERLEAVED_ERROR),
+        }
+    }
+    if let Some(out) = orphans.as_mut() {
+        out.flush().unwrap();
+    }
+    output1.flush().unwrap();
+    output2.flush().unwrap();
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rec(head: &[u8]) -> OwnedRecord {
+        OwnedRecord{head: head.to_vec(), seq: b"ACGT".to_vec(), qual: None}
+    }
+
+    #[test]
+    fn test_mate_pairer(){
+        assert_eq!(mate_name(b"read1/1"), b"read1");
+        assert_eq!(mate_name(b"read1 2:N:0:ATCACG"), b"read1");
+        assert_eq!(mate_name(b"read1/2\tcomment"), b"read1");
+
+        let mut pairer = MatePairer::new();
+        assert!(pairer.add(rec(b"a/1"), 0).is_none());
+        assert!(pairer.add(rec(b"b 2:N:0"), 1).is_none());
+        assert!(pairer.add(rec(b"c/1"), 0).is_none());
+        let (r1, r2) = pairer.add(rec(b"a/2"), 1).unwrap();
+        assert_eq!((r1.head.as_slice(), r2.head.as_slice()), (b"a/1".as_slice(), b"a/2".as_slice()));
+        let (r1, _) = pairer.add(rec(b"c/2"), 1).unwrap();
+        assert_eq!(r1.head, b"c/1");
+        assert_eq!(pairer.n_pending(), 1);
+        assert_eq!(pairer.into_orphans()[0].head, b"b 2:N:0");
+
+        // Mate numbers from the names, or from the order if the names do not tell
+        assert_eq!(mate_number(b"x/2 1:N:0"), Some(2));
+        assert_eq!(mate_number(b"x 1:N:0"), Some(1));
+        assert_eq!(mate_number(b"x"), None);
+        let mut pairer = MatePairer::new();
+        assert!(pairer.add_interleaved(rec(b"x 2:N:0")).is_none());
+        assert!(pairer.add_interleaved(rec(b"y")).is_none());
+        assert_eq!(pairer.add_interleaved(rec(b"x 1:N:0")).unwrap().0.head, b"x 1:N:0");
+        assert_eq!(pairer.add_interleaved(rec(b"y")).unwrap().1.head, b"y");
+    }
+
+    #[test]
+    fn test_repair_memory(){
+        // In-order files where every other first mate has lost its mate
+        let (mut fasta1, mut fasta2) = (String::new(), String::new());
+        for i in 0..20000 {
+            fasta1.push_str(&format!(">r{}/1\nACGT\n", i));
+            if i % 2 == 1 {
+                fasta2.push_str(&format!(">r{}/2\nACGT\n", i));
+            }
+        }
+        let mut input1 = DynamicFastXReader::new(std::io::Cursor::new(fasta1.into_bytes())).unwrap();
+        let mut input2 = DynamicFastXReader::new(std::io::Cursor::new(fasta2.into_bytes())).unwrap();
+        let mut singletons = jseqio::writer::FastXWriter::new(Vec::<u8>::new(), jseqio::FileType::FASTA);
+        let mut n_mismatched = 0;
+        let report = repair(&mut input1, Some(&mut input2), 100, |r1, r2| {
+            n_mismatched += (mate_name(&r1.head) != mate_name(&r2.head)) as usize;
+        }, Some(&mut singletons));
+        assert_eq!((report.n_pairs, report.n_orphans, n_mismatched), (10000, 10000, 0));
+        assert!(report.max_pending <= 100);
+        let singletons = String::from_utf8(singletons.into_inner().unwrap()).unwrap();
+        assert!(singletons.starts_with(">r0/1\nACGT\n>r2/1\nACGT\n>r4/1\n"));
+
+        // The same with the singletons in the second file
+        let (mut fasta1, mut fasta2) = (String::new(), String::new());
+        for i in 0..20000 {
+            if i % 3 == 0 {
+                fasta1.push_str(&format!(">r{}/1\nACGT\n", i));
+            }
+            fasta2.push_str(&format!(">r{}/2\nACGT\n", i));
+        }
+        let mut input1 = DynamicFastXReader::new(std::io::Cursor::new(fasta1.into_bytes())).unwrap();
+        let mut input2 = DynamicFastXReader::new(std::io::Cursor::new(fasta2.into_bytes())).unwrap();
+        let report = repair(&mut input1, Some(&mut input2), 100, |_, _| (), None::<&mut jseqio::writer::FastXWriter<Vec<u8>>>);
+        assert_eq!((report.n_pairs, report.n_orphans), (6667, 13333));
+        assert!(report.max_pending <= 100);
+    }
+}
@@ -0,0 +1,161 @@
+// Nested subsets of a sequence file of increasing sizes, for titration experiments.
+//
+// Every output is a subset of the next larger one. In prefix mode the subsets are prefixes
+// of the input. In random mode they are prefixes of a random permutation of the records,
+// so the subsets are random but still nested. A subset of size S in bases consists of the
+// records that start before S bases, so it has at least S bases unless the input is smaller.
+
+use jseqio::reader::DynamicFastXReader;
+use jseqio::writer::{DynamicFastXWriter, SeqRecordWriter};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PrefixUnit {
+    Reads,
+    Bases,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum PrefixSizes {
+    Ratio{ratio: f64, steps: Option<usize>}, // total, total / ratio, total / ratio^2, ... up to steps sizes or down to 1
+    Linear{step: u64, steps: Option<usize>}, // step, 2 * step, ... up to steps sizes or up to the total
+    List(Vec<u64>),
+}
+
+impl PrefixSizes {
+    pub fn needs_total(&self) -> bool {
+        matches!(self, PrefixSizes::Ratio{..} | PrefixSizes::Linear{steps: None, ..})
+    }
+
+    // Returns the distinct positive sizes in increasing order. The total size of the input
+    // must be given if needs_total() is true.
+    pub fn resolve(&self, total: Option<u64>) -> Vec<u64> {
+        let mut sizes: Vec<u64> = match self {
+            PrefixSizes::Ratio{ratio, steps} => {
+                let total = total.unwrap();
+                let sizes = (0..).map(|i| (total as f64 / ratio.powi(i)).floor() as u64).take_while(|&s| s > 0);
+                sizes.take(steps.unwrap_or(usize::MAX)).collect()
+            }
+            PrefixSizes::Linear{step, steps: Some(steps)} => (1..=*steps as u64).map(|i| i * step).collect(),
+            PrefixSizes::Linear{step, steps: None} => (1..=total.unwrap() / step).map(|i| i * step).collect(),
+            PrefixSizes::List(sizes) => sizes.clone(),
+        };
+        sizes.retain(|&s| s > 0);
+        sizes.sort_unstable();
+        sizes.dedup();
+        sizes
+    }
+}
+
+pub struct PrefixOptions {
+    pub sizes: PrefixSizes,
+    pub unit: PrefixUnit,
+    pub random: bool, // Random mode instead of prefix mode
+    pub seed: Option<u64>, // Seed for random mode. If not given, a seed is generated from the current time.
+    pub out_prefix: String,
+    pub gzip: bool,
+}
+
+// For each record, the number of units before it in the order in which records are taken:
+// the input order or a random permutation. Also returns the total number of units.
+fn random_keys(path: &str, unit: PrefixUnit, seed: Option<u64>) -> (Vec<u64>, u64) {
+    let mut reader = DynamicFastXReader::from_file(&path).unwrap();
+    let mut sizes = Vec::<u64>::new();
+    while let Some(rec) = reader.read_next().unwrap() {
+        sizes.push(match unit {
+            PrefixUnit::Reads => 1,
+            PrefixUnit::Bases => rec.seq.len() as u64,
+        });
+    }
+    let perm = crate::get_random_permutation(sizes.len(), seed);
+    let mut keys = vec![0_u64; sizes.len()];
+    let mut units_before = 0_u64;
+    for &idx in perm.iter() {
+        keys[idx] = units_before;
+        units_before += sizes[idx];
+    }
+    (keys, units_before)
+}
+
+// Total number of units in the file
+fn count_units(path: &str, unit: PrefixUnit) -> u64 {
+    let mut reader = DynamicFastXReader::from_file(&path).unwrap();
+    let mut total = 0_u64;
+    while let Some(rec) = reader.read_next().unwrap() {
+        total += match unit {
+            PrefixUnit::Reads => 1,
+            PrefixUnit::Bases => rec.seq.len() as u64,
+        };
+    }
+    total
+}
+
+// Writes the subsets of the file into files named <out_prefix>-<size>.<extension> in a single
+// pass. The file is read once more before that in random mode or if the sizes depend on
+// the total size of the input.
+pub fn write_prefixes(path: &str, options: &PrefixOptions) -> Vec<(String, u64, u64)> {
+    let (keys, random_total) = match options.random {
+        true => {
+            let (keys, total) = random_keys(path, options.unit, options.seed);
+            (Some(keys), Some(total))
+        }
+        false => (None, None),
+    };
+    let total = options.sizes.needs_total().then(|| random_total.unwrap_or_else(|| count_units(path, options.unit)));
+    let sizes = options.sizes.resolve(total);
+
+    let mut reader = DynamicFastXReader::from_file(&path).unwrap();
+    let extension = match (reader.filetype(), options.gzip) {
+        (jseqio::FileType::FASTA, false) => "fna",
+        (jseqio::FileType::FASTQ, false) => "fastq",
+        (jseqio::FileType::FASTA, true) => "fna.gz",
+        (jseqio::FileType::FASTQ, true) => "fastq.gz",
+    };
+    let filenames: Vec<String> = sizes.iter().map(|s| format!("{}-{}.{}", options.out_prefix, s, extension)).collect();
+    let mut writers: Vec<DynamicFastXWriter> = filenames.iter().map(|f| DynamicFastXWriter::new_to_file(f).unwrap()).collect();
+    let mut written = vec![(0_u64, 0_u64); sizes.len()]; // Reads and bases in each output
+
+    let mut rec_idx = 0_usize;
+    let mut units_before = 0_u64; // In prefix mode
+    while let Some(rec) = reader.read_next().unwrap() {
+        let key = match &keys {
+            Some(keys) => keys[rec_idx],
+            None => units_before,
+        };
+        if keys.is_none() && key >= *sizes.last().unwrap_or(&0) {
+            break; // All prefixes are done
+        }
+
+        // The record goes to all outputs with a size larger than the key
+        let first = sizes.partition_point(|&s| s <= key);
+        for i in first..sizes.len() {
+            writers[i].write(&rec).unwrap();
+            written[i].0 += 1;
+            written[i].1 += rec.seq.len() as u64;
+        }
+        units_before += match options.unit {
+            PrefixUnit::Reads => 1,
+            PrefixUnit::Bases => rec.seq.len() as u64,
+        };
+        rec_idx += 1;
+    }
+
+    for w in writers.iter_mut() {
+        w.flush().unwrap();
+    }
+    filenames.into_iter().zip(written).map(|(f, (reads, bases))| (f, reads, bases)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resolve_sizes(){
+        assert_eq!(PrefixSizes::Ratio{ratio: 2.0, steps: Some(4)}.resolve(Some(100)), vec![12, 25, 50, 100]);
+        assert_eq!(PrefixSizes::Ratio{ratio: 10.0, steps: Some(5)}.resolve(Some(100)), vec![1, 10, 100]);
+        assert_eq!(PrefixSizes::Ratio{ratio: 3.0, steps: None}.resolve(Some(100)), vec![1, 3, 11, 33, 100]);
+        assert_eq!(PrefixSizes::Linear{step: 30, steps: None}.resolve(Some(100)), vec![30, 60, 90]);
+        assert_eq!(PrefixSizes::Linear{step: 30, steps: Some(2)}.resolve(None), vec![30, 60]);
+        assert_eq!(PrefixSizes::List(vec![5, 0, 3, 5]).resolve(None), vec![3, 5]);
+    }
+}
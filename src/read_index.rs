@@ -0,0 +1,287 @@
+// On-disk index of the records of a FASTA or FASTQ file, for looking up reads by rank or
+// by name without reading the whole file.
+//
+// File format (all integers are little-endian u64):
+//   magic "SEQRIDX2"
+//   size of the indexed file in bytes, its modification time in nanoseconds since the
+//   epoch and a hash of its first and last 64 KiB (to detect stale indexes)
+//   file type: 0 = FASTA, 1 = FASTQ
+//   number of records n
+//   n byte offsets of the records, in file order
+//   n pairs (name start, rank) sorted by name, then rank
+//   the names concatenated in the sorted order
+// The name of the i-th pair spans from its name start to the name start of the next pair,
+// or to the end of the file for the last pair. The offsets refer to the uncompressed data,
+// so bgzip-compressed files are supported through their .gzi index.
+
+use std::io::{BufRead, BufReader, Read, Seek, SeekFrom, Write};
+use sha2::{Digest, Sha256};
+use jseqio::record::OwnedRecord;
+use crate::fasta_index::{open_seekable, ReadSeek};
+
+const MAGIC: &[u8; 8] = b"SEQRIDX2";
+const HEADER_LEN: u64 = 48;
+const FINGERPRINT_BLOCK: u64 = 1 << 16;
+
+pub fn read_index_path(path: &std::path::Path) -> std::path::PathBuf {
+    let mut p = path.as_os_str().to_owned();
+    p.push(".ridx");
+    p.into()
+}
+
+// Identifies the version of the indexed file, to check that an index belongs to it. The
+// hash catches files that are rewritten with the same size within the resolution of the
+// modification time.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FileSignature {
+    pub size: u64,
+    pub mtime: u64, // Nanoseconds since the epoch
+    pub fingerprint: u64, // Hash of the first and last FINGERPRINT_BLOCK bytes
+}
+
+impl FileSignature {
+    pub fn of(path: &std::path::Path) -> Result<Self, Box<dyn std::error::Error>> {
+        let metadata = std::fs::metadata(path)?;
+        let size = metadata.len();
+        let mtime = metadata.modified()?.duration_since(std::time::UNIX_EPOCH).map_or(0, |d| d.as_nanos() as u64);
+
+        let mut file = std::fs::File::open(path)?;
+        let mut hasher = Sha256::new();
+        let mut block = Vec::<u8>::new();
+        (&mut file).take(FINGERPRINT_BLOCK).read_to_end(&mut block)?;
+        hasher.update(&block);
+        if size > FINGERPRINT_BLOCK {
+            file.seek(SeekFrom::Start(std::cmp::max(size - FINGERPRINT_BLOCK, FINGERPRINT_BLOCK)))?;
+            block.clear();
+            file.read_to_end(&mut block)?;
+            hasher.update(&block);
+        }
+        let fingerprint = u64::from_le_bytes(hasher.finalize()[..8].try_into().unwrap());
+        Ok(FileSignature{size, mtime, fingerprint})
+    }
+}
+
+// Streams through the uncompressed data and writes the index.
+pub fn build_read_index<P: AsRef<std::path::Path>>(path: P) -> Result<(), Box<dyn std::error::Error>> {
+    let path = path.as_ref();
+    let mut input = BufReader::new(open_seekable(path)?);
+    let is_fastq = match input.fill_buf()?.first() {
+        Some(b'>') | None => false,
+        Some(b'@') => true,
+        Some(_) => return Err("File does not start with '>' or '@'".into()),
+    };
+
+    let mut offsets = Vec::<u64>::new();
+    let mut names = Vec::<(Vec<u8>, u64)>::new(); // (name, rank)
+    let mut offset = 0_u64;
+    let mut line_idx = 0_u64;
+    let mut line = Vec::<u8>::new();
+    loop {
+        line.clear();
+        let n = input.read_until(b'\n', &mut line)?;
+        if n == 0 { break }
+
+        // FASTQ records are four lines like in the sequential parser.
+        let is_header = if is_fastq { line_idx.is_multiple_of(4) } else { line[0] == b'>' };
+        if is_header {
+            let header = line[1..].strip_suffix(b"\n").unwrap_or(&line[1..]);
+            let header = header.strip_suffix(b"\r").unwrap_or(header);
+            let name = header.split(|c| *c == b' ').next().unwrap(); // Same as Record::name
+            names.push((name.to_vec(), offsets.len() as u64));
+            offsets.push(offset);
+        }
+        offset += n as u64;
+        line_idx += 1;
+    }
+    names.sort();
+
+    let mut out = std::io::BufWriter::new(std::fs::File::create(read_index_path(path))?);
+    out.write_all(MAGIC)?;
+    let signature = FileSignature::of(path)?;
+    for x in [signature.size, signature.mtime, signature.fingerprint] {
+        out.write_all(&x.to_le_bytes())?;
+    }
+    out.write_all(&(is_fastq as u64).to_le_bytes())?;
+    out.write_all(&(offsets.len() as u64).to_le_bytes())?;
+    for x in offsets.iter() {
+        out.write_all(&x.to_le_bytes())?;
+    }
+    let mut name_start = 0_u64;
+    for (name, rank) in names.iter() {
+        out.write_all(&name_start.to_le_bytes())?;
+        out.write_all(&rank.to_le_bytes())?;
+        name_start += name.len() as u64;
+    }
+    for (name, _) in names.iter() {
+        out.write_all(name)?;
+    }
+    out.flush()?;
+    Ok(())
+}
+
+pub struct ReadIndex {
+    file: BufReader<std::fs::File>,
+    pub n_records: u64,
+    pub filetype: jseqio::FileType,
+    blob_end: u64,
+}
+
+impl ReadIndex {
+
+    pub fn open<P: AsRef<std::path::Path>>(index_path: P, indexed_file: &FileSignature) -> Result<Self, Box<dyn std::error::Error>> {
+        let mut file = BufReader::new(std::fs::File::open(index_path)?);
+        let mut magic = [0u8; 8];
+        file.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err("Not a read index file".into());
+        }
+        let mut read_index = ReadIndex{file, n_records: 0, filetype: jseqio::FileType::FASTA, blob_end: 0};
+        let signature = FileSignature{size: read_index.read_u64()?, mtime: read_index.read_u64()?, fingerprint: read_index.read_u64()?};
+        if signature != *indexed_file {
+            return Err("The read index is out of date: the indexed file has changed".into());
+        }
+        if read_index.read_u64()? == 1 {
+            read_index.filetype = jseqio::FileType::FASTQ;
+        }
+        read_index.n_records = read_index.read_u64()?;
+        read_index.blob_end = read_index.file.seek(SeekFrom::End(0))?;
+        Ok(read_index)
+    }
+
+    fn read_u64(&mut self) -> Result<u64, Box<dyn std::error::Error>> {
+        let mut bytes = [0u8; 8];
+        self.file.read_exact(&mut bytes)?;
+        Ok(u64::from_le_bytes(bytes))
+    }
+
+    fn read_u64_at(&mut self, pos: u64) -> Result<u64, Box<dyn std::error::Error>> {
+        self.file.seek(SeekFrom::Start(pos))?;
+        self.read_u64()
+    }
+
+    pub fn offset_of_rank(&mut self, rank: u64) -> Result<Option<u64>, Box<dyn std::error::Error>> {
+        if rank >= self.n_records { return Ok(None) }
+        Ok(Some(self.read_u64_at(HEADER_LEN + 8 * rank)?))
+    }
+
+    // Returns the name and rank of the i-th record in name order
+    fn sorted_entry(&mut self, i: u64) -> Result<(Vec<u8>, u64), Box<dyn std::error::Error>> {
+        let table_start = HEADER_LEN + 8 * self.n_records;
+        let blob_start = table_start + 16 * self.n_records;
+        let name_start = self.read_u64_at(table_start + 16 * i)?;
+        let rank = self.read_u64()?;
+        let name_end = if i + 1 < self.n_records { self.read_u64_at(table_start + 16 * (i + 1))? } else { self.blob_end - blob_start };
+
+        let mut name = vec![0u8; (name_end - name_start) as usize];
+        self.file.seek(SeekFrom::Start(blob_start + name_start))?;
+        self.file.read_exact(&mut name)?;
+        Ok((name, rank))
+    }
+
+    // Returns the ranks of all records with the given name in file order, using binary search.
+    pub fn ranks_of_name(&mut self, name: &[u8]) -> Result<Vec<u64>, Box<dyn std::error::Error>> {
+        let (mut lo, mut hi) = (0_u64, self.n_records);
+        while lo < hi {
+            let mid = lo + (hi - lo) / 2;
+            if self.sorted_entry(mid)?.0.as_slice() < name {
+                lo = mid + 1;
+            } else {
+                hi = mid;
+            }
+        }
+
+        let mut ranks = vec![];
+        while lo < self.n_records {
+            let (entry_name, rank) = self.sorted_entry(lo)?;
+            if entry_name != name { break }
+            ranks.push(rank);
+            lo += 1;
+        }
+        Ok(ranks)
+    }
+}
+
+// A FASTA or FASTQ file opened for reading records by rank or by name
+pub struct IndexedReads {
+    source: Box<dyn ReadSeek>,
+    pub index: ReadIndex,
+}
+
+impl IndexedReads {
+
+    // Opens the file using the existing index. The index is built if it does not exist or
+    // does not match the file.
+    pub fn open<P: AsRef<std::path::Path>>(path: P) -> Result<Self, Box<dyn std::error::Error>> {
+        let path = path.as_ref();
+        let index_path = read_index_path(path);
+        let index = match ReadIndex::open(&index_path, &FileSignature::of(path)?) {
+            Ok(index) => index,
+            Err(e) => {
+                if index_path.exists() {
+                    eprintln!("Can not use index {}: {}", index_path.display(), e);
+                }
+                eprintln!("Building index {}", index_path.display());
+                build_read_index(path)?;
+                ReadIndex::open(&index_path, &FileSignature::of(path)?)?
+            }
+        };
+        Ok(IndexedReads{source: open_seekable(path)?, index})
+    }
+
+    fn read_at(&mut self, offset: u64) -> Result<OwnedRecord, Box<dyn std::error::Error>> {
+        self.source.seek(SeekFrom::Start(offset))?;
+        let mut reader = jseqio::reader::StaticFastXReader::new(BufReader::new(&mut self.source))?;
+        let rec = reader.read_next()?.ok_or("Read index points past the end of the file")?;
+        Ok(rec.to_owned())
+    }
+
+    pub fn get_by_rank(&mut self, rank: u64) -> Result<Option<OwnedRecord>, Box<dyn std::error::Error>> {
+        match self.index.offset_of_rank(rank)? {
+            Some(offset) => Ok(Some(self.read_at(offset)?)),
+            None => Ok(None),
+        }
+    }
+
+    // There may be multiple records with the same name, like in interleaved paired-end data
+    pub fn get_by_name(&mut self, name: &[u8]) -> Result<Vec<OwnedRecord>, Box<dyn std::error::Error>> {
+        let mut records = vec![];
+        for rank in self.index.ranks_of_name(name)? {
+            records.push(self.get_by_rank(rank)?.unwrap());
+        }
+        Ok(records)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_read_index(){
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("reads.fastq");
+        std::fs::write(&path, "@b 1\nACGT\n+\nIIII\n@a\nGG\n+\nII\n@c/1\nT\n+\n#\n@b 2\nCC\n+\nHH\n").unwrap();
+
+        let mut indexed = IndexedReads::open(&path).unwrap();
+        assert_eq!(indexed.index.n_records, 4);
+        assert_eq!(indexed.get_by_rank(2).unwrap().unwrap().head, b"c/1");
+        assert!(indexed.get_by_rank(4).unwrap().is_none());
+
+        let recs = indexed.get_by_name(b"b").unwrap();
+        assert_eq!(recs.iter().map(|r| r.seq.clone()).collect::<Vec<_>>(), vec![b"ACGT".to_vec(), b"CC".to_vec()]);
+        assert_eq!(indexed.get_by_name(b"c/1").unwrap()[0].qual.as_ref().unwrap(), b"#");
+        assert!(indexed.get_by_name(b"aa").unwrap().is_empty());
+        assert!(indexed.get_by_name(b"d").unwrap().is_empty());
+
+        // Changing the file makes the index stale, so it is rebuilt
+        std::fs::write(&path, "@b 1\nACGT\n+\nIIII\n").unwrap();
+        assert_eq!(IndexedReads::open(&path).unwrap().index.n_records, 1);
+
+        // Also when the file is rewritten with the same size and modification time
+        let mtime = std::fs::metadata(&path).unwrap().modified().unwrap();
+        std::fs::write(&path, "@abc\nGGGG\n+\nIIII\n").unwrap();
+        std::fs::File::options().write(true).open(&path).unwrap().set_modified(mtime).unwrap();
+        let mut indexed = IndexedReads::open(&path).unwrap();
+        assert_eq!(indexed.get_by_name(b"abc").unwrap()[0].seq, b"GGGG");
+    }
+}
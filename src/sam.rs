@@ -0,0 +1,356 @@
+// Reading of alignments from SAM and BAM files.
+//
+// The format is detected from the content: BAM files are BGZF-compressed and start with
+// the magic "BAM\1" after decompression. Anything else is read as SAM text, gzipped or not.
+// Only the fields needed for statistics are parsed. Sequences, qualities and tags are skipped.
+
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader, Read};
+use flate2::read::MultiGzDecoder;
+
+const CIGAR_OPS: &[u8; 9] = b"MIDNSHP=X"; // In the order of the BAM operation codes
+
+pub const FLAG_UNMAPPED: u16 = 0x4;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Reference {
+    pub name: String,
+    pub length: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Alignment {
+    pub qname: Vec<u8>,
+    pub flag: u16,
+    pub reference: Option<usize>, // Index in the reference list, None if RNAME is '*'
+    pub pos: u64, // 1-based leftmost position, 0 if not available
+    pub mapq: u8, // 255 if not available
+    pub cigar: Vec<(u32, u8)>, // (length, operation character). Empty if CIGAR is '*'.
+    pub tlen: i64,
+}
+
+impl Alignment {
+    // Number of reference bases covered by the alignment: the total length of the
+    // M, D, N, = and X operations.
+    pub fn reference_length(&self) -> u64 {
+        self.cigar.iter().filter(|(_, op)| b"MDN=X".contains(op)).map(|&(len, _)| len as u64).sum()
+    }
+}
+
+pub fn parse_cigar(cigar: &[u8]) -> Result<Vec<(u32, u8)>, Box<dyn std::error::Error>> {
+    if cigar == b"*" {
+        return Ok(vec![]);
+    }
+    let mut ops = vec![];
+    let mut len = 0_u32;
+    let mut has_digits = false;
+    for &c in cigar {
+        if c.is_ascii_digit() {
+            len = len.checked_mul(10).and_then(|x| x.checked_add((c - b'0') as u32)).ok_or("CIGAR operation length overflows")?;
+            has_digits = true;
+        } else if CIGAR_OPS.contains(&c) && has_digits {
+            ops.push((len, c));
+            len = 0;
+            has_digits = false;
+        } else {
+            return Err(format!("Invalid CIGAR string '{}'", String::from_utf8_lossy(cigar)).into());
+        }
+    }
+    if has_digits {
+        return Err(format!("Invalid CIGAR string '{}'", String::from_utf8_lossy(cigar)).into());
+    }
+    Ok(ops)
+}
+
+pub struct AlignmentReader {
+    input: Box<dyn BufRead>,
+    bam: bool,
+    references: Vec<Reference>,
+    reference_idx: HashMap<String, usize>,
+    line: Vec<u8>,
+    pending_line: bool, // The first alignment line of a SAM file was read with the header
+    line_number: u64,
+}
+
+impl AlignmentReader {
+    pub fn new(input: impl Read + 'static) -> Result<Self, Box<dyn std::error::Error>> {
+        let mut input: Box<dyn BufRead> = Box::new(BufReader::new(input));
+        if input.fill_buf()?.starts_with(&[0x1f, 0x8b]) {
+            input = Box::new(BufReader::new(MultiGzDecoder::new(input)));
+        }
+        let bam = input.fill_buf()?.starts_with(b"BAM\x01");
+        let mut reader = AlignmentReader{input, bam, references: vec![], reference_idx: HashMap::new(), line: vec![], pending_line: false, line_number: 0};
+        match bam {
+            true => reader.read_bam_header()?,
+            false => reader.read_sam_header()?,
+        }
+        Ok(reader)
+    }
+
+    pub fn from_file<P: AsRef<std::path::Path>>(path: P) -> Result<Self, Box<dyn std::error::Error>> {
+        Self::new(std::fs::File::open(path)?)
+    }
+
+    // The references of the header, and in SAM files without @SQ lines, the references
+    // seen so far.
+    pub fn references(&self) -> &[Reference] {
+        &self.references
+    }
+
+    fn add_reference(&mut self, name: String, length: u64) -> usize {
+        let idx = self.references.len();
+        self.reference_idx.insert(name.clone(), idx);
+        self.references.push(Reference{name, length});
+        idx
+    }
+
+    fn read_sam_header(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        while self.read_line()? {
+            if !self.line.starts_with(b"@") {
+                self.pending_line = true;
+                break;
+            }
+            if !self.line.starts_with(b"@SQ\t") { continue }
+            let line = String::from_utf8_lossy(&self.line).into_owned();
+            let field = |tag: &str| line.split('\t').find_map(|f| f.strip_prefix(tag));
+            let name = field("SN:").ok_or_else(|| format!("@SQ line without SN on line {}", self.line_number))?;
+            let length = field("LN:").and_then(|x| x.parse::<u64>().ok()).ok_or_else(|| format!("@SQ line without a valid LN on line {}", self.line_number))?;
+            self.add_reference(name.to_owned(), length);
+        }
+        Ok(())
+    }
+
+    // Reads the next line without the line terminator into self.line. Returns false at the end.
+    fn read_line(&mut self) -> Result<bool, Box<dyn std::error::Error>> {
+        self.line.clear();
+        if self.input.read_until(b'\n', &mut self.line)? == 0 {
+            return Ok(false);
+        }
+        self.line_number += 1;
+        while self.line.last().is_some_and(|&c| c == b'\n' || c == b'\r') {
+            self.line.pop();
+        }
+        Ok(true)
+    }
+
+    fn parse_sam_line(&mut self) -> Result<Alignment, Box<dyn std::error::Error>> {
+        let line_number = self.line_number;
+        let line = std::mem::take(&mut self.line);
+        let fields: Vec<&[u8]> = line.split(|&c| c == b'\t').collect();
+        if fields.len() < 11 {
+            return Err(format!("SAM line {} has {} fields instead of at least 11", line_number, fields.len()).into());
+        }
+        let number = |i: usize, name: &str| -> Result<i64, Box<dyn std::error::Error>> {
+            std::str::from_utf8(fields[i]).ok().and_then(|x| x.parse::<i64>().ok())
+                .ok_or_else(|| format!("Invalid {} '{}' on SAM line {}", name, String::from_utf8_lossy(fields[i]), line_number).into())
+        };
+        let in_range = |i: usize, name: &str, max: i64| -> Result<i64, Box<dyn std::error::Error>> {
+            let x = number(i, name)?;
+            if !(0..=max).contains(&x) {
+                return Err(format!("{} {} out of range 0..={} on SAM line {}", name, x, max, line_number).into());
+            }
+            Ok(x)
+        };
+        let flag = in_range(1, "FLAG", u16::MAX as i64)? as u16;
+        let pos = number(3, "POS")?;
+        let mapq = in_range(4, "MAPQ", u8::MAX as i64)? as u8;
+        let tlen = number(8, "TLEN")?;
+        let reference = match fields[2] {
+            b"*" => None,
+            name => {
+                let name = String::from_utf8_lossy(name).into_owned();
+                Some(match self.reference_idx.get(&name) {
+                    Some(&idx) => idx,
+                    None => self.add_reference(name, 0), // No @SQ line, so the length is not known
+                })
+            }
+        };
+        let cigar = parse_cigar(fields[5]).map_err(|e| format!("{} on SAM line {}", e, line_number))?;
+        let alignment = Alignment{qname: fields[0].to_vec(), flag, reference, pos: pos.max(0) as u64, mapq, cigar, tlen};
+        self.line = line;
+        Ok(alignment)
+    }
+
+    fn read_bam_header(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        let mut magic = [0_u8; 4];
+        self.input.read_exact(&mut magic)?;
+        let l_text = read_u32(&mut self.input)? as usize;
+        std::io::copy(&mut (&mut self.input).take(l_text as u64), &mut std::io::sink())?;
+        let n_ref = read_u32(&mut self.input)?;
+        for _ in 0..n_ref {
+            let l_name = read_u32(&mut self.input)? as usize;
+            let mut name = vec![0_u8; l_name];
+            self.input.read_exact(&mut name)?;
+            name.pop(); // NUL terminator
+            let length = read_u32(&mut self.input)? as u64;
+            self.add_reference(String::from_utf8_lossy(&name).into_owned(), length);
+        }
+        Ok(())
+    }
+
+    fn read_bam_record(&mut self) -> Result<Option<Alignment>, Box<dyn std::error::Error>> {
+        if self.input.fill_buf()?.is_empty() {
+            return Ok(None);
+        }
+        let block_size = read_u32(&mut self.input)? as usize;
+        let mut block = vec![0_u8; block_size];
+        self.input.read_exact(&mut block)?;
+        if block_size < 32 {
+            return Err(format!("BAM record of {} bytes is too short", block_size).into());
+        }
+
+        let i32_at = |i: usize| i32::from_le_bytes(block[i..i+4].try_into().unwrap());
+        let u16_at = |i: usize| u16::from_le_bytes(block[i..i+2].try_into().unwrap());
+        let ref_id = i32_at(0);
+        let pos = i32_at(4);
+        let l_read_name = block[8] as usize;
+        let mapq = block[9];
+        let n_cigar_op = u16_at(12) as usize;
+        let flag = u16_at(14);
+        let tlen = i32_at(28);
+        let cigar_start = 32 + l_read_name;
+        if block_size < cigar_start + 4 * n_cigar_op {
+            return Err("BAM record is shorter than its read name and CIGAR".into());
+        }
+        let qname = block[32..cigar_start].strip_suffix(b"\0").unwrap_or(&block[32..cigar_start]).to_vec();
+        let cigar = (0..n_cigar_op).map(|i| {
+            let x = u32::from_le_bytes(block[cigar_start + 4*i..cigar_start + 4*i + 4].try_into().unwrap());
+            (x >> 4, CIGAR_OPS.get((x & 0xf) as usize).copied().unwrap_or(b'?'))
+        }).collect();
+        let reference = match ref_id {
+            -1 => None,
+            id if id >= 0 && (id as usize) < self.references.len() => Some(id as usize),
+            id => return Err(format!("BAM record has an invalid reference id {}", id).into()),
+        };
+        Ok(Some(Alignment{qname, flag, reference, pos: (pos + 1).max(0) as u64, mapq, cigar, tlen: tlen as i64}))
+    }
+
+    pub fn read_next(&mut self) -> Result<Option<Alignment>, Box<dyn std::error::Error>> {
+        if self.bam {
+            return self.read_bam_record();
+        }
+        if self.pending_line {
+            self.pending_line = false;
+        } else {
+            loop {
+                if !self.read_line()? {
+                    return Ok(None);
+                }
+                if !self.line.is_empty() { break }
+            }
+        }
+        self.parse_sam_line().map(Some)
+    }
+}
+
+fn read_u32(input: &mut impl Read) -> Result<u32, Box<dyn std::error::Error>> {
+    let mut bytes = [0_u8; 4];
+    input.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+// Parses SAM flags given in decimal or in hexadecimal with a 0x prefix
+pub fn parse_flags(s: &str) -> Result<u16, String> {
+    let parsed = match s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+        Some(hex) => u16::from_str_radix(hex, 16),
+        None => s.parse::<u16>(),
+    };
+    parsed.map_err(|_| format!("invalid SAM flags '{}'", s))
+}
+
+// Selects alignments like samtools view -f, -F and a list of reference names.
+#[derive(Debug, Clone, Default)]
+pub struct AlignmentFilter {
+    pub require_flags: u16, // All of these flags must be set
+    pub exclude_flags: u16, // None of these flags may be set
+    pub references: Vec<String>, // If not empty, the alignment must be on one of these references
+}
+
+impl AlignmentFilter {
+    pub fn matches(&self, alignment: &Alignment, references: &[Reference]) -> bool {
+        if alignment.flag & self.require_flags != self.require_flags || alignment.flag & self.exclude_flags != 0 {
+            return false;
+        }
+        self.references.is_empty() || alignment.reference.is_some_and(|i| self.references.contains(&references[i].name))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AlignmentField {
+    Position,
+    MappingQuality,
+    TemplateLength, // Only from the leftmost mate (positive TLEN), so that each pair is counted once
+    AlignedLength, // Number of reference bases covered
+}
+
+impl AlignmentField {
+    // The value of the field, or None if it is not available in the alignment
+    pub fn value(&self, alignment: &Alignment) -> Option<i64> {
+        match self {
+            AlignmentField::Position => (alignment.pos > 0).then_some(alignment.pos as i64),
+            AlignmentField::MappingQuality => (alignment.mapq != 255).then_some(alignment.mapq as i64),
+            AlignmentField::TemplateLength => (alignment.tlen > 0).then_some(alignment.tlen),
+            AlignmentField::AlignedLength => (!alignment.cigar.is_empty()).then(|| alignment.reference_length() as i64),
+        }
+    }
+}
+
+// Values of the field in the alignments that pass the filter
+pub fn alignment_values(reader: &mut AlignmentReader, field: AlignmentField, filter: &AlignmentFilter) -> Result<Vec<i64>, Box<dyn std::error::Error>> {
+    let mut values = vec![];
+    while let Some(alignment) = reader.read_next()? {
+        if filter.matches(&alignment, reader.references()) {
+            values.extend(field.value(&alignment));
+        }
+    }
+    Ok(values)
+}
+
+pub fn print_alignment_histogram(reader: &mut AlignmentReader, field: AlignmentField, filter: &AlignmentFilter, options: &crate::histogram::HistogramOptions) -> Result<(), Box<dyn std::error::Error>> {
+    let values = alignment_values(reader, field, filter)?;
+    crate::histogram::print_histogram(values.into_iter(), options);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_sam(){
+        let sam = "@HD\tVN:1.6\n@SQ\tSN:chr1\tLN:100\n\
+            r1\t99\tchr1\t5\t60\t3S10M2D5M1I4M\t=\t40\t50\tACGT\tIIII\n\
+            r2\t4\t*\t0\t0\t*\t*\t0\t0\t*\t*\n\
+            r3\t0\tchr2\t7\t255\t10M\t*\t0\t0\t*\t*\n";
+        let mut reader = AlignmentReader::new(std::io::Cursor::new(sam)).unwrap();
+        let a = reader.read_next().unwrap().unwrap();
+        assert_eq!((a.flag, a.reference, a.pos, a.mapq, a.tlen), (99, Some(0), 5, 60, 50));
+        assert_eq!(a.cigar[0], (3, b'S'));
+        assert_eq!(a.reference_length(), 21);
+        let b = reader.read_next().unwrap().unwrap();
+        assert_eq!((b.reference, b.pos, AlignmentField::AlignedLength.value(&b)), (None, 0, None));
+        let c = reader.read_next().unwrap().unwrap();
+        assert_eq!(c.reference, Some(1)); // Added without an @SQ line
+        assert_eq!(reader.references()[1], Reference{name: "chr2".to_owned(), length: 0});
+        assert_eq!(AlignmentField::MappingQuality.value(&c), None);
+        assert!(reader.read_next().unwrap().is_none());
+
+        for bad in ["r4\t70000\tchr1\t5\t60\t*\t*\t0\t0\t*\t*\n", "r5\t0\tchr1\t5\t300\t*\t*\t0\t0\t*\t*\n", "r6\t-1\tchr1\t5\t60\t*\t*\t0\t0\t*\t*\n"] {
+            let mut reader = AlignmentReader::new(std::io::Cursor::new(bad)).unwrap();
+            assert!(reader.read_next().is_err());
+        }
+
+        let filter = AlignmentFilter{require_flags: 0, exclude_flags: FLAG_UNMAPPED, references: vec!["chr1".to_owned()]};
+        assert!(filter.matches(&a, reader.references()));
+        assert!(!filter.matches(&b, reader.references()));
+        assert!(!filter.matches(&c, reader.references()));
+
+        assert_eq!(parse_flags("0x904"), Ok(2308));
+        assert_eq!(parse_flags("16"), Ok(16));
+        assert!(parse_flags("0xg").is_err());
+
+        assert!(parse_cigar(b"10M5").is_err());
+        assert!(parse_cigar(b"M").is_err());
+        assert!(parse_cigar(b"4Q").is_err());
+    }
+}
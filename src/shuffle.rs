@@ -0,0 +1,178 @@
+// Shuffling of sequences into a uniformly random order with bounded memory.
+//
+// Sequences are read into memory until the memory limit is reached. If the whole input fits,
+// it is shuffled in memory. Otherwise every sequence is written to one of N_BUCKETS temporary
+// files chosen uniformly at random, and then each bucket in turn is shuffled in memory and
+// written out. Buckets that are still larger than the memory limit are scattered again in the
+// same way. Scattering at random and shuffling the buckets uniformly gives a uniformly random
+// permutation. The random numbers come from the same seeded generator as in
+// get_random_permutation, so the output is determined by the seed, the input and the memory limit.
+
+use std::io::{BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use rand::Rng;
+use rand::seq::SliceRandom;
+use rand_chacha::ChaCha20Rng;
+use jseqio::reader::DynamicFastXReader;
+use jseqio::record::OwnedRecord;
+use jseqio::writer::SeqRecordWriter;
+use crate::sort::{estimated_memory, read_records, write_records};
+
+const N_BUCKETS: usize = 256;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ShuffleOptions {
+    pub seed: Option<u64>, // Generated from the current time if None
+    pub paired_interleaved: bool, // Mates stay together
+    pub max_memory: usize, // Bytes of sequences held in memory at a time, approximately
+    pub temp_dir: Option<PathBuf>, // Directory for the buckets. The system default if None.
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct ShuffleReport {
+    pub n_seqs: u64,
+    pub n_buckets: usize, // Non-empty buckets written to disk, 0 if everything fit in memory
+}
+
+struct Buckets {
+    dir: tempfile::TempDir,
+    writers: Vec<BufWriter<std::fs::File>>,
+    memory: Vec<usize>, // Estimated memory of the records in each bucket
+    n_groups: Vec<usize>,
+}
+
+impl Buckets {
+    fn new(parent: Option<&Path>) -> std::io::Result<Self> {
+        let dir = match parent {
+            Some(parent) => tempfile::tempdir_in(parent)?,
+            None => tempfile::tempdir()?,
+        };
+        let writers = (0..N_BUCKETS).map(|i| Ok(BufWriter::new(std::fs::File::create(Self::path(&dir, i))?))).collect::<std::io::Result<Vec<_>>>()?;
+        Ok(Buckets{dir, writers, memory: vec![0; N_BUCKETS], n_groups: vec![0; N_BUCKETS]})
+    }
+
+    fn path(dir: &tempfile::TempDir, bucket: usize) -> PathBuf {
+        dir.path().join(format!("bucket_{}", bucket))
+    }
+
+    fn write(&mut self, group: &[OwnedRecord], rng: &mut ChaCha20Rng) -> std::io::Result<()> {
+        let bucket = rng.random_range(0..N_BUCKETS);
+        write_records(&mut self.writers[bucket], group)?;
+        self.memory[bucket] += estimated_memory(group);
+        self.n_groups[bucket] += 1;
+        Ok(())
+    }
+}
+
+fn write_groups(groups: &[Vec<OwnedRecord>], output: &mut impl SeqRecordWriter) -> Result<(), Box<dyn std::error::Error>> {
+    for rec in groups.iter().flatten() {
+        output.write_owned_record(rec)?;
+    }
+    Ok(())
+}
+
+// Writes out the buckets in order, each shuffled
+fn shuffle_buckets(buckets: Buckets, output: &mut impl SeqRecordWriter, max_memory: usize, rng: &mut ChaCha20Rng, report: &mut ShuffleReport) -> Result<(), Box<dyn std::error::Error>> {
+    let Buckets{dir, writers, memory, n_groups} = buckets;
+    for writer in writers {
+        writer.into_inner().map_err(|e| e.into_error())?.flush()?;
+    }
+    report.n_buckets += n_groups.iter().filter(|&&n| n > 0).count();
+
+    for bucket in 0..N_BUCKETS {
+        let path = Buckets::path(&dir, bucket);
+        let mut reader = BufReader::new(std::fs::File::open(&path)?);
+        // A bucket with a single group is shuffled already, however large it is
+        if memory[bucket] > max_memory && n_groups[bucket] > 1 {
+            let mut sub_buckets = Buckets::new(Some(dir.path()))?;
+            while let Some(group) = read_records(&mut reader)? {
+                sub_buckets.write(&group, rng)?;
+            }
+            std::fs::remove_file(&path)?;
+            shuffle_buckets(sub_buckets, output, max_memory, rng, report)?;
+        } else {
+            let mut groups = Vec::<Vec<OwnedRecord>>::with_capacity(n_groups[bucket]);
+            while let Some(group) = read_records(&mut reader)? {
+                groups.push(group);
+            }
+            std::fs::remove_file(&path)?;
+            groups.shuffle(rng);
+            write_groups(&groups, output)?;
+        }
+    }
+    Ok(())
+}
+
+pub fn shuffle_reads(input: &mut DynamicFastXReader, output: &mut impl SeqRecordWriter, options: &ShuffleOptions) -> Result<ShuffleReport, Box<dyn std::error::Error>> {
+    let mut rng = crate::seeded_rng(options.seed);
+    let mut report = ShuffleReport::default();
+    let mut groups = Vec::<Vec<OwnedRecord>>::new();
+    let mut memory = 0;
+    let mut buckets: Option<Buckets> = None; // Created when the input does not fit in memory
+    while let Some(group) = crate::paired::read_group(input, options.paired_interleaved)? {
+        report.n_seqs += group.len() as u64;
+
+        match buckets.as_mut() {
+            Some(buckets) => buckets.write(&group, &mut rng)?,
+            None => {
+                memory += estimated_memory(&group);
+                groups.push(group);
+                if memory > options.max_memory {
+                    let mut new_buckets = Buckets::new(options.temp_dir.as_deref())?;
+                    for group in groups.drain(..) {
+                        new_buckets.write(&group, &mut rng)?;
+                    }
+                    buckets = Some(new_buckets);
+                }
+            }
+        }
+    }
+
+    match buckets {
+        Some(buckets) => shuffle_buckets(buckets, output, options.max_memory, &mut rng, &mut report)?,
+        None => {
+            groups.shuffle(&mut rng);
+            write_groups(&groups, output)?;
+        }
+    }
+    output.flush()?;
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn shuffle_file(path: &str, options: &ShuffleOptions) -> (Vec<String>, ShuffleReport) {
+        let mut reader = DynamicFastXReader::from_file(&path).unwrap();
+        let mut writer = jseqio::writer::FastXWriter::new(Vec::<u8>::new(), jseqio::FileType::FASTQ);
+        let report = shuffle_reads(&mut reader, &mut writer, options).unwrap();
+        let output = String::from_utf8(writer.into_inner().unwrap()).unwrap();
+        (output.lines().step_by(4).map(|l| l.split(' ').next().unwrap().to_owned()).collect(), report)
+    }
+
+    #[test]
+    fn test_shuffle(){
+        let input: Vec<String> = (1..=10).map(|i| format!("@SRR403017.{}", i)).collect();
+        for max_memory in [0, 1 << 20] {
+            for paired_interleaved in [false, true] {
+                let options = ShuffleOptions{seed: Some(7), paired_interleaved, max_memory, temp_dir: None};
+                let (names, report) = shuffle_file("tests/data/reads.fastq", &options);
+                assert_eq!(report.n_seqs, 10);
+                assert_eq!(report.n_buckets > 0, max_memory == 0);
+                assert_eq!(shuffle_file("tests/data/reads.fastq", &options).0, names); // Same seed, same order
+                assert_ne!(names, input);
+
+                let mut sorted = names.clone();
+                sorted.sort_by(|a, b| crate::sort::natural_cmp(a.as_bytes(), b.as_bytes()));
+                assert_eq!(sorted, input);
+                if paired_interleaved {
+                    for pair in names.chunks(2) {
+                        let first: usize = pair[0][11..].parse().unwrap();
+                        assert!(first % 2 == 1 && pair[1] == format!("@SRR403017.{}", first + 1));
+                    }
+                }
+            }
+        }
+    }
+}
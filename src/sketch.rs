@@ -0,0 +1,261 @@
+// MinHash sketches of the canonical k-mer sets of sequence files, for estimating the
+// similarity of large numbers of datasets without storing the full k-mer sets.
+//
+// Two kinds of sketches are supported:
+//   bottom-k: the s smallest distinct k-mer hashes (classic MinHash, as in Mash)
+//   scaled:   all distinct k-mer hashes below 2^64 / scale (FracMinHash, as in sourmash)
+// Bottom-k sketches have a fixed size, while scaled sketches grow with the number of
+// distinct k-mers, which makes containment estimates accurate also for sets of very
+// different sizes.
+//
+// File format (all integers are little-endian u64):
+//   magic "SEQSKCH1"
+//   number of sketches
+//   for each sketch:
+//     k
+//     kind: 0 = bottom-k, 1 = scaled
+//     sketch size s for bottom-k, scale for scaled
+//     length of the name in bytes, followed by the name in UTF-8
+//     number of hashes n, followed by the n hashes in increasing order
+
+use std::collections::BTreeSet;
+use std::io::{BufReader, BufWriter, Read, Write};
+use jseqio::reader::DynamicFastXReader;
+use crate::kmer::{hash_kmer, KmerIter, PackedKmer};
+
+const MAGIC: &[u8; 8] = b"SEQSKCH1";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SketchKind {
+    BottomK(usize), // Sketch size
+    Scaled(u64), // Keep hashes below 2^64 / scale
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Sketch {
+    pub name: String,
+    pub k: usize,
+    pub kind: SketchKind,
+    pub hashes: Vec<u64>, // Sorted and distinct
+}
+
+impl Sketch {
+
+    // Sketches the canonical k-mers of the input. k-mers with characters other than ACGT are skipped.
+    pub fn build(name: &str, reader: &mut DynamicFastXReader, k: usize, kind: SketchKind) -> Sketch {
+        if k <= u64::MAX_K {
+            Self::build_packed::<u64>(name, reader, k, kind)
+        } else if k <= u128::MAX_K {
+            Self::build_packed::<u128>(name, reader, k, kind)
+        } else {
+            panic!("Error: k can be at most {}", u128::MAX_K);
+        }
+    }
+
+    fn build_packed<T: PackedKmer>(name: &str, reader: &mut DynamicFastXReader, k: usize, kind: SketchKind) -> Sketch {
+        let mut hashes = BTreeSet::<u64>::new();
+        while let Some(rec) = reader.read_next().unwrap() {
+            for occ in KmerIter::<T>::new(rec.seq, k) {
+                let h = hash_kmer(occ.canonical());
+                match kind {
+                    SketchKind::BottomK(size) => {
+                        if hashes.len() < size || h < *hashes.last().unwrap() {
+                            hashes.insert(h);
+                            if hashes.len() > size {
+                                hashes.pop_last();
+                            }
+                        }
+                    }
+                    SketchKind::Scaled(scale) => {
+                        if h <= u64::MAX / scale {
+                            hashes.insert(h);
+                        }
+                    }
+                }
+            }
+        }
+        Sketch{name: name.to_owned(), k, kind, hashes: hashes.into_iter().collect()}
+    }
+
+    // Largest hash value that the sketch is complete up to: all k-mers of the input with
+    // a hash at most this are in the sketch.
+    fn max_hash(&self) -> u64 {
+        match self.kind {
+            SketchKind::BottomK(size) if self.hashes.len() >= size => *self.hashes.last().unwrap(),
+            SketchKind::BottomK(_) => u64::MAX, // All k-mers of the input fit in the sketch
+            SketchKind::Scaled(scale) => u64::MAX / scale,
+        }
+    }
+
+    // Estimates the similarity from the hashes up to the largest hash that both sketches
+    // are complete up to. For two scaled sketches this uses the larger scale, and for two
+    // bottom-k sketches it is the bottom-k sketch of the union as in Mash.
+    pub fn compare(&self, other: &Sketch) -> Result<SketchComparison, Box<dyn std::error::Error>> {
+        if self.k != other.k {
+            return Err(format!("Can not compare sketches with different k: {} has k = {} and {} has k = {}", self.name, self.k, other.name, other.k).into());
+        }
+        let max_hash = std::cmp::min(self.max_hash(), other.max_hash());
+        let a = &self.hashes[..self.hashes.partition_point(|&h| h <= max_hash)];
+        let b = &other.hashes[..other.hashes.partition_point(|&h| h <= max_hash)];
+
+        // Merge the sorted lists
+        let (mut i, mut j, mut shared) = (0, 0, 0);
+        while i < a.len() && j < b.len() {
+            match a[i].cmp(&b[j]) {
+                std::cmp::Ordering::Less => i += 1,
+                std::cmp::Ordering::Greater => j += 1,
+                std::cmp::Ordering::Equal => { shared += 1; i += 1; j += 1; }
+            }
+        }
+        Ok(SketchComparison{k: self.k, size1: a.len(), size2: b.len(), shared})
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SketchComparison {
+    pub k: usize,
+    pub size1: usize, // Number of hashes of the first sketch used in the comparison
+    pub size2: usize,
+    pub shared: usize,
+}
+
+impl SketchComparison {
+    pub fn jaccard(&self) -> f64 {
+        let union = self.size1 + self.size2 - self.shared;
+        if union == 0 { return 0.0 }
+        self.shared as f64 / union as f64
+    }
+
+    // Estimated fraction of the k-mers of the first set contained in the second
+    pub fn containment_1_in_2(&self) -> f64 {
+        if self.size1 == 0 { return 0.0 }
+        self.shared as f64 / self.size1 as f64
+    }
+
+    // Estimated fraction of the k-mers of the second set contained in the first
+    pub fn containment_2_in_1(&self) -> f64 {
+        if self.size2 == 0 { return 0.0 }
+        self.shared as f64 / self.size2 as f64
+    }
+
+    // The Mash distance, an estimate of the per-base mutation rate between the sequences
+    // under a simple evolutionary model. It is 1 if no hashes are shared.
+    pub fn mash_distance(&self) -> f64 {
+        let j = self.jaccard();
+        if j == 0.0 { return 1.0 }
+        if j == 1.0 { return 0.0 }
+        -(2.0 * j / (1.0 + j)).ln() / self.k as f64
+    }
+}
+
+pub fn write_sketches<P: AsRef<std::path::Path>>(path: P, sketches: &[Sketch]) -> Result<(), Box<dyn std::error::Error>> {
+    let mut out = BufWriter::new(std::fs::File::create(path)?);
+    out.write_all(MAGIC)?;
+    out.write_all(&(sketches.len() as u64).to_le_bytes())?;
+    for sketch in sketches {
+        let (kind, param) = match sketch.kind {
+            SketchKind::BottomK(size) => (0_u64, size as u64),
+            SketchKind::Scaled(scale) => (1_u64, scale),
+        };
+        for x in [sketch.k as u64, kind, param, sketch.name.len() as u64] {
+            out.write_all(&x.to_le_bytes())?;
+        }
+        out.write_all(sketch.name.as_bytes())?;
+        out.write_all(&(sketch.hashes.len() as u64).to_le_bytes())?;
+        for h in sketch.hashes.iter() {
+            out.write_all(&h.to_le_bytes())?;
+        }
+    }
+    out.flush()?;
+    Ok(())
+}
+
+fn read_u64(input: &mut impl Read) -> Result<u64, Box<dyn std::error::Error>> {
+    let mut bytes = [0u8; 8];
+    input.read_exact(&mut bytes)?;
+    Ok(u64::from_le_bytes(bytes))
+}
+
+pub fn read_sketches<P: AsRef<std::path::Path>>(path: P) -> Result<Vec<Sketch>, Box<dyn std::error::Error>> {
+    let mut input = BufReader::new(std::fs::File::open(path)?);
+    let mut magic = [0u8; 8];
+    input.read_exact(&mut magic)?;
+    if &magic != MAGIC {
+        return Err("Not a sketch file".into());
+    }
+
+    let n_sketches = read_u64(&mut input)?;
+    let mut sketches = vec![];
+    for _ in 0..n_sketches {
+        let k = read_u64(&mut input)? as usize;
+        let kind = match (read_u64(&mut input)?, read_u64(&mut input)?) {
+            (0, size) => SketchKind::BottomK(size as usize),
+            (1, scale) => SketchKind::Scaled(scale),
+            (kind, _) => return Err(format!("Unknown sketch kind {}", kind).into()),
+        };
+        let mut name = vec![0u8; read_u64(&mut input)? as usize];
+        input.read_exact(&mut name)?;
+        let n_hashes = read_u64(&mut input)?;
+        let hashes = (0..n_hashes).map(|_| read_u64(&mut input)).collect::<Result<Vec<u64>, _>>()?;
+        sketches.push(Sketch{name: String::from_utf8(name)?, k, kind, hashes});
+    }
+    Ok(sketches)
+}
+
+// Prints all pairwise comparisons of the sketches as TSV, one pair per line.
+pub fn sketch_compare(sketches: &[Sketch], n_threads: usize, output: &mut impl Write) -> Result<(), Box<dyn std::error::Error>> {
+    let pairs: Vec<(usize, usize)> = (0..sketches.len()).flat_map(|i| (i+1..sketches.len()).map(move |j| (i, j))).collect();
+    let comparisons = crate::parallel_map(&pairs, n_threads, |&(i, j)| sketches[i].compare(&sketches[j]).map_err(|e| e.to_string()));
+
+    writeln!(output, "name1\tname2\tshared_hashes\tjaccard\tcontainment1in2\tcontainment2in1\tmash_distance")?;
+    for (&(i, j), c) in pairs.iter().zip(comparisons) {
+        let c = c?;
+        writeln!(output, "{}\t{}\t{}\t{:.6}\t{:.6}\t{:.6}\t{:.6}", sketches[i].name, sketches[j].name, c.shared, c.jaccard(), c.containment_1_in_2(), c.containment_2_in_1(), c.mash_distance())?;
+    }
+    output.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{Rng, SeedableRng};
+
+    fn sketch_of(seq: &[u8], k: usize, kind: SketchKind) -> Sketch {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("seq.fna");
+        let mut fasta = b">seq\n".to_vec();
+        fasta.extend_from_slice(seq);
+        fasta.push(b'\n');
+        std::fs::write(&path, fasta).unwrap();
+        Sketch::build("seq", &mut DynamicFastXReader::from_file(&path).unwrap(), k, kind)
+    }
+
+    #[test]
+    fn test_sketch_estimates(){
+        let mut rng = rand_chacha::ChaCha20Rng::seed_from_u64(123);
+        let seq: Vec<u8> = (0..20000).map(|_| b"ACGT"[rng.random_range(0..4)]).collect();
+        let half = &seq[..10000];
+
+        // Containment of the first half in the whole sequence is 1 and the Jaccard index is about 1/2
+        for kind in [SketchKind::BottomK(1000), SketchKind::Scaled(10)] {
+            let s1 = sketch_of(half, 21, kind);
+            let s2 = sketch_of(&seq, 21, kind);
+            let c = s1.compare(&s2).unwrap();
+            assert_eq!(c.containment_1_in_2(), 1.0);
+            assert!((c.jaccard() - 0.5).abs() < 0.1, "{:?}", c);
+            assert!(s1.compare(&s1).unwrap().mash_distance() == 0.0);
+        }
+
+        // Bottom-k sketches of small inputs are exact
+        let s = sketch_of(b"ACGTTGCANNACGGTACC", 3, SketchKind::BottomK(100));
+        assert_eq!(s.hashes.len(), 7);
+        assert!(s.compare(&sketch_of(b"ACGTT", 4, SketchKind::BottomK(100))).is_err());
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("sketches.bin");
+        let sketches = vec![s, sketch_of(half, 31, SketchKind::Scaled(100))];
+        write_sketches(&path, &sketches).unwrap();
+        assert_eq!(read_sketches(&path).unwrap(), sketches);
+    }
+}
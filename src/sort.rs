@@ -0,0 +1,372 @@
+// Sorting of sequences with an external merge sort.
+//
+// Sequences are read into memory until the memory limit is reached, and each full batch is
+// sorted and written to a temporary file as a sorted run. The runs are then merged, in several
+// passes if there are more than MAX_MERGE_FAN_IN of them. If all sequences fit in memory,
+// nothing is written to disk. Ties are broken by the input order, so the sort is stable.
+
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use jseqio::reader::DynamicFastXReader;
+use jseqio::record::{OwnedRecord, Record};
+use jseqio::writer::SeqRecordWriter;
+use crate::gc::BaseCounts;
+
+// Estimated memory use of a record in addition to its bytes
+const RECORD_OVERHEAD: usize = 128;
+
+// Largest number of runs merged at once, to stay within the limit of open files
+const MAX_MERGE_FAN_IN: usize = 64;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SortKey {
+    Length,
+    Name{natural: bool}, // The name is the header up to the first whitespace
+    Sequence,
+    Gc, // Sequences without ACGT characters come first
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SortOptions {
+    pub key: SortKey,
+    pub reverse: bool, // Descending order. Ties are still in input order.
+    pub paired_interleaved: bool, // Mates stay together, ordered by the key of the first mate
+    pub max_memory: usize, // Bytes of sequences held in memory at a time, approximately
+    pub temp_dir: Option<PathBuf>, // Directory for the sorted runs. The system default if None.
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct SortReport {
+    pub n_seqs: u64,
+    pub n_runs: usize, // Sorted runs written to disk, 0 if everything fit in memory
+}
+
+// Compares strings so that runs of digits are compared as numbers, so that read2 comes
+// before read10. Numbers that are equal but have a different number of leading zeros, like
+// 7 and 007, are ordered lexically as a last resort so that the order is total.
+pub fn natural_cmp(a: &[u8], b: &[u8]) -> Ordering {
+    let (mut i, mut j) = (0, 0);
+    while i < a.len() && j < b.len() {
+        if a[i].is_ascii_digit() && b[j].is_ascii_digit() {
+            let a_end = i + a[i..].iter().take_while(|c| c.is_ascii_digit()).count();
+            let b_end = j + b[j..].iter().take_while(|c| c.is_ascii_digit()).count();
+            let a_num = &a[i + a[i..a_end].iter().take_while(|&&c| c == b'0').count()..a_end];
+            let b_num = &b[j + b[j..b_end].iter().take_while(|&&c| c == b'0').count()..b_end];
+            let order = a_num.len().cmp(&b_num.len()).then(a_num.cmp(b_num));
+            if order != Ordering::Equal { return order }
+            (i, j) = (a_end, b_end);
+        } else {
+            if a[i] != b[j] { return a[i].cmp(&b[j]) }
+            (i, j) = (i + 1, j + 1);
+        }
+    }
+    (a.len() - i).cmp(&(b.len() - j)).then(a.cmp(b))
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum SortValue {
+    Number(u64),
+    Gc(Option<u64>), // Bits of the GC percentage, which order like the percentage because it is not negative
+    Lexical(Vec<u8>),
+    Natural(Vec<u8>),
+}
+
+impl Ord for SortValue {
+    fn cmp(&self, other: &Self) -> Ordering {
+        match (self, other) {
+            (SortValue::Number(a), SortValue::Number(b)) => a.cmp(b),
+            (SortValue::Gc(a), SortValue::Gc(b)) => a.cmp(b),
+            (SortValue::Lexical(a), SortValue::Lexical(b)) => a.cmp(b),
+            (SortValue::Natural(a), SortValue::Natural(b)) => natural_cmp(a, b),
+            _ => panic!("sort values of different keys compared"),
+        }
+    }
+}
+
+impl PartialOrd for SortValue {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl SortKey {
+    fn value(&self, rec: &OwnedRecord) -> SortValue {
+        match self {
+            SortKey::Length => SortValue::Number(rec.seq.len() as u64),
+            SortKey::Name{natural: true} => SortValue::Natural(rec.name().to_vec()),
+            SortKey::Name{natural: false} => SortValue::Lexical(rec.name().to_vec()),
+            SortKey::Sequence => SortValue::Lexical(rec.seq.clone()),
+            SortKey::Gc => SortValue::Gc(BaseCounts::new(&rec.seq).gc_percent().map(f64::to_bits)),
+        }
+    }
+}
+
+// A sequence, or a pair of mates, with its sort value and position in the input
+struct SortUnit {
+    value: SortValue,
+    index: u64,
+    records: Vec<OwnedRecord>,
+}
+
+impl SortUnit {
+    fn new(key: &SortKey, index: u64, records: Vec<OwnedRecord>) -> Self {
+        SortUnit{value: key.value(&records[0]), index, records}
+    }
+
+    fn order(&self, other: &SortUnit, reverse: bool) -> Ordering {
+        let order = self.value.cmp(&other.value);
+        let order = if reverse { order.reverse() } else { order };
+        order.then(self.index.cmp(&other.index))
+    }
+
+    fn memory(&self) -> usize {
+        estimated_memory(&self.records)
+    }
+}
+
+// Approximate number of bytes that the records take in memory
+pub(crate) fn estimated_memory(records: &[OwnedRecord]) -> usize {
+    records.iter().map(|r| r.head.len() + r.seq.len() + r.qual.as_ref().map_or(0, |q| q.len()) + RECORD_OVERHEAD).sum()
+}
+
+fn write_bytes(output: &mut impl Write, bytes: &[u8]) -> std::io::Result<()> {
+    output.write_all(&(bytes.len() as u64).to_le_bytes())?;
+    output.write_all(bytes)
+}
+
+fn read_bytes(input: &mut impl Read) -> std::io::Result<Vec<u8>> {
+    let mut len = [0_u8; 8];
+    input.read_exact(&mut len)?;
+    let mut bytes = vec![0_u8; u64::from_le_bytes(len) as usize];
+    input.read_exact(&mut bytes)?;
+    Ok(bytes)
+}
+
+// Writes a group of at most 255 records to a temporary file in a binary format that keeps
+// headers, sequences and qualities as they are
+pub(crate) fn write_records(output: &mut impl Write, records: &[OwnedRecord]) -> std::io::Result<()> {
+    output.write_all(&[records.len() as u8])?;
+    for rec in records.iter() {
+        write_bytes(output, &rec.head)?;
+        write_bytes(output, &rec.seq)?;
+        output.write_all(&[rec.qual.is_some() as u8])?;
+        if let Some(qual) = rec.qual.as_ref() {
+            write_bytes(output, qual)?;
+        }
+    }
+    Ok(())
+}
+
+// Reads a group written by write_records. Returns None at the end of the file.
+pub(crate) fn read_records(input: &mut impl Read) -> std::io::Result<Option<Vec<OwnedRecord>>> {
+    let mut n_records = [0_u8; 1];
+    match input.read_exact(&mut n_records) {
+        Ok(()) => (),
+        Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(e) => return Err(e),
+    }
+    let mut records = Vec::with_capacity(n_records[0] as usize);
+    for _ in 0..n_records[0] {
+        let head = read_bytes(input)?;
+        let seq = read_bytes(input)?;
+        let mut has_qual = [0_u8; 1];
+        input.read_exact(&mut has_qual)?;
+        let qual = if has_qual[0] == 1 { Some(read_bytes(input)?) } else { None };
+        records.push(OwnedRecord{head, seq, qual});
+    }
+    Ok(Some(records))
+}
+
+// The runs store the records and the input position without the sort value, which is
+// computed again when merging
+fn write_unit(output: &mut impl Write, unit: &SortUnit) -> std::io::Result<()> {
+    write_records(output, &unit.records)?;
+    output.write_all(&unit.index.to_le_bytes())
+}
+
+// Returns None at the end of the run
+fn read_unit(input: &mut impl Read, key: &SortKey) -> std::io::Result<Option<SortUnit>> {
+    let Some(records) = read_records(input)? else { return Ok(None) };
+    let mut index = [0_u8; 8];
+    input.read_exact(&mut index)?;
+    Ok(Some(SortUnit::new(key, u64::from_le_bytes(index), records)))
+}
+
+// The head of a run in the merge. The heap is a max-heap, so the order is reversed.
+struct MergeItem {
+    unit: SortUnit,
+    run: usize,
+    reverse: bool,
+}
+
+impl Ord for MergeItem {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.unit.order(&self.unit, self.reverse)
+    }
+}
+
+impl PartialOrd for MergeItem {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for MergeItem {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for MergeItem {}
+
+fn write_units(units: &[SortUnit], output: &mut impl SeqRecordWriter) -> Result<(), Box<dyn std::error::Error>> {
+    for unit in units.iter() {
+        for rec in unit.records.iter() {
+            output.write_owned_record(rec)?;
+        }
+    }
+    Ok(())
+}
+
+// Merges the sorted runs, passing the units to emit in sorted order
+fn merge_runs(run_paths: &[PathBuf], options: &SortOptions, mut emit: impl FnMut(&SortUnit) -> Result<(), Box<dyn std::error::Error>>) -> Result<(), Box<dyn std::error::Error>> {
+    let mut runs = run_paths.iter().map(|path| Ok(BufReader::new(std::fs::File::open(path)?))).collect::<std::io::Result<Vec<_>>>()?;
+    let mut heap = BinaryHeap::<MergeItem>::with_capacity(runs.len());
+    for (run, reader) in runs.iter_mut().enumerate() {
+        if let Some(unit) = read_unit(reader, &options.key)? {
+            heap.push(MergeItem{unit, run, reverse: options.reverse});
+        }
+    }
+    while let Some(item) = heap.pop() {
+        emit(&item.unit)?;
+        if let Some(unit) = read_unit(&mut runs[item.run], &options.key)? {
+            heap.push(MergeItem{unit, run: item.run, reverse: options.reverse});
+        }
+    }
+    Ok(())
+}
+
+// Merges groups of at most fan_in runs into longer runs until at most fan_in are left, so
+// that the number of open files stays bounded. The merged runs are deleted.
+fn reduce_runs(run_paths: Vec<PathBuf>, dir: &Path, options: &SortOptions, fan_in: usize) -> Result<Vec<PathBuf>, Box<dyn std::error::Error>> {
+    let mut run_paths = run_paths;
+    let mut n_merged = 0;
+    while run_paths.len() > fan_in {
+        let mut merged_paths = Vec::<PathBuf>::new();
+        for group in run_paths.chunks(fan_in) {
+            let path = dir.join(format!("merged_{}", n_merged));
+            n_merged += 1;
+            let mut run = BufWriter::new(std::fs::File::create(&path)?);
+            merge_runs(group, options, |unit| Ok(write_unit(&mut run, unit)?))?;
+            run.flush()?;
+            for old_path in group {
+                std::fs::remove_file(old_path)?;
+            }
+            merged_paths.push(path);
+        }
+        run_paths = merged_paths;
+    }
+    Ok(run_paths)
+}
+
+pub fn sort_reads(input: &mut DynamicFastXReader, output: &mut impl SeqRecordWriter, options: &SortOptions) -> Result<SortReport, Box<dyn std::error::Error>> {
+    sort_reads_with_fan_in(input, output, options, MAX_MERGE_FAN_IN)
+}
+
+fn sort_reads_with_fan_in(input: &mut DynamicFastXReader, output: &mut impl SeqRecordWriter, options: &SortOptions, fan_in: usize) -> Result<SortReport, Box<dyn std::error::Error>> {
+    let temp_dir = match options.temp_dir.as_ref() {
+        Some(dir) => tempfile::tempdir_in(dir)?,
+        None => tempfile::tempdir()?,
+    };
+    let mut report = SortReport::default();
+    let mut run_paths = Vec::<PathBuf>::new();
+    let mut batch = Vec::<SortUnit>::new();
+    let mut batch_memory = 0;
+    let mut index = 0_u64;
+    loop {
+        let group = crate::paired::read_group(input, options.paired_interleaved)?;
+        let end = group.is_none();
+        if let Some(group) = group {
+            report.n_seqs += group.len() as u64;
+            let unit = SortUnit::new(&options.key, index, group);
+            index += 1;
+            batch_memory += unit.memory();
+            batch.push(unit);
+        }
+
+        // Write the batch as a run when it is full, or at the end if there are other runs
+        if (batch_memory >= options.max_memory || end) && !batch.is_empty() && !(end && run_paths.is_empty()) {
+            batch.sort_by(|a, b| a.order(b, options.reverse));
+            let path = temp_dir.path().join(format!("run_{}", run_paths.len()));
+            let mut run = BufWriter::new(std::fs::File::create(&path)?);
+            for unit in batch.iter() {
+                write_unit(&mut run, unit)?;
+            }
+            run.flush()?;
+            run_paths.push(path);
+            batch.clear();
+            batch_memory = 0;
+        }
+        if end { break }
+    }
+
+    if run_paths.is_empty() {
+        batch.sort_by(|a, b| a.order(b, options.reverse));
+        write_units(&batch, output)?;
+    } else {
+        report.n_runs = run_paths.len();
+        let run_paths = reduce_runs(run_paths, temp_dir.path(), options, fan_in)?;
+        merge_runs(&run_paths, options, |unit| write_units(std::slice::from_ref(unit), output))?;
+    }
+    output.flush()?;
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_natural_cmp(){
+        let mut names: Vec<&[u8]> = vec![b"read10", b"read2", b"read", b"read02x", b"read2x", b"read1_10", b"read1_9", b"Read3"];
+        names.sort_by(|a, b| natural_cmp(a, b));
+        let expected: Vec<&[u8]> = vec![b"Read3", b"read", b"read1_9", b"read1_10", b"read2", b"read02x", b"read2x", b"read10"];
+        assert_eq!(names, expected);
+    }
+
+    fn sort_file(path: &str, options: &SortOptions) -> (String, SortReport) {
+        let mut reader = DynamicFastXReader::from_file(&path).unwrap();
+        let mut writer = jseqio::writer::FastXWriter::new(Vec::<u8>::new(), jseqio::FileType::FASTQ);
+        let report = sort_reads(&mut reader, &mut writer, options).unwrap();
+        (String::from_utf8(writer.into_inner().unwrap()).unwrap(), report)
+    }
+
+    #[test]
+    fn test_external_sort(){
+        let options = SortOptions{key: SortKey::Gc, reverse: true, paired_interleaved: true, max_memory: 1 << 20, temp_dir: None};
+        let (in_memory, report) = sort_file("tests/data/reads.fastq", &options);
+        assert_eq!(report, SortReport{n_seqs: 10, n_runs: 0});
+
+        // One pair per run, and runs of three pairs with ties across runs
+        for max_memory in [0, 600] {
+            let (external, report) = sort_file("tests/data/reads.fastq", &SortOptions{max_memory, ..options.clone()});
+            assert_eq!(external, in_memory);
+            assert!(report.n_runs > 1);
+        }
+
+        let options = SortOptions{key: SortKey::Length, reverse: false, paired_interleaved: false, max_memory: 0, temp_dir: None};
+        let (sorted, report) = sort_file("tests/data/reads.fastq", &options);
+        assert_eq!(report, SortReport{n_seqs: 10, n_runs: 10});
+        let lengths: Vec<usize> = sorted.lines().skip(1).step_by(4).map(|l| l.len()).collect();
+        assert_eq!(lengths, vec![38, 42, 46, 46, 49, 49, 49, 49, 52, 54]);
+
+        // Ten runs merged three at a time take three passes
+        let mut reader = DynamicFastXReader::from_file(&"tests/data/reads.fastq").unwrap();
+        let mut writer = jseqio::writer::FastXWriter::new(Vec::<u8>::new(), jseqio::FileType::FASTQ);
+        let report = sort_reads_with_fan_in(&mut reader, &mut writer, &options, 3).unwrap();
+        assert_eq!(report, SortReport{n_seqs: 10, n_runs: 10});
+        assert_eq!(String::from_utf8(writer.into_inner().unwrap()).unwrap(), sorted);
+    }
+}
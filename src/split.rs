@@ -0,0 +1,180 @@
+// Splitting of a sequence file into many files.
+
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use jseqio::reader::DynamicFastXReader;
+use jseqio::record::OwnedRecord;
+use jseqio::writer::DynamicFastXWriter;
+
+// Keeps at most max_open output files open at a time, closing the least recently used one
+// when another is needed. A file that is opened again is appended to. Gzipped files are
+// appended to as new gzip members, which gzip readers concatenate.
+pub struct WriterPool {
+    max_open: usize,
+    filetype: jseqio::FileType,
+    gzip: bool,
+    open: HashMap<PathBuf, (u64, DynamicFastXWriter)>, // Writer with its last use time
+    created: HashSet<PathBuf>,
+    time: u64,
+}
+
+impl WriterPool {
+    pub fn new(max_open: usize, filetype: jseqio::FileType, gzip: bool) -> Self {
+        assert!(max_open > 0, "max_open must be positive");
+        WriterPool{max_open, filetype, gzip, open: HashMap::new(), created: HashSet::new(), time: 0}
+    }
+
+    fn open_writer(&self, path: &Path) -> Result<DynamicFastXWriter, Box<dyn std::error::Error>> {
+        let file = if self.created.contains(path) {
+            std::fs::OpenOptions::new().append(true).open(path)?
+        } else {
+            std::fs::File::create(path)?
+        };
+        Ok(match self.gzip {
+            true => DynamicFastXWriter::new(flate2::write::GzEncoder::new(file, flate2::Compression::fast()), self.filetype),
+            false => DynamicFastXWriter::new(file, self.filetype),
+        })
+    }
+
+    pub fn write(&mut self, path: &Path, rec: &OwnedRecord) -> Result<(), Box<dyn std::error::Error>> {
+        self.time += 1;
+        if !self.open.contains_key(path) {
+            if self.open.len() >= self.max_open {
+                let lru = self.open.iter().min_by_key(|(_, (t, _))| *t).map(|(p, _)| p.clone()).unwrap();
+                self.open.remove(&lru); // Dropping the writer flushes and closes the file
+            }
+            let writer = self.open_writer(path)?;
+            self.created.insert(path.to_owned());
+            self.open.insert(path.to_owned(), (0, writer));
+        }
+        let (last_used, writer) = self.open.get_mut(path).unwrap();
+        *last_used = self.time;
+        writer.write(rec)
+    }
+
+    // Number of distinct files written
+    pub fn n_files(&self) -> usize {
+        self.created.len()
+    }
+}
+
+pub enum SplitMode {
+    HeaderRegex(regex::bytes::Regex), // By the first capture group, or the whole match if there are no groups
+    RoundRobin(usize), // Into this many parts
+    Contiguous(usize, u64), // Into this many parts of consecutive units, given the total number of units
+    ChunkReads(u64), // Chunks of this many units
+    ChunkBases(u64), // Chunks of about this many bases. A chunk ends before the unit that would exceed the size.
+    LengthBins(Vec<u64>), // Bin boundaries in increasing order
+}
+
+pub struct SplitOptions {
+    pub mode: SplitMode,
+    pub outdir: PathBuf,
+    pub prefix: String,
+    pub paired_interleaved: bool, // Split by pairs, which are kept in the same file
+    pub max_open_files: usize,
+    pub gzip: bool,
+}
+
+// Replaces characters that can not be in file names
+fn sanitize(label: &[u8]) -> String {
+    String::from_utf8_lossy(label).chars().map(|c| if c == '/' || c == '\\' || c == '\0' { '_' } else { c }).collect()
+}
+
+fn length_bin_label(bins: &[u64], len: u64) -> String {
+    let i = bins.partition_point(|&b| b <= len);
+    let lo = if i == 0 { 0 } else { bins[i - 1] };
+    match bins.get(i) {
+        Some(hi) => format!("{}-{}", lo, hi - 1),
+        None => format!("{}-inf", lo),
+    }
+}
+
+// Splits the input into files in options.outdir. The unit of splitting is a read, or a pair
+// of reads with options.paired_interleaved, and the label of the output file of a pair is
+// decided by the first mate, or by the total length for length bins. Returns the number of
+// units and files written.
+pub fn split(input: &mut DynamicFastXReader, options: &SplitOptions) -> (u64, usize) {
+    let filetype = input.filetype();
+    let extension = match (filetype, options.gzip) {
+        (jseqio::FileType::FASTA, false) => "fna",
+        (jseqio::FileType::FASTQ, false) => "fastq",
+        (jseqio::FileType::FASTA, true) => "fna.gz",
+        (jseqio::FileType::FASTQ, true) => "fastq.gz",
+    };
+    std::fs::create_dir_all(&options.outdir).unwrap();
+    let mut pool = WriterPool::new(options.max_open_files, filetype, options.gzip);
+
+    let mut n_units = 0_u64;
+    let mut chunk_idx = 0_u64; // For chunk modes
+    let mut chunk_size = 0_u64; // Units or bases in the current chunk
+    loop {
+        let unit: Vec<OwnedRecord> = match options.paired_interleaved {
+            true => match crate::paired::read_pair(input, None) {
+                Some((r1, r2)) => vec![r1, r2],
+                None => break,
+            },
+            false => match input.read_next().unwrap() {
+                Some(rec) => vec![rec.to_owned()],
+                None => break,
+            },
+        };
+        let n_bases: u64 = unit.iter().map(|r| r.seq.len() as u64).sum();
+
+        let label = match &options.mode {
+            SplitMode::HeaderRegex(re) => match re.captures(&unit[0].head) {
+                Some(caps) => sanitize(caps.get(1).unwrap_or_else(|| caps.get(0).unwrap()).as_bytes()),
+                None => "unmatched".to_owned(),
+            },
+            SplitMode::RoundRobin(n_parts) => (n_units % *n_parts as u64 + 1).to_string(),
+            SplitMode::Contiguous(n_parts, total) => {
+                let part_size = total.div_ceil(*n_parts as u64).max(1);
+                (n_units / part_size + 1).to_string()
+            }
+            SplitMode::ChunkReads(size) => (n_units / size + 1).to_string(),
+            SplitMode::ChunkBases(size) => {
+                if chunk_size > 0 && chunk_size + n_bases > *size {
+                    chunk_idx += 1;
+                    chunk_size = 0;
+                }
+                chunk_size += n_bases;
+                (chunk_idx + 1).to_string()
+            }
+            SplitMode::LengthBins(bins) => length_bin_label(bins, n_bases),
+        };
+
+        let path = options.outdir.join(format!("{}{}.{}", options.prefix, label, extension));
+        for rec in unit.iter() {
+            pool.write(&path, rec).unwrap();
+        }
+        n_units += 1;
+    }
+    (n_units, pool.n_files())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_writer_pool(){
+        let dir = tempfile::tempdir().unwrap();
+        let mut pool = WriterPool::new(2, jseqio::FileType::FASTA, true);
+        let rec = |s: &[u8]| OwnedRecord{head: s.to_vec(), seq: s.to_vec(), qual: None};
+        let paths: Vec<PathBuf> = ["a.fna.gz", "b.fna.gz", "c.fna.gz"].iter().map(|f| dir.path().join(f)).collect();
+        for (i, name) in [b"A", b"C", b"G", b"T", b"A"].iter().enumerate() {
+            pool.write(&paths[i % 3], &rec(name.as_slice())).unwrap();
+        }
+        drop(pool);
+
+        // The first file was closed and reopened in between
+        let mut reader = DynamicFastXReader::from_file(&paths[0]).unwrap();
+        assert_eq!(reader.read_next().unwrap().unwrap().seq, b"A");
+        assert_eq!(reader.read_next().unwrap().unwrap().seq, b"T");
+        assert!(reader.read_next().unwrap().is_none());
+
+        assert_eq!(length_bin_label(&[100, 500], 99), "0-99");
+        assert_eq!(length_bin_label(&[100, 500], 100), "100-499");
+        assert_eq!(length_bin_label(&[100, 500], 500), "500-inf");
+    }
+}
@@ -5,6 +5,8 @@ use std::str;
 
 use std::env;
 
+// Superseded by `seqtools split --by-header-regex '^([^ .]*)'`, which does not need the
+// records of an accession to be contiguous.
 fn main(){
     let args: Vec<String> = env::args().collect();
     let infile = args[1].clone();
@@ -27,13 +27,7 @@ fn smith_waterman(needle: &[u8], haystack: &[u8], identity_threshold: f64) -> Op
         }
     }
 
-    for end in (1..=n).rev() {
-        if score_matrix[m][end] as f64 / m as f64 >= identity_threshold {
-            return Some(end);
-        }
-    }
-
-    return None;
+    (1..=n).rev().find(|&end| score_matrix[m][end] as f64 / m as f64 >= identity_threshold)
 }
 
 #[derive(Debug)]
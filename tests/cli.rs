@@ -31,25 +31,40 @@ fn length_histogram() -> Result<(), Box<dyn std::error::Error>> {
     cmd.arg("length-histogram").arg("tests/data/reads.fna").arg("--min").arg("10").arg("--max").arg("100").arg("--nbins").arg("15");
 
     let answer = "\
-10	
-16	
-22	
-28	
-34	######
-40	######
-46	########################################
-52	#############
-58	
-64	
-70	
-76	
-82	
-88	
-94	
+10-16	0	0.0%	
+17-22	0	0.0%	
+23-28	0	0.0%	
+29-34	0	0.0%	
+35-40	1	10.0%	########
+41-46	3	30.0%	########################
+47-52	5	50.0%	########################################
+53-58	1	10.0%	########
+59-64	0	0.0%	
+65-70	0	0.0%	
+71-76	0	0.0%	
+77-82	0	0.0%	
+83-88	0	0.0%	
+89-94	0	0.0%	
+95-100	0	0.0%	
 ";
     cmd.assert()
         .stdout(predicate::str::contains(answer));
 
+    // Automatic range as TSV
+    let mut cmd = Command::cargo_bin("seqtools")?;
+    cmd.arg("length-histogram").arg("tests/data/reads.fna").arg("--nbins").arg("4").arg("--tsv");
+    cmd.assert().success().stdout("start\tend\tcount\tpercentage\n38\t42\t2\t20.00\n43\t46\t2\t20.00\n47\t50\t4\t40.00\n51\t54\t2\t20.00\n");
+
+    // Logarithmic bins from 1 with short bars
+    let mut cmd = Command::cargo_bin("seqtools")?;
+    cmd.arg("length-histogram").arg("tests/data/reads.fna").arg("--min").arg("1").arg("--max").arg("1000").arg("--nbins").arg("3").arg("--log-bins").arg("--width").arg("10");
+    cmd.assert().success().stdout("1-9\t0\t0.0%\t\n10-99\t10\t100.0%\t##########\n100-1000\t0\t0.0%\t\n");
+
+    // More bins than values in the range
+    let mut cmd = Command::cargo_bin("seqtools")?;
+    cmd.arg("length-histogram").arg("tests/data/reads.fna").arg("--min").arg("50").arg("--max").arg("52").arg("--nbins").arg("10");
+    cmd.assert().success().stdout(predicate::str::starts_with("38-50\t8\t80.0%\t"));
+
     Ok(())
 }
 
@@ -273,4 +288,646 @@ IIIIIIII";
         .stderr(predicate::str::contains(deleted_seqs_message));
 
     Ok(())
-}
\ No newline at end of file
+}
+#[test]
+fn extract_region_indexed() -> Result<(), Box<dyn std::error::Error>> {
+    // Work in a temporary directory because the .fai and .gzi indexes are written next to the input
+    let dir = tempfile::tempdir()?;
+    for filename in ["genome.fa", "genome.fa.gz"] { // Plain and bgzip-compressed
+        let path = dir.path().join(filename);
+        std::fs::copy(format!("tests/data/{}", filename), &path)?;
+
+        let mut cmd = Command::cargo_bin("seqtools")?;
+        cmd.arg("extract-region").arg(&path).arg("chr1:25-40").arg("--fasta-out");
+        cmd.assert().success().stdout(">chr1:25-40\nACGTCAGCACGAAACT\n");
+        assert!(dir.path().join(format!("{}.fai", filename)).exists());
+
+        // Second time uses the existing index
+        let mut cmd = Command::cargo_bin("seqtools")?;
+        cmd.arg("extract-region").arg(&path).arg("chr2:28-35").arg("--fasta-out");
+        cmd.assert().success().stdout(">chr2:28-35\nTTTATTAC\n");
+    }
+    assert!(dir.path().join("genome.fa.gz.gzi").exists());
+
+    let fai = std::fs::read_to_string(dir.path().join("genome.fa.fai"))?;
+    assert_eq!(fai, "chr1\t100\t23\t30\t31\nchr2\t45\t133\t30\t31\n");
+
+    Ok(())
+}
+
+#[test]
+fn extract_region_multiple() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = tempfile::tempdir()?;
+    let path = dir.path().join("genome.fa");
+    std::fs::copy("tests/data/genome.fa", &path)?;
+    let bed_path = dir.path().join("regions.bed");
+    std::fs::write(&bed_path, "track name=test\nchr2\t0\t5\tfirst\t0\t-\nchr1\t96\t100\n")?;
+
+    let mut cmd = Command::cargo_bin("seqtools")?;
+    cmd.arg("extract-region").arg(&path).arg("chr1:1-4").arg("chr2:44-45").arg("--bed").arg(&bed_path).arg("--fasta-out");
+    cmd.assert().success().stdout(">chr1:1-4\nGCTA\n>chr2:44-45\nAC\n>chr2:1-5(-)\nCACAG\n>chr1:97-100\nCTTG\n");
+
+    Ok(())
+}
+
+#[test]
+fn extract_reads_indexed() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = tempfile::tempdir()?;
+    let path = dir.path().join("reads.fastq");
+    std::fs::copy("tests/data/reads.fastq", &path)?;
+
+    let mut cmd = Command::cargo_bin("seqtools")?;
+    cmd.arg("extract-reads").arg(&path).arg("--index").arg("-r").arg("7").arg("-r").arg("1");
+    let out = cmd.assert().success().get_output().stdout.clone();
+    let out = str::from_utf8(&out)?;
+    assert!(out.starts_with("@SRR403017.8 HWUSI-EAS108E_0007:3:1:8825:983/1\nATAGAGAAGGGGGACAATGAGCCTGGATCTTTGCCTTGNNNN\n+\n"));
+    assert!(out.contains("\n@SRR403017.2 "));
+    assert!(dir.path().join("reads.fastq.ridx").exists());
+
+    // Reuses the index
+    let mut cmd = Command::cargo_bin("seqtools")?;
+    cmd.arg("extract-reads").arg(&path).arg("--index").arg("--name").arg("SRR403017.10").arg("--name").arg("SRR403017.3");
+    let out = cmd.assert().success().get_output().stdout.clone();
+    let headers: Vec<&str> = str::from_utf8(&out)?.lines().filter(|l| l.starts_with("@SRR")).collect();
+    assert_eq!(headers, vec!["@SRR403017.10 HWUSI-EAS108E_0007:3:1:14685:981/1", "@SRR403017.3 HWUSI-EAS108E_0007:3:1:13569:972/1"]);
+
+    Ok(())
+}
+
+#[test]
+fn extract_reads_stream_invert() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("seqtools")?;
+    cmd.arg("extract-reads").arg("tests/data/reads.fna").arg("--stream").arg("--name").arg("SRR403017.9").arg("--name").arg("SRR403017.2");
+    let out = cmd.assert().success().get_output().stdout.clone();
+    let headers: Vec<&str> = str::from_utf8(&out)?.lines().filter(|l| l.starts_with('>')).collect();
+    assert_eq!(headers, vec![">SRR403017.2 HWUSI-EAS108E_0007:3:1:10327:976/1", ">SRR403017.9 HWUSI-EAS108E_0007:3:1:11308:980/1"]); // File order
+
+    let dir = tempfile::tempdir()?;
+    let list_path = dir.path().join("names.txt");
+    std::fs::write(&list_path, "SRR403017.1/1\nSRR403017.5/2\n")?;
+    let mut cmd = Command::cargo_bin("seqtools")?;
+    cmd.arg("extract-reads").arg("tests/data/reads.fna").arg("--invert").arg("--strip-mate-suffix").arg("--names-listfile").arg(&list_path);
+    let out = cmd.assert().success().stderr(predicate::str::contains("Wrote 8 out of 10 reads")).get_output().stdout.clone();
+    let out = str::from_utf8(&out)?;
+    assert!(!out.contains(">SRR403017.1 ") && !out.contains(">SRR403017.5 ") && out.contains(">SRR403017.10 "));
+
+    Ok(())
+}
+
+#[test]
+fn grep() -> Result<(), Box<dyn std::error::Error>> {
+    // The reverse complement of the motif is in read 8
+    let mut cmd = Command::cargo_bin("seqtools")?;
+    cmd.arg("grep").arg("tests/data/reads.fna").arg("--motif").arg("TCCCCC").arg("--fasta-out");
+    cmd.assert().success().stdout(">SRR403017.8 HWUSI-EAS108E_0007:3:1:8825:983/1\nATAGAGAAGGGGGACAATGAGCCTGGATCTTTGCCTTGNNNN\n");
+
+    let mut cmd = Command::cargo_bin("seqtools")?;
+    cmd.arg("grep").arg("tests/data/reads.fna").arg("--motif").arg("TCCCCC").arg("--forward-only").arg("--count");
+    cmd.assert().success().stdout("0\n");
+
+    // Degenerate motif with a mismatch
+    let mut cmd = Command::cargo_bin("seqtools")?;
+    cmd.arg("grep").arg("tests/data/reads.fna").arg("--motif").arg("GGGGRAA").arg("--max-mismatches").arg("1").arg("--count");
+    cmd.assert().success().stdout("2\n");
+
+    let mut cmd = Command::cargo_bin("seqtools")?;
+    cmd.arg("grep").arg("tests/data/reads.fna").arg("--header-regex").arg(":3:1:8[0-9]+:").arg("--invert").arg("--count");
+    cmd.assert().success().stdout("7\n");
+
+    Ok(())
+}
+
+#[test]
+fn locate() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("seqtools")?;
+    cmd.arg("locate").arg("tests/data/genome.fa").arg("--motif").arg("GTGTG").arg("--motif").arg("TTAAR");
+    let answer = "\
+chr1	50	55	GTGTG	0	+
+chr1	76	81	GTGTG	0	+
+chr1	60	65	TTAAR	0	-
+chr1	61	66	TTAAR	0	+
+chr1	68	73	TTAAR	0	+
+";
+    cmd.assert().success().stdout(answer).stderr(predicate::str::contains("Found 5 occurrences"));
+
+    Ok(())
+}
+
+#[test]
+fn kmer_compare() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("seqtools")?;
+    cmd.arg("kmer-compare").arg("tests/data/reads.fna").arg("tests/data/reads.fastq.gz").arg("tests/data/genome.fa").arg("-k").arg("5").arg("--threads").arg("2");
+    let answer = "\
+file1	file2	kmers1	kmers2	intersection	union	jaccard	containment1in2	containment2in1
+tests/data/reads.fna	tests/data/reads.fastq.gz	248	248	248	248	1.000000	1.000000	1.000000
+tests/data/reads.fna	tests/data/genome.fa	248	118	67	299	0.224080	0.270161	0.567797
+tests/data/reads.fastq.gz	tests/data/genome.fa	248	118	67	299	0.224080	0.270161	0.567797
+";
+    cmd.assert().success().stdout(answer);
+
+    // No valid k-mers: a sequence of N and one shorter than k
+    let dir = tempfile::tempdir()?;
+    let empty = dir.path().join("empty.fa");
+    std::fs::write(&empty, ">x\nNNNNNNNN\n>y\nACG\n")?;
+    let mut cmd = Command::cargo_bin("seqtools")?;
+    cmd.arg("kmer-compare").arg(&empty).arg(&empty).arg("-k").arg("5");
+    cmd.assert().success().stdout(predicate::str::ends_with("\t0\t0\t0\t0\t0.000000\t0.000000\t0.000000\n"));
+
+    Ok(())
+}
+
+#[test]
+fn kmer_count() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = tempfile::tempdir()?;
+    let kmers_path = dir.path().join("kmers.fa");
+    let mut cmd = Command::cargo_bin("seqtools")?;
+    cmd.arg("tests/data/genome.fa").arg("kmer-count").arg("-k").arg("3").arg("--spectrum-tsv")
+        .arg("--kmers-out").arg(&kmers_path).arg("--format").arg("fasta").arg("--min-count").arg("10");
+    cmd.assert().success().stdout("1\t3\n2\t5\n3\t4\n4\t8\n5\t4\n6\t2\n7\t2\n8\t1\n9\t1\n10\t1\n11\t1\n");
+    assert_eq!(std::fs::read_to_string(&kmers_path)?, ">10\nACA\n>11\nTAA\n");
+
+    // Counts above --max go to the last bin
+    let mut cmd = Command::cargo_bin("seqtools")?;
+    cmd.arg("tests/data/genome.fa").arg("kmer-count").arg("-k").arg("3").arg("--max").arg("10").arg("--nbins").arg("10");
+    cmd.assert().success().stdout(predicate::str::ends_with("9\t1\t3.1%\t#####\n10-11\t2\t6.2%\t##########\n"));
+
+    Ok(())
+}
+
+#[test]
+fn sketch_compare() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = tempfile::tempdir()?;
+    let bottom_k = dir.path().join("bottom_k.sketch");
+    let scaled = dir.path().join("scaled.sketch");
+    let mut cmd = Command::cargo_bin("seqtools")?;
+    cmd.arg("sketch").arg("tests/data/genome.fa").arg("tests/data/reads.fna").arg("-k").arg("5").arg("-s").arg("50").arg("-o").arg(&bottom_k);
+    cmd.assert().success();
+    let mut cmd = Command::cargo_bin("seqtools")?;
+    cmd.arg("sketch").arg("tests/data/reads.fastq.gz").arg("-k").arg("5").arg("--scaled").arg("2").arg("-o").arg(&scaled);
+    cmd.assert().success();
+
+    // The fasta and fastq reads are the same, so their sketches agree up to the largest hash
+    // in the bottom-50 sketch. The scaled sketch covers more of the k-mers of genome.fa.
+    let mut cmd = Command::cargo_bin("seqtools")?;
+    cmd.arg("sketch-compare").arg(&bottom_k).arg(&scaled);
+    let answer = "\
+name1	name2	shared_hashes	jaccard	containment1in2	containment2in1	mash_distance
+tests/data/genome.fa	tests/data/reads.fna	9	0.160714	0.600000	0.180000	0.256803
+tests/data/genome.fa	tests/data/reads.fastq.gz	31	0.229630	0.620000	0.267241	0.196971
+tests/data/reads.fna	tests/data/reads.fastq.gz	50	1.000000	1.000000	1.000000	0.000000
+";
+    cmd.assert().success().stdout(answer);
+
+    // Sketches with different k can not be compared
+    let mut cmd = Command::cargo_bin("seqtools")?;
+    cmd.arg("sketch").arg("tests/data/genome.fa").arg("-k").arg("7").arg("-o").arg(&scaled);
+    cmd.assert().success();
+    let mut cmd = Command::cargo_bin("seqtools")?;
+    cmd.arg("sketch-compare").arg(&bottom_k).arg(&scaled);
+    cmd.assert().failure();
+
+    Ok(())
+}
+
+#[test]
+fn kmer_filter() -> Result<(), Box<dyn std::error::Error>> {
+    // The reference contains the first 24 bases of the first read: 14 of its 24 11-mers
+    let dir = tempfile::tempdir()?;
+    let reference = dir.path().join("reference.fna");
+    std::fs::write(&reference, ">contaminant\nTTGGACCGGCGCAAGACGGACCAG\n")?;
+
+    let mut cmd = Command::cargo_bin("seqtools")?;
+    cmd.arg("kmer-filter").arg("tests/data/reads.fna").arg("-r").arg(&reference).arg("-k").arg("11").arg("--fasta-out");
+    cmd.assert().success()
+        .stdout(predicate::str::contains("SRR403017.1 ").not().and(predicate::str::contains("SRR403017.2 ")))
+        .stderr(predicate::str::contains("Removed 1 out of 10 reads"));
+
+    // Pairs are kept together
+    let mut cmd = Command::cargo_bin("seqtools")?;
+    cmd.arg("kmer-filter").arg("tests/data/reads.fna").arg("-r").arg(&reference).arg("-k").arg("11").arg("--keep-matching").arg("--paired-interleaved").arg("--fasta-out");
+    cmd.assert().success()
+        .stdout(predicate::str::starts_with(">SRR403017.1 ").and(predicate::str::contains(">SRR403017.2 ")).and(predicate::str::contains(">SRR403017.3 ").not()))
+        .stderr(predicate::str::contains("Removed 8 out of 10 reads"));
+
+    // Fraction of shared k-mers is too low
+    let mut cmd = Command::cargo_bin("seqtools")?;
+    cmd.arg("kmer-filter").arg("tests/data/reads.fna").arg("-r").arg(&reference).arg("-k").arg("11").arg("--min-fraction").arg("0.6").arg("--fasta-out");
+    cmd.assert().success().stderr(predicate::str::contains("Removed 0 out of 10 reads"));
+
+    Ok(())
+}
+
+#[test]
+fn minimizers() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = tempfile::tempdir()?;
+    let input = dir.path().join("seq.fna");
+    std::fs::write(&input, ">s1 x\nACGTTGCAAAGGNTACCATGACGA\n")?;
+
+    let mut cmd = Command::cargo_bin("seqtools")?;
+    cmd.arg("minimizers").arg(&input).arg("-k").arg("3").arg("-w").arg("4").arg("--order").arg("lex");
+    let answer = "name\tposition\tminimizer\tstrand\ns1\t0\tACG\t+\ns1\t1\tCGT\t+\ns1\t5\tGCA\t+\ns1\t6\tCAA\t+\ns1\t7\tAAA\t+\ns1\t14\tACC\t+\ns1\t17\tATG\t+\ns1\t20\tACG\t+\n";
+    cmd.assert().success().stdout(answer);
+
+    // Super-k-mers overlap by w+k-2 bases and do not span the N
+    let mut cmd = Command::cargo_bin("seqtools")?;
+    cmd.arg("minimizers").arg(&input).arg("-k").arg("3").arg("-w").arg("4").arg("--order").arg("lex").arg("--canonical").arg("--super-kmers");
+    let answer = ">s1:1-8 minimizer=AAC\nACGTTGCA\n>s1:4-9 minimizer=CAA\nTTGCAA\n>s1:5-12 minimizer=AAA\nTGCAAAGG\n>s1:14-20 minimizer=ACC\nTACCATG\n>s1:16-22 minimizer=ATG\nCCATGAC\n>s1:18-24 minimizer=ACG\nATGACGA\n";
+    cmd.assert().success().stdout(answer);
+
+    Ok(())
+}
+
+#[test]
+fn interleave_deinterleave() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = tempfile::tempdir()?;
+    let mates1 = dir.path().join("r1.fna");
+    let mates2 = dir.path().join("r2.fna");
+    let interleaved = dir.path().join("interleaved.fna");
+    let orphans = dir.path().join("orphans.fna");
+    std::fs::write(&mates1, ">a 1:N:0:AC\nAAAA\n>b 1:N:0:AC\nCCCC\n>c 1:N:0:AC\nGGGG\n")?;
+    std::fs::write(&mates2, ">a 2:N:0:AC\nTTTT\n>c 2:N:0:AC\nAAAA\n")?;
+
+    // Mate b is missing from the second file
+    let mut cmd = Command::cargo_bin("seqtools")?;
+    cmd.arg("interleave").arg(&mates1).arg(&mates2).arg("--fasta-out");
+    cmd.assert().failure().stderr(predicate::str::contains("mate names do not match in pair 2"));
+
+    let mut cmd = Command::cargo_bin("seqtools")?;
+    cmd.arg("interleave").arg(&mates1).arg(&mates2).arg("-o").arg(&interleaved).arg("--orphans").arg(&orphans);
+    cmd.assert().success().stderr(predicate::str::contains("Wrote 2 pairs and 1 orphans"));
+    assert_eq!(std::fs::read_to_string(&interleaved)?, ">a 1:N:0:AC\nAAAA\n>a 2:N:0:AC\nTTTT\n>c 1:N:0:AC\nGGGG\n>c 2:N:0:AC\nAAAA\n");
+    assert_eq!(std::fs::read_to_string(&orphans)?, ">b 1:N:0:AC\nCCCC\n");
+
+    // Deinterleave with the orphan in the middle
+    std::fs::write(&interleaved, ">a/1\nAAAA\n>a/2\nTTTT\n>b/1\nCCCC\n>c/1\nGGGG\n>c/2\nAAAA\n")?;
+    let mut cmd = Command::cargo_bin("seqtools")?;
+    cmd.arg("deinterleave").arg(&interleaved).arg("--out1").arg(&mates1).arg("--out2").arg(&mates2);
+    cmd.assert().failure().stderr(predicate::str::contains("mate names do not match in pair 2: b/1 and c/1"));
+
+    let mut cmd = Command::cargo_bin("seqtools")?;
+    cmd.arg("deinterleave").arg(&interleaved).arg("--out1").arg(&mates1).arg("--out2").arg(&mates2).arg("--orphans").arg(&orphans);
+    cmd.assert().success().stderr(predicate::str::contains("Wrote 2 pairs and 1 orphans"));
+    assert_eq!(std::fs::read_to_string(&mates1)?, ">a/1\nAAAA\n>c/1\nGGGG\n");
+    assert_eq!(std::fs::read_to_string(&mates2)?, ">a/2\nTTTT\n>c/2\nAAAA\n");
+    assert_eq!(std::fs::read_to_string(&orphans)?, ">b/1\nCCCC\n");
+
+    Ok(())
+}
+
+#[test]
+fn repair() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = tempfile::tempdir()?;
+    let mates1 = dir.path().join("r1.fna");
+    let mates2 = dir.path().join("r2.fna");
+    let out1 = dir.path().join("out1.fna");
+    let out2 = dir.path().join("out2.fna");
+    let singletons = dir.path().join("singletons.fna");
+    std::fs::write(&mates1, ">a/1\nA\n>b/1\nC\n>d/1\nG\n>e/1\nT\n")?;
+    std::fs::write(&mates2, ">b/2\nAA\n>c/2\nCC\n>a/2\nGG\n>e/2\nTT\n")?;
+
+    let mut cmd = Command::cargo_bin("seqtools")?;
+    cmd.arg("repair").arg(&mates1).arg(&mates2).arg("--out1").arg(&out1).arg("--out2").arg(&out2).arg("--singletons").arg(&singletons);
+    cmd.assert().success().stderr(predicate::str::contains("Wrote 3 pairs and 2 singletons"));
+    assert_eq!(std::fs::read_to_string(&out1)?, ">b/1\nC\n>a/1\nA\n>e/1\nT\n");
+    assert_eq!(std::fs::read_to_string(&out2)?, ">b/2\nAA\n>a/2\nGG\n>e/2\nTT\n");
+    assert_eq!(std::fs::read_to_string(&singletons)?, ">d/1\nG\n>c/2\nCC\n");
+
+    // Interleaved input where the mate numbers are only in the order of the reads
+    let interleaved = dir.path().join("interleaved.fna");
+    std::fs::write(&interleaved, ">a\nA\n>b\nC\n>a\nAA\n>c\nG\n>b\nCC\n")?;
+    let mut cmd = Command::cargo_bin("seqtools")?;
+    cmd.arg("repair").arg(&interleaved).arg("--fasta-out");
+    cmd.assert().success()
+        .stdout(">a\nA\n>a\nAA\n>b\nC\n>b\nCC\n")
+        .stderr(predicate::str::contains("Wrote 2 pairs and 1 singletons"));
+
+    Ok(())
+}
+
+#[test]
+fn merge_pairs() -> Result<(), Box<dyn std::error::Error>> {
+    // Pair a overlaps by 13 bases, pair b does not overlap
+    let dir = tempfile::tempdir()?;
+    let interleaved = dir.path().join("pairs.fastq");
+    let unmerged = dir.path().join("unmerged.fastq");
+    std::fs::write(&interleaved, "\
+@a/1\nGCTAAAGACAATTACATAAC\n+\nIIIIIIIIIIIIIIIIIIII\n\
+@a/2\nTGTGTATGTTATGTAATTGT\n+\nIIIIIIIIIIIIIIIIIIII\n\
+@b/1\nCTGTGTCCACCCCATCGGAC\n+\nIIIIIIIIIIIIIIIIIIII\n\
+@b/2\nGTTTCTGAGTGTAATAAAAA\n+\nIIIIIIIIIIIIIIIIIIII\n")?;
+
+    let mut cmd = Command::cargo_bin("seqtools")?;
+    cmd.arg("merge-pairs").arg(&interleaved).arg("--min-overlap").arg("8").arg("--unmerged").arg(&unmerged).arg("--fastq-out");
+    cmd.assert().success()
+        .stdout("@a/1\nGCTAAAGACAATTACATAACATACACA\n+\nIIIIIIIJJJJJJJJJJJJJIIIIIII\n")
+        .stderr(predicate::str::contains("Merged 1 out of 2 pairs"));
+    assert!(std::fs::read_to_string(&unmerged)?.starts_with("@b/1\n"));
+
+    // No merged pairs does not print NaN
+    let mut cmd = Command::cargo_bin("seqtools")?;
+    cmd.arg("merge-pairs").arg(&unmerged).arg("--min-overlap").arg("8").arg("--fastq-out");
+    cmd.assert().success().stdout("").stderr(predicate::str::contains("Merged 0 out of 1 pairs (0.00%). Mean overlap length: 0.0"));
+
+    Ok(())
+}
+
+#[test]
+fn split() -> Result<(), Box<dyn std::error::Error>> {
+    let count = |path: &std::path::Path| std::fs::read_to_string(path).unwrap().matches('>').count();
+
+    // Round-robin pairs with only one file open at a time
+    let dir = tempfile::tempdir()?;
+    let mut cmd = Command::cargo_bin("seqtools")?;
+    cmd.arg("split").arg("tests/data/reads.fna").arg("--outdir").arg(dir.path()).arg("--parts").arg("3").arg("--paired-interleaved").arg("--max-open-files").arg("1");
+    cmd.assert().success().stderr(predicate::str::contains("Wrote 5 pairs into 3 files"));
+    assert_eq!(count(&dir.path().join("1.fna")), 4);
+    assert_eq!(count(&dir.path().join("2.fna")), 4);
+    assert_eq!(count(&dir.path().join("3.fna")), 2);
+    assert!(std::fs::read_to_string(dir.path().join("1.fna"))?.starts_with(">SRR403017.1 HWUSI-EAS108E_0007:3:1:3797:973/1\n"));
+
+    // Contiguous parts
+    let dir = tempfile::tempdir()?;
+    let mut cmd = Command::cargo_bin("seqtools")?;
+    cmd.arg("split").arg("tests/data/reads.fna").arg("--outdir").arg(dir.path()).arg("--parts").arg("3").arg("--contiguous").arg("--prefix").arg("part");
+    cmd.assert().success();
+    assert_eq!(count(&dir.path().join("part1.fna")), 4);
+    assert_eq!(count(&dir.path().join("part3.fna")), 2);
+
+    // By header
+    let dir = tempfile::tempdir()?;
+    let mut cmd = Command::cargo_bin("seqtools")?;
+    cmd.arg("split").arg("tests/data/genome.fa").arg("--outdir").arg(dir.path()).arg("--by-header-regex").arg("^chr(1)").arg("--gzip");
+    cmd.assert().success();
+    assert!(dir.path().join("1.fna.gz").exists());
+    assert!(dir.path().join("unmatched.fna.gz").exists());
+
+    // By length
+    let dir = tempfile::tempdir()?;
+    let mut cmd = Command::cargo_bin("seqtools")?;
+    cmd.arg("split").arg("tests/data/genome.fa").arg("--outdir").arg(dir.path()).arg("--by-length-bins").arg("50,100");
+    cmd.assert().success();
+    assert!(std::fs::read_to_string(dir.path().join("0-49.fna"))?.starts_with(">chr2"));
+    assert!(std::fs::read_to_string(dir.path().join("100-inf.fna"))?.starts_with(">chr1"));
+
+    // Chunks of bases
+    let dir = tempfile::tempdir()?;
+    let mut cmd = Command::cargo_bin("seqtools")?;
+    cmd.arg("split").arg("tests/data/genome.fa").arg("--outdir").arg(dir.path()).arg("--chunk-size").arg("120").arg("--chunk-unit").arg("bases");
+    cmd.assert().success().stderr(predicate::str::contains("Wrote 2 reads into 2 files"));
+
+    Ok(())
+}
+
+#[test]
+fn prefixes() -> Result<(), Box<dyn std::error::Error>> {
+    let headers = |path: &std::path::Path| -> Vec<String> {
+        std::fs::read_to_string(path).unwrap().lines().filter(|l| l.starts_with('>')).map(|l| l.to_owned()).collect()
+    };
+
+    // Explicit sizes as prefixes
+    let dir = tempfile::tempdir()?;
+    let out_prefix = dir.path().join("sub");
+    let mut cmd = Command::cargo_bin("seqtools")?;
+    cmd.arg("prefixes").arg("tests/data/reads.fna").arg("--out-prefix").arg(&out_prefix).arg("--sizes").arg("5,2");
+    cmd.assert().success().stderr(predicate::str::contains("Wrote 2 reads"));
+    let sub2 = headers(&dir.path().join("sub-2.fna"));
+    let sub5 = headers(&dir.path().join("sub-5.fna"));
+    assert_eq!(sub2.len(), 2);
+    assert_eq!(sub5[..2], sub2[..]);
+
+    // Halving down to one read
+    let dir = tempfile::tempdir()?;
+    let out_prefix = dir.path().join("sub");
+    let mut cmd = Command::cargo_bin("seqtools")?;
+    cmd.arg("prefixes").arg("tests/data/reads.fna").arg("--out-prefix").arg(&out_prefix).arg("--ratio").arg("2");
+    cmd.assert().success();
+    for (size, n) in [(1, 1), (2, 2), (5, 5), (10, 10)] {
+        assert_eq!(headers(&dir.path().join(format!("sub-{}.fna", size))).len(), n);
+    }
+
+    // Random nested subsets in bases
+    let dir = tempfile::tempdir()?;
+    let out_prefix = dir.path().join("sub");
+    let mut cmd = Command::cargo_bin("seqtools")?;
+    cmd.arg("prefixes").arg("tests/data/reads.fna").arg("--out-prefix").arg(&out_prefix).arg("--linear").arg("150").arg("--unit").arg("bases").arg("--random").arg("--seed").arg("1");
+    cmd.assert().success();
+    let sub150 = headers(&dir.path().join("sub-150.fna"));
+    let sub300 = headers(&dir.path().join("sub-300.fna"));
+    assert!(sub150.len() >= 3 && sub150.len() < sub300.len());
+    assert!(sub150.iter().all(|h| sub300.contains(h)));
+
+    Ok(())
+}
+
+#[test]
+fn alignment_histogram() -> Result<(), Box<dyn std::error::Error>> {
+    // SAM and BAM give the same histogram. The secondary alignment and the unmapped read are excluded by default.
+    let mut outputs = vec![];
+    for file in ["tests/data/alignments.sam", "tests/data/alignments.bam"] {
+        let mut cmd = Command::cargo_bin("seqtools")?;
+        cmd.arg("alignment-histogram").arg(file).arg("--field").arg("aligned-length").arg("--min").arg("0").arg("--max").arg("39").arg("--nbins").arg("4");
+        let output = cmd.output()?;
+        assert!(output.status.success());
+        outputs.push(String::from_utf8(output.stdout)?);
+    }
+    assert_eq!(outputs[0], outputs[1]);
+    assert_eq!(outputs[0].lines().map(|l| l.matches('#').count()).collect::<Vec<_>>(), vec![0, 10, 40, 10]);
+
+    // Positions on one reference, including the secondary alignment
+    let mut cmd = Command::cargo_bin("seqtools")?;
+    cmd.arg("alignment-histogram").arg("tests/data/alignments.bam").arg("--reference").arg("chr1").arg("-F").arg("0x4").arg("--min").arg("1").arg("--max").arg("60").arg("--nbins").arg("3");
+    cmd.assert().success().stdout("1-20\t2\t40.0%\t########################################\n21-40\t1\t20.0%\t####################\n41-60\t2\t40.0%\t########################################\n");
+
+    // Template lengths are counted once per pair
+    let mut cmd = Command::cargo_bin("seqtools")?;
+    cmd.arg("alignment-histogram").arg("tests/data/alignments.sam").arg("--field").arg("tlen").arg("--min").arg("50").arg("--max").arg("69").arg("--nbins").arg("2");
+    cmd.assert().success().stdout("50-59\t1\t50.0%\t########################################\n60-69\t1\t50.0%\t########################################\n");
+
+    Ok(())
+}
+
+#[test]
+fn coverage() -> Result<(), Box<dyn std::error::Error>> {
+    // Per-base bedGraph of one reference, with the insertion of r4 not covering any bases
+    let mut cmd = Command::cargo_bin("seqtools")?;
+    cmd.arg("coverage").arg("tests/data/alignments.sam").arg("--reference").arg("chr2");
+    cmd.assert().success()
+        .stdout("chr2\t0\t4\t0\nchr2\t4\t19\t1\nchr2\t19\t34\t2\nchr2\t34\t39\t1\nchr2\t39\t45\t0\n")
+        .stderr(predicate::str::contains("Mean depth 1.11 over 45 bases. Breadth of coverage 77.78% at 1x"));
+
+    // SAM and BAM give the same windowed depths and summary
+    let dir = tempfile::tempdir()?;
+    let mut outputs = vec![];
+    for file in ["tests/data/alignments.sam", "tests/data/alignments.bam"] {
+        let summary = dir.path().join("summary.tsv");
+        let histogram = dir.path().join("histogram.tsv");
+        let mut cmd = Command::cargo_bin("seqtools")?;
+        cmd.arg("coverage").arg(file).arg("--window").arg("20").arg("--summary").arg(&summary).arg("--depth-histogram").arg(&histogram);
+        let output = cmd.output()?;
+        assert!(output.status.success());
+        outputs.push((String::from_utf8(output.stdout)?, std::fs::read_to_string(&summary)?, std::fs::read_to_string(&histogram)?));
+    }
+    assert_eq!(outputs[0], outputs[1]);
+    assert!(outputs[0].0.starts_with("chr1\t0\t20\t1.50\n"));
+    assert!(outputs[0].1.contains("total\t145\t0.8621\t0.6207\t0.0000\n"));
+    assert_eq!(outputs[0].2, "depth\tbases\n0\t55\n1\t55\n2\t35\n");
+
+    Ok(())
+}
+
+#[test]
+fn gc_content() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("seqtools")?;
+    cmd.arg("gc-content").arg("tests/data/genome.fa").arg("--per-sequence");
+    cmd.assert().success().stdout("name\tlength\tgc_percent\tn_percent\nchr1\t100\t42.00\t0.00\nchr2\t45\t46.67\t0.00\n");
+
+    // Overlapping windows, with a shorter last window
+    let mut cmd = Command::cargo_bin("seqtools")?;
+    cmd.arg("gc-content").arg("tests/data/genome.fa").arg("--window").arg("40").arg("--step").arg("30");
+    cmd.assert().success().stdout("chr1\t0\t40\t37.50\nchr1\t30\t70\t50.00\nchr1\t60\t100\t40.00\nchr2\t0\t40\t47.50\nchr2\t30\t45\t33.33\n");
+
+    // Gaps between windows when the step is longer than the window
+    let mut cmd = Command::cargo_bin("seqtools")?;
+    cmd.arg("gc-content").arg("tests/data/genome.fa").arg("--window").arg("10").arg("--step").arg("30");
+    cmd.assert().success().stdout("chr1\t0\t10\t40.00\nchr1\t30\t40\t50.00\nchr1\t60\t70\t40.00\nchr1\t90\t100\t40.00\nchr2\t0\t10\t60.00\nchr2\t30\t40\t30.00\n");
+
+    let mut cmd = Command::cargo_bin("seqtools")?;
+    cmd.arg("gc-content").arg("tests/data/reads.fastq").arg("--histogram").arg("--nbins").arg("10").arg("--tsv");
+    cmd.assert().success().stdout(predicate::str::contains("41\t50\t5\t50.00\n51\t60\t3\t30.00\n"));
+
+    Ok(())
+}
+
+#[test]
+fn filter() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("seqtools")?;
+    cmd.arg("filter").arg("tests/data/reads.fastq").arg("--min-length").arg("45").arg("--max-expected-errors").arg("1").arg("--fastq-out");
+    let output = cmd.output()?;
+    assert!(output.status.success());
+    assert_eq!(str::from_utf8(&output.stdout)?.lines().count(), 7 * 4);
+    let report = str::from_utf8(&output.stderr)?;
+    assert!(report.contains("Removed 3 out of 10 reads\n  too short: 2\n  too many expected errors: 1\n"));
+
+    // Pairs are removed together
+    let mut cmd = Command::cargo_bin("seqtools")?;
+    cmd.arg("filter").arg("tests/data/reads.fastq").arg("--min-mean-quality").arg("35").arg("--min-gc").arg("40").arg("--min-complexity").arg("0.8").arg("--paired-interleaved").arg("--fasta-out");
+    cmd.assert().success().stderr(predicate::str::contains("Removed 4 out of 10 reads\n  GC content too low: 2\n  mean quality too low: 2\n"));
+
+    // Quality criteria need qualities
+    let mut cmd = Command::cargo_bin("seqtools")?;
+    cmd.arg("filter").arg("tests/data/reads.fna").arg("--min-mean-quality").arg("35").arg("--fasta-out");
+    cmd.assert().failure();
+
+    Ok(())
+}
+
+#[test]
+fn mask() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("seqtools")?;
+    cmd.arg("mask").arg("tests/data/low_complexity.fa").arg("--fasta-out");
+    let output = cmd.output()?;
+    assert!(output.status.success());
+    let lines: Vec<&str> = str::from_utf8(&output.stdout)?.lines().collect();
+    assert_eq!(lines[1], format!("{}{}", "GCTAAAGACAATTACATAACATACACGTCAGCACGAAACTTGTTGGCCCAGTGTGAATCG", "a".repeat(30)));
+    assert_eq!(lines[3], "ac".repeat(25));
+    assert_eq!(lines[5], "CTTAAGGGTTAAGTAAGTGTGATGCATACGCCTTTACTTGCTGTGTCCACCCCATCGGACTGGCATTTTT");
+    assert!(str::from_utf8(&output.stderr)?.contains("Masked 80 out of 210 bases"));
+
+    let mut cmd = Command::cargo_bin("seqtools")?;
+    cmd.arg("mask").arg("tests/data/low_complexity.fa").arg("--method").arg("entropy").arg("-w").arg("20").arg("--hard").arg("--fasta-out");
+    let output = cmd.output()?;
+    assert!(output.status.success());
+    let lines: Vec<&str> = str::from_utf8(&output.stdout)?.lines().collect();
+    assert!(lines[1].ends_with(&"N".repeat(30)));
+    assert_eq!(lines[3], "N".repeat(50));
+
+    // The dinucleotide repeat is removed, but only a third of polyA_tail is masked
+    let mut cmd = Command::cargo_bin("seqtools")?;
+    cmd.arg("mask").arg("tests/data/low_complexity.fa").arg("--discard").arg("--fasta-out");
+    let output = cmd.output()?;
+    assert!(output.status.success());
+    let stdout = str::from_utf8(&output.stdout)?;
+    assert!(stdout.starts_with(">polyA_tail\nGCTAAAGACAATTACATAACATACACGTCAGCACGAAACTTGTTGGCCCAGTGTGAATCG"));
+    assert!(stdout.ends_with(">random\nCTTAAGGGTTAAGTAAGTGTGATGCATACGCCTTTACTTGCTGTGTCCACCCCATCGGACTGGCATTTTT\n"));
+    assert!(str::from_utf8(&output.stderr)?.contains("Removed 1 out of 3 sequences"));
+
+    let mut cmd = Command::cargo_bin("seqtools")?;
+    cmd.arg("mask").arg("tests/data/low_complexity.fa").arg("-w").arg("1001");
+    cmd.assert().failure().stderr(predicate::str::contains("the dust window must be between 4 and 1000"));
+
+    Ok(())
+}
+
+#[test]
+fn sort() -> Result<(), Box<dyn std::error::Error>> {
+    let names = |stdout: &[u8]| -> Vec<String> {
+        str::from_utf8(stdout).unwrap().lines().step_by(2).map(|l| l.split(' ').next().unwrap().to_owned()).collect()
+    };
+
+    let mut cmd = Command::cargo_bin("seqtools")?;
+    cmd.arg("sort").arg("tests/data/reads.fna").arg("-k").arg("length").arg("-r").arg("--fasta-out");
+    let output = cmd.output()?;
+    assert!(output.status.success());
+    assert_eq!(names(&output.stdout), [">SRR403017.10", ">SRR403017.6", ">SRR403017.1", ">SRR403017.3", ">SRR403017.5", ">SRR403017.9", ">SRR403017.2", ">SRR403017.7", ">SRR403017.8", ">SRR403017.4"]);
+
+    // Natural order puts .10 last, lexical order right after .1
+    let mut cmd = Command::cargo_bin("seqtools")?;
+    cmd.arg("sort").arg("tests/data/reads.fna").arg("-k").arg("name").arg("--fasta-out");
+    let output = cmd.output()?;
+    assert_eq!(names(&output.stdout).last().unwrap(), ">SRR403017.10");
+    let mut cmd = Command::cargo_bin("seqtools")?;
+    cmd.arg("sort").arg("tests/data/reads.fna").arg("-k").arg("name").arg("--name-order").arg("lexical").arg("--fasta-out");
+    let output = cmd.output()?;
+    assert_eq!(names(&output.stdout)[1], ">SRR403017.10");
+
+    // Pairs are sorted by the length of the first mate
+    let mut cmd = Command::cargo_bin("seqtools")?;
+    cmd.arg("sort").arg("tests/data/reads.fastq").arg("-k").arg("length").arg("--paired-interleaved").arg("--fasta-out");
+    let output = cmd.output()?;
+    assert_eq!(names(&output.stdout)[..4], [">SRR403017.7", ">SRR403017.8", ">SRR403017.1", ">SRR403017.2"]);
+
+    Ok(())
+}
+
+#[test]
+fn shuffle() -> Result<(), Box<dyn std::error::Error>> {
+    let run = || -> Result<String, Box<dyn std::error::Error>> {
+        let mut cmd = Command::cargo_bin("seqtools")?;
+        cmd.arg("shuffle").arg("tests/data/reads.fastq").arg("--seed").arg("42").arg("--fastq-out");
+        let output = cmd.output()?;
+        assert!(output.status.success());
+        Ok(String::from_utf8(output.stdout)?)
+    };
+    let shuffled = run()?;
+    assert_eq!(run()?, shuffled);
+
+    // The same records in a different order
+    let input = std::fs::read_to_string("tests/data/reads.fastq")?;
+    let records = |s: &str| -> Vec<String> {
+        let lines: Vec<&str> = s.lines().collect();
+        lines.chunks(4).map(|c| c.join("\n")).collect()
+    };
+    let (mut a, mut b) = (records(&input), records(&shuffled));
+    assert_ne!(a, b);
+    a.sort();
+    b.sort();
+    assert_eq!(a, b);
+
+    Ok(())
+}
+
+#[test]
+fn extract_reads_both_mates_listed() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = tempfile::tempdir()?;
+    let reads_path = dir.path().join("pairs.fna");
+    std::fs::write(&reads_path, ">p1/1\nACGT\n>p1/2\nTTTT\n>p2/1\nGGGG\n>p2/2\nCCCC\n")?;
+    let list_path = dir.path().join("names.txt");
+    std::fs::write(&list_path, "p2/1\np1/1\np1/2\np2/1\n")?;
+
+    // Each pair is printed once even though both mates, or the same mate twice, are listed
+    let mut cmd = Command::cargo_bin("seqtools")?;
+    cmd.arg("extract-reads").arg(&reads_path).arg("--strip-mate-suffix").arg("--names-listfile").arg(&list_path);
+    cmd.assert().success().stdout(">p2/1\nGGGG\n>p2/2\nCCCC\n>p1/1\nACGT\n>p1/2\nTTTT\n");
+
+    Ok(())
+}